@@ -3,8 +3,8 @@ use deeb::Query;
 use serde_json::json;
 
 async fn setup() -> deeb::Deeb {
-    let user = deeb::Entity::from("user");
-    let comment = deeb::Entity::from("comment");
+    let user = deeb::Entity::new("user");
+    let comment = deeb::Entity::new("comment");
 
     let db = deeb::Deeb::new();
     db.add_instance("test", "./user.json", vec![user.clone()])
@@ -18,7 +18,7 @@ async fn setup() -> deeb::Deeb {
 }
 
 fn insert_benchmark(c: &mut Criterion) {
-    let user = deeb::Entity::from("user");
+    let user = deeb::Entity::new("user");
     let rt = tokio::runtime::Runtime::new().unwrap();
     let db = rt.block_on(setup());
 
@@ -37,7 +37,7 @@ fn insert_benchmark(c: &mut Criterion) {
 }
 
 // fn insert_1000_benchmark(c: &mut Criterion) {
-//     let user = deeb::Entity::from("user");
+//     let user = deeb::Entity::new("user");
 //     let rt = tokio::runtime::Runtime::new().unwrap();
 //     let db = rt.block_on(setup());
 
@@ -58,7 +58,7 @@ fn insert_benchmark(c: &mut Criterion) {
 // }
 
 fn insert_1000_transaction_benchmark(c: &mut Criterion) {
-    let user = deeb::Entity::from("user");
+    let user = deeb::Entity::new("user");
     let rt = tokio::runtime::Runtime::new().unwrap();
     let db = rt.block_on(setup());
 
@@ -82,7 +82,7 @@ fn insert_1000_transaction_benchmark(c: &mut Criterion) {
 }
 
 fn find_one_benchmark(c: &mut Criterion) {
-    let user = deeb::Entity::from("user");
+    let user = deeb::Entity::new("user");
     let rt = tokio::runtime::Runtime::new().unwrap();
     let db = rt.block_on(setup());
     // let query = Query::eq("name", json!("John Doe"));
@@ -98,7 +98,7 @@ fn find_one_benchmark(c: &mut Criterion) {
 }
 
 fn find_many_benchmark(c: &mut Criterion) {
-    let user = deeb::Entity::from("user");
+    let user = deeb::Entity::new("user");
     let rt = tokio::runtime::Runtime::new().unwrap();
     let db = rt.block_on(setup());
     let query = Query::eq("name", json!("John Doe"));
@@ -0,0 +1,109 @@
+/* Generated with cbindgen. Do not edit. */
+
+#ifndef DEEB_H
+#define DEEB_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// An open Deeb database.
+typedef struct DeebHandle DeebHandle;
+
+// A transaction used to group operations. Operations are executed once committed.
+typedef struct DeebTransaction DeebTransaction;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Open a new Deeb database. Release it with `deeb_close`.
+struct DeebHandle *deeb_open(void);
+
+// Close a database opened with `deeb_open`.
+//
+// # Safety
+//
+// `handle` must have been returned by `deeb_open` and not already closed.
+void deeb_close(struct DeebHandle *handle);
+
+// Add an instance, a JSON file holding one or more entities. `entities_json` is a JSON
+// array of entity names, such as `["user", "comment"]`.
+//
+// # Safety
+//
+// `handle` must be a valid handle and the strings must be valid, NUL terminated UTF-8.
+int deeb_add_instance(struct DeebHandle *handle,
+                      const char *name,
+                      const char *file_path,
+                      const char *entities_json);
+
+// Insert a JSON document. Returns the inserted document as a JSON string.
+//
+// When `transaction` is not `NULL`, the insert is queued until the transaction is committed.
+//
+// # Safety
+//
+// `handle` must be a valid handle, `transaction` must be `NULL` or a valid transaction and
+// the strings must be valid, NUL terminated UTF-8.
+char *deeb_insert(struct DeebHandle *handle,
+                  const char *entity,
+                  const char *value_json,
+                  struct DeebTransaction *transaction);
+
+// Find a single document. Returns the document as a JSON string. A `NULL` query matches
+// all documents.
+//
+// # Safety
+//
+// `handle` must be a valid handle and the strings must be `NULL` or valid, NUL terminated UTF-8.
+char *deeb_find_one(struct DeebHandle *handle, const char *entity, const char *query_json);
+
+// Find multiple documents. Returns a JSON array string. A `NULL` query matches all documents.
+//
+// # Safety
+//
+// `handle` must be a valid handle and the strings must be `NULL` or valid, NUL terminated UTF-8.
+char *deeb_find_many(struct DeebHandle *handle, const char *entity, const char *query_json);
+
+// Begin a new transaction. The transaction is released by `deeb_commit` or
+// `deeb_transaction_free`.
+//
+// # Safety
+//
+// `handle` must be a valid handle.
+struct DeebTransaction *deeb_begin_transaction(struct DeebHandle *handle);
+
+// Commit a transaction, executing its operations and writing the JSON files. The
+// transaction is released whether or not the commit succeeds.
+//
+// # Safety
+//
+// `handle` must be a valid handle and `transaction` must have been returned by
+// `deeb_begin_transaction` and not already released.
+int deeb_commit(struct DeebHandle *handle, struct DeebTransaction *transaction);
+
+// Release a transaction without committing it.
+//
+// # Safety
+//
+// `transaction` must have been returned by `deeb_begin_transaction` and not already released.
+void deeb_transaction_free(struct DeebTransaction *transaction);
+
+// Release a string returned by Deeb.
+//
+// # Safety
+//
+// `s` must have been returned by Deeb and not already released.
+void deeb_string_free(char *s);
+
+// The last error that occurred on the calling thread, or `NULL`. The string is owned by
+// Deeb and remains valid until the next failing call on the same thread.
+const char *deeb_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* DEEB_H */
@@ -0,0 +1,411 @@
+//! # Deeb C Bindings
+//!
+//! A stable `extern "C"` surface for embedding Deeb in other languages. The header at
+//! `include/deeb.h` is generated with cbindgen and checked by the tests.
+//!
+//! Values and queries are passed as JSON strings. Queries use the JSON representation of
+//! [Query](deeb::Query), such as `{"Eq": ["name", "Joey"]}`. Strings returned by Deeb must
+//! be released with `deeb_string_free`.
+//!
+//! Functions returning a pointer return `NULL` on failure, while functions returning an
+//! `int` return `-1`. The reason can be read with `deeb_last_error`.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use anyhow::Error;
+use deeb::{Deeb, Entity, Query, Transaction};
+use serde_json::Value;
+use tokio::runtime::{Builder, Runtime};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// An open Deeb database.
+pub struct DeebHandle {
+    db: Deeb,
+    entities: HashMap<String, Entity>,
+    runtime: Runtime,
+}
+
+impl DeebHandle {
+    fn entity(&self, name: &str) -> Result<Entity, Error> {
+        self.entities
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("Entity `{}` not found", name)))
+    }
+}
+
+/// A transaction used to group operations. Operations are executed once committed.
+pub struct DeebTransaction {
+    transaction: Transaction,
+}
+
+fn set_last_error(err: Error) {
+    let message = CString::new(err.to_string().replace('\0', ""))
+        .unwrap_or_else(|_| CString::new("Unknown error").unwrap());
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+fn ffi<T>(fallback: T, f: impl FnOnce() -> Result<T, Error>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(err)) => {
+            set_last_error(err);
+            fallback
+        }
+        Err(_) => {
+            set_last_error(Error::msg("Deeb panicked"));
+            fallback
+        }
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char, name: &str) -> Result<&'a str, Error> {
+    if s.is_null() {
+        return Err(Error::msg(format!("`{}` must not be null", name)));
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+unsafe fn to_handle<'a>(handle: *mut DeebHandle) -> Result<&'a mut DeebHandle, Error> {
+    handle
+        .as_mut()
+        .ok_or_else(|| Error::msg("`handle` must not be null"))
+}
+
+unsafe fn to_query(query_json: *const c_char) -> Result<Query, Error> {
+    if query_json.is_null() {
+        return Ok(Query::All);
+    }
+    Ok(serde_json::from_str(to_str(query_json, "query_json")?)?)
+}
+
+fn to_c_string(value: &Value) -> Result<*mut c_char, Error> {
+    Ok(CString::new(serde_json::to_string(value)?)?.into_raw())
+}
+
+/// Open a new Deeb database. Release it with `deeb_close`.
+#[no_mangle]
+pub extern "C" fn deeb_open() -> *mut DeebHandle {
+    ffi(ptr::null_mut(), || {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let handle = DeebHandle {
+            db: Deeb::new(),
+            entities: HashMap::new(),
+            runtime,
+        };
+        Ok(Box::into_raw(Box::new(handle)))
+    })
+}
+
+/// Close a database opened with `deeb_open`.
+///
+/// # Safety
+///
+/// `handle` must have been returned by `deeb_open` and not already closed.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_close(handle: *mut DeebHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// Add an instance, a JSON file holding one or more entities. `entities_json` is a JSON
+/// array of entity names, such as `["user", "comment"]`.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle and the strings must be valid, NUL terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_add_instance(
+    handle: *mut DeebHandle,
+    name: *const c_char,
+    file_path: *const c_char,
+    entities_json: *const c_char,
+) -> c_int {
+    ffi(-1, || {
+        let handle = to_handle(handle)?;
+        let name = to_str(name, "name")?;
+        let file_path = to_str(file_path, "file_path")?;
+        let entities: Vec<String> = serde_json::from_str(to_str(entities_json, "entities_json")?)?;
+        let entities = entities
+            .iter()
+            .map(|entity| Entity::new(entity))
+            .collect::<Vec<_>>();
+        handle
+            .runtime
+            .block_on(handle.db.add_instance(name, file_path, entities.clone()))?;
+        for entity in entities {
+            handle.entities.insert(entity.name.to_string(), entity);
+        }
+        Ok(0)
+    })
+}
+
+/// Insert a JSON document. Returns the inserted document as a JSON string.
+///
+/// When `transaction` is not `NULL`, the insert is queued until the transaction is committed.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle, `transaction` must be `NULL` or a valid transaction and
+/// the strings must be valid, NUL terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_insert(
+    handle: *mut DeebHandle,
+    entity: *const c_char,
+    value_json: *const c_char,
+    transaction: *mut DeebTransaction,
+) -> *mut c_char {
+    ffi(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let entity = handle.entity(to_str(entity, "entity")?)?;
+        let value: Value = serde_json::from_str(to_str(value_json, "value_json")?)?;
+        let transaction = transaction.as_mut().map(|t| &mut t.transaction);
+        let value = handle
+            .runtime
+            .block_on(handle.db.insert(&entity, value, transaction))?;
+        to_c_string(&value)
+    })
+}
+
+/// Find a single document. Returns the document as a JSON string. A `NULL` query matches
+/// all documents.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle and the strings must be `NULL` or valid, NUL terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_find_one(
+    handle: *mut DeebHandle,
+    entity: *const c_char,
+    query_json: *const c_char,
+) -> *mut c_char {
+    ffi(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let entity = handle.entity(to_str(entity, "entity")?)?;
+        let query = to_query(query_json)?;
+        let value = handle
+            .runtime
+            .block_on(handle.db.find_one(&entity, query, None))?;
+        to_c_string(&value)
+    })
+}
+
+/// Find multiple documents. Returns a JSON array string. A `NULL` query matches all documents.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle and the strings must be `NULL` or valid, NUL terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_find_many(
+    handle: *mut DeebHandle,
+    entity: *const c_char,
+    query_json: *const c_char,
+) -> *mut c_char {
+    ffi(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let entity = handle.entity(to_str(entity, "entity")?)?;
+        let query = to_query(query_json)?;
+        let values = handle
+            .runtime
+            .block_on(handle.db.find_many(&entity, query, None))?;
+        to_c_string(&Value::Array(values))
+    })
+}
+
+/// Begin a new transaction. The transaction is released by `deeb_commit` or
+/// `deeb_transaction_free`.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_begin_transaction(handle: *mut DeebHandle) -> *mut DeebTransaction {
+    ffi(ptr::null_mut(), || {
+        let handle = to_handle(handle)?;
+        let transaction = handle.runtime.block_on(handle.db.begin_transaction());
+        Ok(Box::into_raw(Box::new(DeebTransaction { transaction })))
+    })
+}
+
+/// Commit a transaction, executing its operations and writing the JSON files. The
+/// transaction is released whether or not the commit succeeds.
+///
+/// # Safety
+///
+/// `handle` must be a valid handle and `transaction` must have been returned by
+/// `deeb_begin_transaction` and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_commit(
+    handle: *mut DeebHandle,
+    transaction: *mut DeebTransaction,
+) -> c_int {
+    if transaction.is_null() {
+        set_last_error(Error::msg("`transaction` must not be null"));
+        return -1;
+    }
+    let mut transaction = Box::from_raw(transaction);
+    ffi(-1, || {
+        let handle = to_handle(handle)?;
+        handle
+            .runtime
+            .block_on(handle.db.commit(&mut transaction.transaction))?;
+        Ok(0)
+    })
+}
+
+/// Release a transaction without committing it.
+///
+/// # Safety
+///
+/// `transaction` must have been returned by `deeb_begin_transaction` and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_transaction_free(transaction: *mut DeebTransaction) {
+    if !transaction.is_null() {
+        drop(Box::from_raw(transaction));
+    }
+}
+
+/// Release a string returned by Deeb.
+///
+/// # Safety
+///
+/// `s` must have been returned by Deeb and not already released.
+#[no_mangle]
+pub unsafe extern "C" fn deeb_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// The last error that occurred on the calling thread, or `NULL`. The string is owned by
+/// Deeb and remains valid until the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn deeb_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| {
+        last_error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    unsafe fn last_error() -> String {
+        CStr::from_ptr(deeb_last_error())
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    unsafe fn take_string(s: *mut c_char) -> Value {
+        assert!(!s.is_null(), "{}", last_error());
+        let value = serde_json::from_str(CStr::from_ptr(s).to_str().unwrap()).unwrap();
+        deeb_string_free(s);
+        value
+    }
+
+    #[test]
+    fn rejects_null_and_invalid_strings() {
+        unsafe {
+            let entity = c("player");
+            assert!(deeb_find_one(ptr::null_mut(), entity.as_ptr(), ptr::null()).is_null());
+            assert_eq!(last_error(), "`handle` must not be null");
+
+            let db = deeb_open();
+            assert!(deeb_find_many(db, ptr::null(), ptr::null()).is_null());
+            assert_eq!(last_error(), "`entity` must not be null");
+            let entities = c("[\"player\"]");
+            let result = deeb_add_instance(db, c("save").as_ptr(), ptr::null(), entities.as_ptr());
+            assert_eq!(result, -1);
+            assert_eq!(last_error(), "`file_path` must not be null");
+
+            let invalid = b"\xff\xfe\0";
+            let result = deeb_add_instance(
+                db,
+                invalid.as_ptr() as *const c_char,
+                c("./save.json").as_ptr(),
+                entities.as_ptr(),
+            );
+            assert_eq!(result, -1);
+            assert!(last_error().contains("utf-8"), "{}", last_error());
+            let found = deeb_find_one(db, invalid.as_ptr() as *const c_char, ptr::null());
+            assert!(found.is_null());
+            assert!(last_error().contains("utf-8"), "{}", last_error());
+            assert_eq!(deeb_commit(db, ptr::null_mut()), -1);
+            assert_eq!(last_error(), "`transaction` must not be null");
+            deeb_close(db);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("deeb-c-{}.json", std::process::id()));
+        let path = c(path.to_str().unwrap());
+        unsafe {
+            let db = deeb_open();
+            let result = deeb_add_instance(
+                db,
+                c("save").as_ptr(),
+                path.as_ptr(),
+                c("[\"player\"]").as_ptr(),
+            );
+            assert_eq!(result, 0, "{}", last_error());
+
+            let inserted = deeb_insert(
+                db,
+                c("player").as_ptr(),
+                c("{\"name\": \"link\", \"hp\": 3}").as_ptr(),
+                ptr::null_mut(),
+            );
+            assert_eq!(take_string(inserted)["name"], "link");
+
+            let transaction = deeb_begin_transaction(db);
+            deeb_insert(
+                db,
+                c("player").as_ptr(),
+                c("{\"name\": \"zelda\", \"hp\": 5}").as_ptr(),
+                transaction,
+            );
+            assert_eq!(deeb_commit(db, transaction), 0, "{}", last_error());
+
+            let found = deeb_find_one(
+                db,
+                c("player").as_ptr(),
+                c("{\"Gt\": [\"hp\", 4]}").as_ptr(),
+            );
+            assert_eq!(take_string(found)["name"], "zelda");
+            let players = deeb_find_many(db, c("player").as_ptr(), ptr::null());
+            assert_eq!(take_string(players).as_array().unwrap().len(), 2);
+            deeb_close(db);
+        }
+        std::fs::remove_file(path.to_str().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn header_is_up_to_date() {
+        let crate_dir = env!("CARGO_MANIFEST_DIR");
+        let mut header = vec![];
+        cbindgen::generate(crate_dir)
+            .expect("Unable to generate bindings")
+            .write(&mut header);
+        let checked_in = std::fs::read_to_string(format!("{}/include/deeb.h", crate_dir)).unwrap();
+        assert!(
+            String::from_utf8(header).unwrap() == checked_in,
+            "include/deeb.h is out of date, run `cbindgen --config cbindgen.toml --output include/deeb.h`"
+        );
+    }
+}
@@ -0,0 +1,29 @@
+const assert = require("node:assert");
+const fs = require("node:fs");
+const os = require("node:os");
+const path = require("node:path");
+const test = require("node:test");
+
+const { Deeb } = require("../index.js");
+
+test("round trip", async () => {
+  const filePath = path.join(os.tmpdir(), `deeb-node-${process.pid}.json`);
+  const db = new Deeb();
+  await db.addInstance("app", filePath, ["user"]);
+
+  await db.insert("user", { id: 1, name: "Joey", age: 10 });
+  const transaction = db.beginTransaction();
+  await db.insertMany("user", [{ id: 2, name: "Steve", age: 3 }], transaction);
+  await db.commit(transaction);
+
+  const found = await db.findOne("user", { name: "Joey" });
+  assert.deepStrictEqual(found, { id: 1, name: "Joey", age: 10 });
+  const young = await db.findMany("user", { age: { $lt: 5 } });
+  assert.deepStrictEqual(young.map((user) => user.name), ["Steve"]);
+
+  await db.updateOne("user", { id: 2 }, { age: 4 });
+  await db.deleteOne("user", { name: "Joey" });
+  const users = JSON.parse(fs.readFileSync(filePath, "utf8")).user;
+  assert.deepStrictEqual(users, [{ id: 2, name: "Steve", age: 4 }]);
+  fs.rmSync(filePath);
+});
@@ -0,0 +1,134 @@
+use anyhow::Error;
+use deeb::Query;
+use serde_json::Value;
+
+/// Convert a Mongo-style filter into a [Query].
+///
+/// Fields map to exact matches unless a non empty operator object is provided:
+///
+/// ```json
+/// { "name": "Joey", "age": { "$gte": 10 }, "$or": [{ "city": "Denver" }, { "city": "Boise" }] }
+/// ```
+///
+/// Supported operators are `$eq`, `$ne`, `$gt`, `$gte`, `$lt`, `$lte`, `$like`, `$and`, and
+/// `$or`. An empty filter, or no filter, matches all documents.
+pub fn to_query(filter: Option<&Value>) -> Result<Query, Error> {
+    let filter = match filter {
+        None | Some(Value::Null) => return Ok(Query::All),
+        Some(filter) => filter,
+    };
+    let filter = filter
+        .as_object()
+        .ok_or_else(|| Error::msg("Filter must be an object"))?;
+
+    let mut queries = vec![];
+    for (key, value) in filter.iter() {
+        match key.as_str() {
+            "$and" => queries.push(Query::and(to_queries(value)?)),
+            "$or" => queries.push(Query::or(to_queries(value)?)),
+            _ if key.starts_with('$') => {
+                return Err(Error::msg(format!("Unknown operator `{}`", key)))
+            }
+            _ => queries.append(&mut field_queries(key, value)?),
+        }
+    }
+
+    match queries.len() {
+        0 => Ok(Query::All),
+        1 => Ok(queries.remove(0)),
+        _ => Ok(Query::and(queries)),
+    }
+}
+
+fn to_queries(value: &Value) -> Result<Vec<Query>, Error> {
+    value
+        .as_array()
+        .ok_or_else(|| Error::msg("`$and` and `$or` must be arrays of filters"))?
+        .iter()
+        .map(|filter| to_query(Some(filter)))
+        .collect()
+}
+
+fn field_queries(key: &str, value: &Value) -> Result<Vec<Query>, Error> {
+    let operators = match value {
+        // An empty object is a value to match, not an empty set of operators.
+        Value::Object(operators)
+            if !operators.is_empty() && operators.keys().all(|k| k.starts_with('$')) =>
+        {
+            operators
+        }
+        _ => return Ok(vec![Query::eq(key, value.clone())]),
+    };
+
+    operators
+        .iter()
+        .map(|(operator, value)| match operator.as_str() {
+            "$eq" => Ok(Query::eq(key, value.clone())),
+            "$ne" => Ok(Query::ne(key, value.clone())),
+            "$gt" => Ok(Query::gt(key, value.clone())),
+            "$gte" => Ok(Query::gte(key, value.clone())),
+            "$lt" => Ok(Query::lt(key, value.clone())),
+            "$lte" => Ok(Query::lte(key, value.clone())),
+            "$like" => value
+                .as_str()
+                .map(|value| Query::like(key, value))
+                .ok_or_else(|| Error::msg("`$like` must be a string")),
+            _ => Err(Error::msg(format!("Unknown operator `{}`", operator))),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fields_match_exactly() {
+        assert_eq!(to_query(None).unwrap(), Query::All);
+        assert_eq!(to_query(Some(&json!({}))).unwrap(), Query::All);
+        assert_eq!(
+            to_query(Some(&json!({"name": "Joey"}))).unwrap(),
+            Query::eq("name", "Joey")
+        );
+        assert_eq!(
+            to_query(Some(&json!({"address": {"city": "Denver"}}))).unwrap(),
+            Query::eq("address", json!({"city": "Denver"}))
+        );
+        assert_eq!(
+            to_query(Some(&json!({"address": {}}))).unwrap(),
+            Query::eq("address", json!({}))
+        );
+    }
+
+    #[test]
+    fn operators() {
+        assert_eq!(
+            to_query(Some(&json!({"age": {"$gte": 10, "$lt": 20}}))).unwrap(),
+            Query::and(vec![Query::gte("age", 10), Query::lt("age", 20)])
+        );
+        assert_eq!(
+            to_query(Some(&json!({"name": {"$like": "Jo"}}))).unwrap(),
+            Query::like("name", "Jo")
+        );
+        assert_eq!(
+            to_query(Some(
+                &json!({"$or": [{"city": "Denver"}, {"city": "Boise"}]})
+            ))
+            .unwrap(),
+            Query::or(vec![
+                Query::eq("city", "Denver"),
+                Query::eq("city", "Boise")
+            ])
+        );
+    }
+
+    #[test]
+    fn invalid_filters() {
+        assert!(to_query(Some(&json!([]))).is_err());
+        assert!(to_query(Some(&json!({"$nor": []}))).is_err());
+        assert!(to_query(Some(&json!({"age": {"$in": [1]}}))).is_err());
+        assert!(to_query(Some(&json!({"name": {"$like": 1}}))).is_err());
+        assert!(to_query(Some(&json!({"$or": {"city": "Denver"}}))).is_err());
+    }
+}
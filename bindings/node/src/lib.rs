@@ -0,0 +1,256 @@
+//! # Deeb Node.js Bindings
+//!
+//! Node.js bindings for Deeb. Instances opened from Node use the same file locking and
+//! commit protocol as the Rust crate, so Node scripts and Electron apps can safely share
+//! JSON files with a Rust application.
+//!
+//! ```js
+//! const { Deeb } = require("deeb");
+//!
+//! const db = new Deeb();
+//! await db.addInstance("app", "./app.json", ["user", "comment"]);
+//!
+//! await db.insert("user", { id: 1, name: "Joey", age: 10 });
+//! await db.findMany("user", { age: { $gte: 10 } });
+//!
+//! const transaction = db.beginTransaction();
+//! await db.insert("user", { id: 2, name: "Steve", age: 3 }, transaction);
+//! await db.commit(transaction);
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use deeb::{Deeb, Entity, Transaction};
+use napi::bindgen_prelude::*;
+use napi_derive::napi;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+mod filter;
+
+fn to_napi_err(err: anyhow::Error) -> napi::Error {
+    napi::Error::from_reason(err.to_string())
+}
+
+/// A transaction used to group operations. Operations are executed once committed.
+#[napi(js_name = "Transaction")]
+pub struct JsTransaction {
+    transaction: Arc<Mutex<Transaction>>,
+}
+
+/// A Deeb database handle.
+#[napi(js_name = "Deeb")]
+pub struct JsDeeb {
+    db: Deeb,
+    entities: RwLock<HashMap<String, Entity>>,
+}
+
+impl JsDeeb {
+    fn entity(&self, name: &str) -> Result<Entity> {
+        self.entities
+            .read()
+            .map_err(|err| napi::Error::from_reason(err.to_string()))?
+            .get(name)
+            .cloned()
+            .ok_or_else(|| napi::Error::from_reason(format!("Entity `{}` not found", name)))
+    }
+}
+
+#[napi]
+impl JsDeeb {
+    #[napi(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            db: Deeb::new(),
+            entities: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Add an instance, a JSON file holding one or more entities.
+    #[napi]
+    pub async fn add_instance(
+        &self,
+        name: String,
+        file_path: String,
+        entities: Vec<String>,
+    ) -> Result<()> {
+        let entities = entities
+            .iter()
+            .map(|entity| Entity::new(entity))
+            .collect::<Vec<_>>();
+        self.db
+            .add_instance(name.as_str(), &file_path, entities.clone())
+            .await
+            .map_err(to_napi_err)?;
+        let mut registered = self
+            .entities
+            .write()
+            .map_err(|err| napi::Error::from_reason(err.to_string()))?;
+        for entity in entities {
+            registered.insert(entity.name.to_string(), entity);
+        }
+        Ok(())
+    }
+
+    /// Insert a single document.
+    #[napi]
+    pub async fn insert(
+        &self,
+        entity: String,
+        value: Value,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Value> {
+        let entity = self.entity(&entity)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .insert(&entity, value, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Insert multiple documents.
+    #[napi]
+    pub async fn insert_many(
+        &self,
+        entity: String,
+        values: Vec<Value>,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Vec<Value>> {
+        let entity = self.entity(&entity)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .insert_many(&entity, values, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Find a single document matching the filter.
+    #[napi]
+    pub async fn find_one(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Value> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .find_one(&entity, query, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Find all documents matching the filter.
+    #[napi]
+    pub async fn find_many(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Vec<Value>> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .find_many(&entity, query, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Update a single document, merging the update into the existing document.
+    #[napi]
+    pub async fn update_one(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        update: Value,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Value> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .update_one(&entity, query, update, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Update all documents matching the filter.
+    #[napi]
+    pub async fn update_many(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        update: Value,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Vec<Value>> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .update_many(&entity, query, update, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Delete a single document matching the filter.
+    #[napi]
+    pub async fn delete_one(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Value> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .delete_one(&entity, query, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Delete all documents matching the filter.
+    #[napi]
+    pub async fn delete_many(
+        &self,
+        entity: String,
+        filter: Option<Value>,
+        transaction: Option<&JsTransaction>,
+    ) -> Result<Vec<Value>> {
+        let entity = self.entity(&entity)?;
+        let query = filter::to_query(filter.as_ref()).map_err(to_napi_err)?;
+        let mut transaction = lock(transaction).await;
+        self.db
+            .delete_many(&entity, query, transaction.as_deref_mut())
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// Begin a new transaction.
+    #[napi]
+    pub fn begin_transaction(&self) -> JsTransaction {
+        JsTransaction {
+            transaction: Arc::new(Mutex::new(Transaction::new())),
+        }
+    }
+
+    /// Commit a transaction, executing its operations and writing the JSON files.
+    #[napi]
+    pub async fn commit(&self, transaction: &JsTransaction) -> Result<()> {
+        let mut transaction = transaction.transaction.lock().await;
+        self.db.commit(&mut transaction).await.map_err(to_napi_err)
+    }
+}
+
+async fn lock(
+    transaction: Option<&JsTransaction>,
+) -> Option<tokio::sync::MutexGuard<'_, Transaction>> {
+    match transaction {
+        Some(transaction) => Some(transaction.transaction.lock().await),
+        None => None,
+    }
+}
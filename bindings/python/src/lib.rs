@@ -0,0 +1,321 @@
+//! # Deeb Python Bindings
+//!
+//! Python bindings for Deeb, allowing Python programs to read and write the same JSON
+//! instances as a Rust application without running a server.
+//!
+//! ```python
+//! import deeb
+//!
+//! db = deeb.Deeb()
+//! db.add_instance("app", "./app.json", ["user", "comment"])
+//!
+//! db.insert("user", {"id": 1, "name": "Joey", "age": 10})
+//! db.find_one("user", {"Eq": ["name", "Joey"]})
+//!
+//! transaction = db.begin_transaction()
+//! db.insert("user", {"id": 2, "name": "Steve", "age": 3}, transaction)
+//! db.commit(transaction)
+//! ```
+//!
+//! Queries use the JSON representation of [Query](deeb::Query). Passing `None` matches
+//! all documents.
+
+// Triggered by code generated from `#[pymethods]`.
+#![allow(clippy::useless_conversion)]
+
+use std::collections::HashMap;
+
+use ::deeb::{Deeb, Entity, Query, Transaction};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+
+fn to_py_err(err: anyhow::Error) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}
+
+fn to_value(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let json = object.py().import_bound("json")?;
+    let dumped: String = json.call_method1("dumps", (object,))?.extract()?;
+    serde_json::from_str(&dumped).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    let json = py.import_bound("json")?;
+    let dumped =
+        serde_json::to_string(value).map_err(|err| PyValueError::new_err(err.to_string()))?;
+    Ok(json.call_method1("loads", (dumped,))?.unbind())
+}
+
+fn to_query(query: Option<&Bound<'_, PyAny>>) -> PyResult<Query> {
+    match query {
+        Some(query) if !query.is_none() => serde_json::from_value(to_value(query)?)
+            .map_err(|err| PyValueError::new_err(format!("Invalid query: {}", err))),
+        _ => Ok(Query::All),
+    }
+}
+
+/// A transaction used to group operations. Operations are executed once committed.
+#[pyclass(name = "Transaction")]
+struct PyTransaction {
+    transaction: Transaction,
+}
+
+/// A Deeb database handle.
+#[pyclass(name = "Deeb")]
+struct PyDeeb {
+    db: Deeb,
+    entities: HashMap<String, Entity>,
+    runtime: Runtime,
+}
+
+impl PyDeeb {
+    fn entity(&self, name: &str) -> PyResult<Entity> {
+        self.entities
+            .get(name)
+            .cloned()
+            .ok_or_else(|| PyValueError::new_err(format!("Entity `{}` not found", name)))
+    }
+}
+
+#[pymethods]
+impl PyDeeb {
+    #[new]
+    fn new() -> PyResult<Self> {
+        let runtime = Runtime::new().map_err(|err| PyRuntimeError::new_err(err.to_string()))?;
+        Ok(Self {
+            db: Deeb::new(),
+            entities: HashMap::new(),
+            runtime,
+        })
+    }
+
+    /// Add an instance, a JSON file holding one or more entities.
+    fn add_instance(&mut self, name: &str, file_path: &str, entities: Vec<String>) -> PyResult<()> {
+        let entities = entities
+            .iter()
+            .map(|entity| Entity::new(entity))
+            .collect::<Vec<_>>();
+        self.runtime
+            .block_on(self.db.add_instance(name, file_path, entities.clone()))
+            .map_err(to_py_err)?;
+        for entity in entities {
+            self.entities.insert(entity.name.to_string(), entity);
+        }
+        Ok(())
+    }
+
+    /// Insert a single document.
+    #[pyo3(signature = (entity, value, transaction=None))]
+    fn insert(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        value: &Bound<'_, PyAny>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let value = to_value(value)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.insert(
+                &entity,
+                value,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &result)
+    }
+
+    /// Insert multiple documents.
+    #[pyo3(signature = (entity, values, transaction=None))]
+    fn insert_many(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        values: Vec<Bound<'_, PyAny>>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let values = values
+            .iter()
+            .map(|value| to_value(value))
+            .collect::<PyResult<Vec<_>>>()?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.insert_many(
+                &entity,
+                values,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &Value::Array(result))
+    }
+
+    /// Find a single document.
+    #[pyo3(signature = (entity, query=None, transaction=None))]
+    fn find_one(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.find_one(
+                &entity,
+                query,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &result)
+    }
+
+    /// Find multiple documents.
+    #[pyo3(signature = (entity, query=None, transaction=None))]
+    fn find_many(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.find_many(
+                &entity,
+                query,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &Value::Array(result))
+    }
+
+    /// Update a single document, merging the update into the existing document.
+    #[pyo3(signature = (entity, query, update, transaction=None))]
+    fn update_one(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        update: &Bound<'_, PyAny>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let update = to_value(update)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.update_one(
+                &entity,
+                query,
+                update,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &result)
+    }
+
+    /// Update multiple documents, merging the update into each existing document.
+    #[pyo3(signature = (entity, query, update, transaction=None))]
+    fn update_many(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        update: &Bound<'_, PyAny>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let update = to_value(update)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.update_many(
+                &entity,
+                query,
+                update,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &Value::Array(result))
+    }
+
+    /// Delete a single document.
+    #[pyo3(signature = (entity, query, transaction=None))]
+    fn delete_one(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.delete_one(
+                &entity,
+                query,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &result)
+    }
+
+    /// Delete multiple documents.
+    #[pyo3(signature = (entity, query, transaction=None))]
+    fn delete_many(
+        &self,
+        py: Python<'_>,
+        entity: &str,
+        query: Option<&Bound<'_, PyAny>>,
+        transaction: Option<PyRefMut<'_, PyTransaction>>,
+    ) -> PyResult<PyObject> {
+        let entity = self.entity(entity)?;
+        let query = to_query(query)?;
+        let mut transaction = transaction;
+        let result = self
+            .runtime
+            .block_on(self.db.delete_many(
+                &entity,
+                query,
+                transaction.as_mut().map(|t| &mut t.transaction),
+            ))
+            .map_err(to_py_err)?;
+        to_py(py, &Value::Array(result))
+    }
+
+    /// Begin a new transaction.
+    fn begin_transaction(&self) -> PyTransaction {
+        let transaction = self.runtime.block_on(self.db.begin_transaction());
+        PyTransaction { transaction }
+    }
+
+    /// Commit a transaction, executing its operations and writing the JSON files.
+    fn commit(&self, mut transaction: PyRefMut<'_, PyTransaction>) -> PyResult<()> {
+        self.runtime
+            .block_on(self.db.commit(&mut transaction.transaction))
+            .map_err(to_py_err)
+    }
+}
+
+#[pymodule]
+fn deeb(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDeeb>()?;
+    module.add_class::<PyTransaction>()?;
+    Ok(())
+}
@@ -0,0 +1,44 @@
+import json
+import os
+import tempfile
+import unittest
+
+import deeb
+
+
+class DeebTest(unittest.TestCase):
+    def setUp(self):
+        self.file_path = os.path.join(tempfile.gettempdir(), f"deeb-python-{os.getpid()}.json")
+        self.db = deeb.Deeb()
+        self.db.add_instance("app", self.file_path, ["user"])
+        self.db.delete_many("user", None)
+
+    def tearDown(self):
+        os.remove(self.file_path)
+
+    def test_round_trip(self):
+        self.db.insert("user", {"id": 1, "name": "Joey", "age": 10})
+        transaction = self.db.begin_transaction()
+        self.db.insert_many("user", [{"id": 2, "name": "Steve", "age": 3}], transaction)
+        self.db.commit(transaction)
+
+        found = self.db.find_one("user", {"Eq": ["name", "Joey"]})
+        self.assertEqual(found, {"id": 1, "name": "Joey", "age": 10})
+        young = self.db.find_many("user", {"Lt": ["age", 5]})
+        self.assertEqual([user["name"] for user in young], ["Steve"])
+
+        self.db.update_one("user", {"Eq": ["id", 2]}, {"age": 4})
+        self.db.delete_one("user", {"Eq": ["name", "Joey"]})
+        with open(self.file_path) as file:
+            users = json.load(file)["user"]
+        self.assertEqual(users, [{"id": 2, "name": "Steve", "age": 4}])
+
+    def test_invalid_arguments(self):
+        with self.assertRaisesRegex(ValueError, "Invalid query"):
+            self.db.find_many("user", {"Unknown": []})
+        with self.assertRaisesRegex(ValueError, "not found"):
+            self.db.find_one("comment", None)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,50 @@
+use anyhow::Error;
+use deeb::{entities_from_config, verify_integrity, EntityName};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Check the instances at `path` against the entities of a config, printing every issue.
+/// `path` may be a single JSON file or a directory of JSON files.
+pub fn run(config_path: &str, path: &str) -> Result<(), Error> {
+    let config: Value = serde_json::from_str(&std::fs::read_to_string(config_path)?)?;
+    let entities = entities_from_config(&config)
+        .map_err(|err| Error::msg(format!("{}: {}", config_path, err)))?;
+    let report = verify_integrity(&entities, &read_data(Path::new(path))?);
+    print!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn read_data(path: &Path) -> Result<HashMap<EntityName, Vec<Value>>, Error> {
+    let files = if path.is_dir() {
+        let mut files = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| {
+                file.extension()
+                    .is_some_and(|extension| extension == "json")
+                    && file.file_stem().is_some_and(|stem| stem != "_meta")
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut data = HashMap::new();
+    for file in files {
+        let instance: Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+        let Value::Object(instance) = instance else {
+            continue;
+        };
+        for (name, documents) in instance {
+            if let Value::Array(documents) = documents {
+                data.insert(EntityName::from(name.as_str()), documents);
+            }
+        }
+    }
+    Ok(data)
+}
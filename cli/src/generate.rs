@@ -0,0 +1,160 @@
+use anyhow::Error;
+use deeb::{entity_from_json_schema, json_schema_definitions, Entity};
+use serde_json::Value;
+use std::fmt::Write;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while",
+];
+
+/// Print Rust source with an `Entity` definition for every object schema in a JSON Schema
+/// or OpenAPI document. With `structs`, a serde struct is generated for each entity as well.
+pub fn run(path: &str, structs: bool) -> Result<(), Error> {
+    let document: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    print!("{}", generate(&document, structs)?);
+    Ok(())
+}
+
+fn generate(document: &Value, structs: bool) -> Result<String, Error> {
+    let definitions = json_schema_definitions(document)?;
+    let mut source = String::new();
+    writeln!(source, "use deeb::*;")?;
+    if structs {
+        writeln!(source, "use serde::{{Deserialize, Serialize}};")?;
+    }
+
+    for (name, schema) in definitions.iter() {
+        let entity = entity_from_json_schema(name, schema);
+        writeln!(source)?;
+        write_entity(&mut source, &entity)?;
+        if structs {
+            writeln!(source)?;
+            write_struct(&mut source, name, schema)?;
+        }
+    }
+    Ok(source)
+}
+
+fn write_entity(source: &mut String, entity: &Entity) -> Result<(), Error> {
+    writeln!(source, "pub fn {}_entity() -> Entity {{", entity.name)?;
+    write!(source, "    Entity::new({:?})", entity.name.to_string())?;
+    if let Some(primary_key) = &entity.primary_key {
+        write!(source, "\n        .primary_key({:?})", primary_key)?;
+    }
+    if !entity.encrypted_fields.is_empty() {
+        write!(
+            source,
+            "\n        .encrypted_fields(vec!{:?})",
+            entity.encrypted_fields
+        )?;
+    }
+    if !entity.redacted_fields.is_empty() {
+        write!(
+            source,
+            "\n        .redacted_fields(vec!{:?})",
+            entity.redacted_fields
+        )?;
+    }
+    writeln!(source, "\n}}")?;
+    Ok(())
+}
+
+fn write_struct(source: &mut String, name: &str, schema: &Value) -> Result<(), Error> {
+    let required = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|required| {
+            required
+                .iter()
+                .filter_map(Value::as_str)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    writeln!(source, "#[derive(Debug, Clone, Serialize, Deserialize)]")?;
+    writeln!(source, "pub struct {} {{", to_pascal_case(name))?;
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (key, property) in properties {
+            let field = to_field_name(key);
+            if field.trim_start_matches("r#") != key {
+                writeln!(source, "    #[serde(rename = {:?})]", key)?;
+            }
+            let rust_type = rust_type(property);
+            if required.contains(&key.as_str()) {
+                writeln!(source, "    pub {}: {},", field, rust_type)?;
+            } else {
+                writeln!(
+                    source,
+                    "    #[serde(default, skip_serializing_if = \"Option::is_none\")]"
+                )?;
+                writeln!(source, "    pub {}: Option<{}>,", field, rust_type)?;
+            }
+        }
+    }
+    writeln!(source, "}}")?;
+    Ok(())
+}
+
+fn rust_type(property: &Value) -> String {
+    if let Some(reference) = property.get("$ref").and_then(Value::as_str) {
+        let name = reference.rsplit('/').next().unwrap_or(reference);
+        return to_pascal_case(name);
+    }
+    match property.get("type").and_then(Value::as_str) {
+        Some("string") => "String".to_string(),
+        Some("integer") => "i64".to_string(),
+        Some("number") => "f64".to_string(),
+        Some("boolean") => "bool".to_string(),
+        Some("array") => format!(
+            "Vec<{}>",
+            property
+                .get("items")
+                .map(rust_type)
+                .unwrap_or_else(|| "serde_json::Value".to_string())
+        ),
+        _ => "serde_json::Value".to_string(),
+    }
+}
+
+fn to_field_name(key: &str) -> String {
+    let mut field = String::new();
+    let mut previous_lowercase = false;
+    for character in key.chars() {
+        if character.is_uppercase() {
+            if previous_lowercase {
+                field.push('_');
+            }
+            field.extend(character.to_lowercase());
+            previous_lowercase = false;
+        } else if character.is_alphanumeric() || character == '_' {
+            field.push(character);
+            previous_lowercase = character.is_lowercase() || character.is_ascii_digit();
+        } else {
+            field.push('_');
+            previous_lowercase = false;
+        }
+    }
+    if field.starts_with(|character: char| character.is_ascii_digit()) {
+        field.insert(0, '_');
+    }
+    if KEYWORDS.contains(&field.as_str()) {
+        field.insert_str(0, "r#");
+    }
+    field
+}
+
+fn to_pascal_case(name: &str) -> String {
+    name.split(|character: char| !character.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut characters = part.chars();
+            characters
+                .next()
+                .map(|first| first.to_uppercase().chain(characters).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,48 @@
+//! # Deeb CLI
+//!
+//! Command line tools for working with Deeb instances.
+//!
+//! ```bash
+//! deeb studio ./db
+//! deeb generate ./openapi.json --structs > src/entities.rs
+//! deeb schema-diff ./old-instances.json ./new-instances.json --plan
+//! deeb check ./_meta.json ./db
+//! ```
+
+use anyhow::Error;
+
+mod check;
+mod generate;
+mod schema_diff;
+mod studio;
+
+const USAGE: &str = "Usage: deeb <command>
+
+Commands:
+  studio <path>                       Browse and edit the instances in a directory or JSON file
+  generate <schema.json> [--structs]  Print entity definitions for a JSON Schema or OpenAPI document
+  schema-diff <old> <new> [--plan]    Compare two instance configs and optionally plan the migration
+  check <config> <path>               Check primary keys and association references of the instances at a path";
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<(), Error> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["studio", path] => studio::run(path).await,
+        ["studio"] => studio::run(".").await,
+        ["generate", path] => generate::run(path, false),
+        ["generate", path, "--structs"] => generate::run(path, true),
+        ["schema-diff", old, new] => schema_diff::run(old, new, false),
+        ["schema-diff", old, new, "--plan"] => schema_diff::run(old, new, true),
+        ["check", config, path] => check::run(config, path),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(1);
+        }
+    }
+}
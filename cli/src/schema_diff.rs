@@ -0,0 +1,26 @@
+use anyhow::Error;
+use deeb::{entities_from_config, Entity, SchemaDiff};
+use serde_json::Value;
+
+/// Print the differences between two instance configs, and with `plan`, the migration
+/// steps that bring existing data in line with the new one.
+pub fn run(old_path: &str, new_path: &str, plan: bool) -> Result<(), Error> {
+    let diff = SchemaDiff::new(&read_entities(old_path)?, &read_entities(new_path)?);
+    print!("{}", diff);
+    if plan {
+        let steps = diff.migration_plan();
+        println!();
+        if steps.is_empty() {
+            println!("No migration needed");
+        }
+        for step in steps {
+            println!("{}", step);
+        }
+    }
+    Ok(())
+}
+
+fn read_entities(path: &str) -> Result<Vec<Entity>, Error> {
+    let config: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    entities_from_config(&config).map_err(|err| Error::msg(format!("{}: {}", path, err)))
+}
@@ -0,0 +1,352 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use deeb::{Deeb, Entity, Query};
+use serde_json::{Map, Value};
+
+pub const PAGE_SIZE: usize = 20;
+
+/// An entity within an instance.
+pub struct Collection {
+    pub instance: String,
+    pub entity: Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    Collections,
+    Documents,
+    Query,
+    Edit,
+}
+
+/// The kind of a field, inferred from the value stored in the document. Edited values are
+/// parsed back into the same kind.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldKind {
+    String,
+    Number,
+    Bool,
+    Null,
+    Json,
+}
+
+impl FieldKind {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::String(_) => Self::String,
+            Value::Number(_) => Self::Number,
+            Value::Bool(_) => Self::Bool,
+            Value::Null => Self::Null,
+            Value::Array(_) | Value::Object(_) => Self::Json,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::Number => "number",
+            Self::Bool => "bool",
+            Self::Null => "null",
+            Self::Json => "json",
+        }
+    }
+
+    fn parse(&self, input: &str) -> Result<Value, String> {
+        match self {
+            Self::String => Ok(Value::String(input.to_string())),
+            Self::Number => serde_json::from_str::<serde_json::Number>(input)
+                .map(Value::Number)
+                .map_err(|_| format!("`{}` is not a number", input)),
+            Self::Bool => input
+                .parse::<bool>()
+                .map(Value::Bool)
+                .map_err(|_| format!("`{}` is not true or false", input)),
+            Self::Null | Self::Json => {
+                serde_json::from_str(input).map_err(|err| format!("Invalid JSON: {}", err))
+            }
+        }
+    }
+}
+
+pub struct FormField {
+    pub key: String,
+    pub kind: FieldKind,
+    pub input: String,
+}
+
+/// A form for editing the top level fields of a document.
+pub struct Form {
+    pub original: Value,
+    pub fields: Vec<FormField>,
+    pub selected: usize,
+}
+
+impl Form {
+    fn new(document: &Value) -> Self {
+        let fields = document
+            .as_object()
+            .map(|object| {
+                object
+                    .iter()
+                    .map(|(key, value)| FormField {
+                        key: key.clone(),
+                        kind: FieldKind::of(value),
+                        input: match value {
+                            Value::String(value) => value.clone(),
+                            value => value.to_string(),
+                        },
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            original: document.clone(),
+            fields,
+            selected: 0,
+        }
+    }
+
+    fn to_document(&self) -> Result<Value, String> {
+        let mut document = Map::new();
+        for field in self.fields.iter() {
+            let value = field
+                .kind
+                .parse(&field.input)
+                .map_err(|err| format!("{}: {}", field.key, err))?;
+            document.insert(field.key.clone(), value);
+        }
+        Ok(Value::Object(document))
+    }
+}
+
+pub struct App {
+    pub db: Deeb,
+    pub collections: Vec<Collection>,
+    pub selected_collection: usize,
+    pub documents: Vec<Value>,
+    pub selected_document: usize,
+    pub query: Query,
+    pub query_input: String,
+    pub form: Option<Form>,
+    pub focus: Focus,
+    pub status: String,
+    pub should_quit: bool,
+}
+
+impl App {
+    pub fn new(db: Deeb, collections: Vec<Collection>) -> Self {
+        Self {
+            db,
+            collections,
+            selected_collection: 0,
+            documents: vec![],
+            selected_document: 0,
+            query: Query::All,
+            query_input: String::new(),
+            form: None,
+            focus: Focus::Collections,
+            status: String::new(),
+            should_quit: false,
+        }
+    }
+
+    pub fn collection(&self) -> &Collection {
+        &self.collections[self.selected_collection]
+    }
+
+    pub fn page(&self) -> usize {
+        self.selected_document / PAGE_SIZE
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.documents.len().div_ceil(PAGE_SIZE).max(1)
+    }
+
+    pub fn page_documents(&self) -> &[Value] {
+        let start = self.page() * PAGE_SIZE;
+        let end = (start + PAGE_SIZE).min(self.documents.len());
+        &self.documents[start.min(end)..end]
+    }
+
+    pub fn selected(&self) -> Option<&Value> {
+        self.documents.get(self.selected_document)
+    }
+
+    pub async fn load_documents(&mut self) {
+        let entity = self.collection().entity.clone();
+        match self.db.find_many(&entity, self.query.clone(), None).await {
+            Ok(documents) => {
+                self.status = format!("{} documents", documents.len());
+                self.documents = documents;
+            }
+            Err(err) => {
+                self.status = err.to_string();
+                self.documents = vec![];
+            }
+        }
+        self.selected_document = self
+            .selected_document
+            .min(self.documents.len().saturating_sub(1));
+    }
+
+    pub async fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.should_quit = true;
+            return;
+        }
+        match self.focus {
+            Focus::Collections => self.handle_collections_key(key).await,
+            Focus::Documents => self.handle_documents_key(key).await,
+            Focus::Query => self.handle_query_key(key).await,
+            Focus::Edit => self.handle_edit_key(key).await,
+        }
+    }
+
+    async fn handle_collections_key(&mut self, key: KeyEvent) {
+        let previous = self.selected_collection;
+        match key.code {
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_collection = self.selected_collection.saturating_sub(1)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_collection =
+                    (self.selected_collection + 1).min(self.collections.len() - 1)
+            }
+            KeyCode::Tab | KeyCode::Enter | KeyCode::Right => self.focus = Focus::Documents,
+            KeyCode::Char('/') => self.focus = Focus::Query,
+            _ => {}
+        }
+        if previous != self.selected_collection {
+            self.selected_document = 0;
+            self.query = Query::All;
+            self.query_input.clear();
+            self.load_documents().await;
+        }
+    }
+
+    async fn handle_documents_key(&mut self, key: KeyEvent) {
+        let last = self.documents.len().saturating_sub(1);
+        match key.code {
+            KeyCode::Char('q') => self.should_quit = true,
+            KeyCode::Tab | KeyCode::Esc | KeyCode::Left => self.focus = Focus::Collections,
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected_document = self.selected_document.saturating_sub(1)
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected_document = (self.selected_document + 1).min(last)
+            }
+            KeyCode::PageDown | KeyCode::Char('n') => {
+                self.selected_document = (self.selected_document + PAGE_SIZE).min(last)
+            }
+            KeyCode::PageUp | KeyCode::Char('p') => {
+                self.selected_document = self.selected_document.saturating_sub(PAGE_SIZE)
+            }
+            KeyCode::Char('/') => self.focus = Focus::Query,
+            KeyCode::Char('r') => self.load_documents().await,
+            KeyCode::Char('e') | KeyCode::Enter => {
+                if let Some(document) = self.selected() {
+                    self.form = Some(Form::new(document));
+                    self.focus = Focus::Edit;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    async fn handle_query_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Esc => self.focus = Focus::Documents,
+            KeyCode::Enter => {
+                let query = if self.query_input.trim().is_empty() {
+                    Ok(Query::All)
+                } else {
+                    serde_json::from_str::<Query>(&self.query_input)
+                };
+                match query {
+                    Ok(query) => {
+                        self.query = query;
+                        self.selected_document = 0;
+                        self.focus = Focus::Documents;
+                        self.load_documents().await;
+                    }
+                    Err(err) => self.status = format!("Invalid query: {}", err),
+                }
+            }
+            KeyCode::Backspace => {
+                self.query_input.pop();
+            }
+            KeyCode::Char(c) => self.query_input.push(c),
+            _ => {}
+        }
+    }
+
+    async fn handle_edit_key(&mut self, key: KeyEvent) {
+        let Some(form) = self.form.as_mut() else {
+            self.focus = Focus::Documents;
+            return;
+        };
+        match key.code {
+            KeyCode::Esc => {
+                self.form = None;
+                self.focus = Focus::Documents;
+                self.status = "Edit cancelled".to_string();
+            }
+            KeyCode::Up => form.selected = form.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => {
+                form.selected = (form.selected + 1).min(form.fields.len().saturating_sub(1))
+            }
+            KeyCode::Backspace => {
+                if let Some(field) = form.fields.get_mut(form.selected) {
+                    field.input.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(field) = form.fields.get_mut(form.selected) {
+                    field.input.push(c);
+                }
+            }
+            KeyCode::Enter => self.save_form().await,
+            _ => {}
+        }
+    }
+
+    async fn save_form(&mut self) {
+        let Some(form) = self.form.as_ref() else {
+            return;
+        };
+        let document = match form.to_document() {
+            Ok(document) => document,
+            Err(err) => {
+                self.status = err;
+                return;
+            }
+        };
+        let entity = self.collection().entity.clone();
+        let query = match_document(&form.original);
+        match self.db.update_one(&entity, query, document, None).await {
+            Ok(_) => {
+                self.form = None;
+                self.focus = Focus::Documents;
+                self.load_documents().await;
+                self.status = "Document saved".to_string();
+            }
+            Err(err) => self.status = format!("Failed to save document: {}", err),
+        }
+    }
+}
+
+/// Build a query matching a document by its fields. Arrays are skipped since `Eq` matches
+/// their elements rather than the array itself.
+fn match_document(document: &Value) -> Query {
+    let queries = document
+        .as_object()
+        .map(|object| {
+            object
+                .iter()
+                .filter(|(_, value)| !value.is_array())
+                .map(|(key, value)| Query::eq(key.as_str(), value.clone()))
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    Query::and(queries)
+}
@@ -0,0 +1,90 @@
+//! Deeb Studio, a terminal UI for browsing and editing instances.
+
+use std::path::Path;
+
+use anyhow::Error;
+use crossterm::event::{self, Event, KeyEventKind};
+use deeb::{Deeb, Entity};
+use serde_json::Value;
+
+use self::app::{App, Collection};
+
+mod app;
+mod ui;
+
+/// Open every instance found at `path` and start the studio. `path` may be a single JSON
+/// file or a directory of JSON files.
+pub async fn run(path: &str) -> Result<(), Error> {
+    let db = Deeb::new().unredacted();
+    let collections = load_collections(&db, Path::new(path)).await?;
+    if collections.is_empty() {
+        return Err(Error::msg(format!("No instances found at `{}`", path)));
+    }
+
+    let mut app = App::new(db, collections);
+    app.load_documents().await;
+
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, &mut app).await;
+    ratatui::restore();
+    result
+}
+
+async fn run_app(terminal: &mut ratatui::DefaultTerminal, app: &mut App) -> Result<(), Error> {
+    while !app.should_quit {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn load_collections(db: &Deeb, path: &Path) -> Result<Vec<Collection>, Error> {
+    let files = if path.is_dir() {
+        let mut files = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| {
+                file.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    } else {
+        vec![path.to_path_buf()]
+    };
+
+    let mut collections = vec![];
+    for file in files {
+        let Some(instance) = file.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        if instance == "_meta" {
+            continue;
+        }
+        let data: Value = serde_json::from_str(&std::fs::read_to_string(&file)?)?;
+        let Some(data) = data.as_object() else {
+            continue;
+        };
+        let entities = data
+            .iter()
+            .filter(|(_, documents)| documents.is_array())
+            .map(|(name, _)| Entity::new(name))
+            .collect::<Vec<_>>();
+        let file_path = file
+            .to_str()
+            .ok_or_else(|| Error::msg("Invalid file path"))?;
+        db.add_instance(instance, file_path, entities.clone())
+            .await?;
+        for entity in entities {
+            collections.push(Collection {
+                instance: instance.to_string(),
+                entity,
+            });
+        }
+    }
+    Ok(collections)
+}
@@ -0,0 +1,156 @@
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame,
+};
+
+use super::app::{App, Focus, PAGE_SIZE};
+
+fn block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default()
+    };
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(style)
+        .title(title)
+}
+
+fn highlight() -> Style {
+    Style::default()
+        .fg(Color::Black)
+        .bg(Color::Cyan)
+        .add_modifier(Modifier::BOLD)
+}
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, query, status] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(3),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    let [collections, documents] =
+        Layout::horizontal([Constraint::Percentage(25), Constraint::Percentage(75)]).areas(main);
+    let [list, detail] =
+        Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(documents);
+
+    // Collections
+    let items = app
+        .collections
+        .iter()
+        .map(|collection| {
+            ListItem::new(format!(
+                "{}/{}",
+                collection.instance, collection.entity.name
+            ))
+        })
+        .collect::<Vec<_>>();
+    let mut state = ListState::default().with_selected(Some(app.selected_collection));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(block(
+                "Collections".to_string(),
+                app.focus == Focus::Collections,
+            ))
+            .highlight_style(highlight()),
+        collections,
+        &mut state,
+    );
+
+    // Documents
+    let items = app
+        .page_documents()
+        .iter()
+        .map(|document| ListItem::new(document.to_string()))
+        .collect::<Vec<_>>();
+    let mut state = ListState::default()
+        .with_selected(app.selected().map(|_| app.selected_document % PAGE_SIZE));
+    frame.render_stateful_widget(
+        List::new(items)
+            .block(block(
+                format!(
+                    "Documents ({} of {}, page {} of {})",
+                    app.documents.len().min(app.selected_document + 1),
+                    app.documents.len(),
+                    app.page() + 1,
+                    app.page_count()
+                ),
+                app.focus == Focus::Documents,
+            ))
+            .highlight_style(highlight()),
+        list,
+        &mut state,
+    );
+
+    // Detail or edit form
+    match app.form.as_ref() {
+        Some(form) => {
+            let lines = form
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(index, field)| {
+                    let style = if index == form.selected {
+                        highlight()
+                    } else {
+                        Style::default()
+                    };
+                    Line::from(vec![
+                        Span::styled(format!("{} ({}): ", field.key, field.kind.label()), style),
+                        Span::raw(field.input.clone()),
+                    ])
+                })
+                .collect::<Vec<_>>();
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .block(block("Edit (enter: save, esc: cancel)".to_string(), true))
+                    .wrap(Wrap { trim: false }),
+                detail,
+            );
+        }
+        None => {
+            let text = app
+                .selected()
+                .and_then(|document| serde_json::to_string_pretty(document).ok())
+                .unwrap_or_default();
+            frame.render_widget(
+                Paragraph::new(text)
+                    .block(block("Document".to_string(), false))
+                    .wrap(Wrap { trim: false }),
+                detail,
+            );
+        }
+    }
+
+    // Query
+    frame.render_widget(
+        Paragraph::new(app.query_input.clone()).block(block(
+            "Query (JSON, e.g. {\"Eq\": [\"name\", \"Joey\"]})".to_string(),
+            app.focus == Focus::Query,
+        )),
+        query,
+    );
+
+    // Status
+    let hints = match app.focus {
+        Focus::Collections => "↑↓ select  tab documents  / query  q quit",
+        Focus::Documents => {
+            "↑↓ select  n/p page  e edit  / query  r reload  tab collections  q quit"
+        }
+        Focus::Query => "enter run  esc cancel",
+        Focus::Edit => "↑↓ field  enter save  esc cancel",
+    };
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled(app.status.clone(), Style::default().fg(Color::Yellow)),
+            Span::raw("  "),
+            Span::styled(hints, Style::default().fg(Color::DarkGray)),
+        ])),
+        status,
+    );
+}
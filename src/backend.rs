@@ -0,0 +1,174 @@
+use anyhow::Error;
+use serde_json::Value;
+use std::future::Future;
+
+use crate::database::{entity::Entity, query::Query, transaction::Transaction};
+use crate::deeb::Deeb;
+
+/// A backend capable of executing Deeb operations.
+///
+/// Application code written against `DeebBackend` can switch between the embedded
+/// [Deeb](crate::Deeb) instance and a remote client without rewriting call sites.
+/// Backends that do not support transactions should return an error when one is provided.
+///
+/// ```
+/// # use deeb::*;
+/// # use anyhow::Error;
+/// # use serde_json::json;
+/// async fn add_user<B: DeebBackend>(backend: &B, user: &Entity) -> Result<(), Error> {
+///     backend.insert(user, json!({"id": 1, "name": "Joey"}), None).await?;
+///     Ok(())
+/// }
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// # let user = Entity::new("user");
+/// # let db = Deeb::new();
+/// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+/// add_user(&db, &user).await?;
+/// # Ok(())
+/// # }
+/// ```
+pub trait DeebBackend {
+    /// Insert a single value.
+    fn insert(
+        &self,
+        entity: &Entity,
+        value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Value, Error>> + Send;
+
+    /// Insert multiple values.
+    fn insert_many(
+        &self,
+        entity: &Entity,
+        values: Vec<Value>,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Vec<Value>, Error>> + Send;
+
+    /// Find a single value.
+    fn find_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Value, Error>> + Send;
+
+    /// Find multiple values.
+    fn find_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Vec<Value>, Error>> + Send;
+
+    /// Delete a single value.
+    fn delete_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Value, Error>> + Send;
+
+    /// Delete multiple values.
+    fn delete_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Vec<Value>, Error>> + Send;
+
+    /// Update a single value.
+    fn update_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Value, Error>> + Send;
+
+    /// Update multiple values.
+    fn update_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> impl Future<Output = Result<Vec<Value>, Error>> + Send;
+}
+
+impl DeebBackend for Deeb {
+    async fn insert(
+        &self,
+        entity: &Entity,
+        value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        Deeb::insert(self, entity, value, transaction).await
+    }
+
+    async fn insert_many(
+        &self,
+        entity: &Entity,
+        values: Vec<Value>,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        Deeb::insert_many(self, entity, values, transaction).await
+    }
+
+    async fn find_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        Deeb::find_one(self, entity, query, transaction).await
+    }
+
+    async fn find_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        Deeb::find_many(self, entity, query, transaction).await
+    }
+
+    async fn delete_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        Deeb::delete_one(self, entity, query, transaction).await
+    }
+
+    async fn delete_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        Deeb::delete_many(self, entity, query, transaction).await
+    }
+
+    async fn update_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        Deeb::update_one(self, entity, query, update_value, transaction).await
+    }
+
+    async fn update_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        Deeb::update_many(self, entity, query, update_value, transaction).await
+    }
+}
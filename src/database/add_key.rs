@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::path;
+
+/// What [Deeb::add_key_with_strategy](crate::Deeb::add_key_with_strategy) does with a
+/// document that conflicts with the new key. A document conflicts when the key already
+/// exists, or when a value on the way to a nested key is not an object.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AddKeyStrategy {
+    /// Replace the conflicting values. Scalars on the path become objects.
+    #[default]
+    Overwrite,
+    /// Leave conflicting documents unchanged.
+    Skip,
+    /// Change nothing and return an error if any document conflicts.
+    Fail,
+}
+
+/// How many documents adding a key would touch.
+///
+/// ```
+/// # use deeb::*;
+/// let report = AddKeyReport { documents: 10, conflicts: 3 };
+/// assert_eq!(report.touched(AddKeyStrategy::Overwrite), 10);
+/// assert_eq!(report.touched(AddKeyStrategy::Skip), 7);
+/// assert_eq!(report.touched(AddKeyStrategy::Fail), 0);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddKeyReport {
+    pub documents: usize,
+    pub conflicts: usize,
+}
+
+impl AddKeyReport {
+    /// Number of documents the strategy changes.
+    pub fn touched(&self, strategy: AddKeyStrategy) -> usize {
+        match strategy {
+            AddKeyStrategy::Overwrite => self.documents,
+            AddKeyStrategy::Skip => self.documents - self.conflicts,
+            AddKeyStrategy::Fail if self.conflicts > 0 => 0,
+            AddKeyStrategy::Fail => self.documents,
+        }
+    }
+}
+
+/// Check if setting the key would replace an existing value in the document.
+pub fn has_conflict(value: &Value, key: &str) -> bool {
+    path::has_conflict(value, &path::parse(key))
+}
+
+/// Set the key, replacing any value in the way.
+pub fn set_key(value: &mut Value, key: &str, default_value: Value) {
+    path::set(value, &path::parse(key), default_value);
+}
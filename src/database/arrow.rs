@@ -0,0 +1,96 @@
+use anyhow::Error;
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMillisecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use chrono::DateTime;
+use serde_json::Value;
+use std::sync::Arc;
+
+use super::{entity::Entity, field_type::FieldType, path};
+
+fn data_type(field_type: FieldType) -> DataType {
+    match field_type {
+        FieldType::String => DataType::Utf8,
+        FieldType::Int => DataType::Int64,
+        FieldType::Float => DataType::Float64,
+        FieldType::Bool => DataType::Boolean,
+        FieldType::DateTime => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+    }
+}
+
+fn column(field: &str, field_type: FieldType, values: &[Value]) -> ArrayRef {
+    let cells = values.iter().map(|value| path::get(value, field));
+    match field_type {
+        FieldType::String => Arc::new(
+            cells
+                .map(|cell| cell.and_then(Value::as_str))
+                .collect::<StringArray>(),
+        ),
+        FieldType::Int => Arc::new(
+            cells
+                .map(|cell| cell.and_then(Value::as_i64))
+                .collect::<Int64Array>(),
+        ),
+        FieldType::Float => Arc::new(
+            cells
+                .map(|cell| cell.and_then(Value::as_f64))
+                .collect::<Float64Array>(),
+        ),
+        FieldType::Bool => Arc::new(
+            cells
+                .map(|cell| cell.and_then(Value::as_bool))
+                .collect::<BooleanArray>(),
+        ),
+        FieldType::DateTime => Arc::new(
+            cells
+                .map(|cell| {
+                    cell.and_then(Value::as_str)
+                        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+                        .map(|at| at.timestamp_millis())
+                })
+                .collect::<TimestampMillisecondArray>()
+                .with_timezone("UTC"),
+        ),
+    }
+}
+
+/// Build an Arrow record batch from documents, with a nullable column for each field
+/// declared with [Entity::field_type](crate::Entity::field_type). Nested fields are
+/// flattened into columns named by their dot path, and missing values or values of
+/// another type are null. Requires the `arrow` feature.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+///
+/// let user = Entity::new("user")
+///     .field_type("name", FieldType::String)
+///     .field_type("address.zip", FieldType::Int);
+/// let batch = to_record_batch(&user, &[json!({"name": "Joey", "address": {"zip": 10001}})]).unwrap();
+/// assert_eq!(batch.num_rows(), 1);
+/// assert_eq!(batch.schema().field(0).name(), "address.zip");
+/// ```
+pub fn to_record_batch(entity: &Entity, values: &[Value]) -> Result<RecordBatch, Error> {
+    if entity.field_types.is_empty() {
+        return Err(Error::msg(format!(
+            "Entity `{}` declares no field types to export",
+            entity.name
+        )));
+    }
+    let fields = entity
+        .field_types
+        .iter()
+        .map(|(field, field_type)| Field::new(field, data_type(*field_type), true))
+        .collect::<Vec<_>>();
+    let columns = entity
+        .field_types
+        .iter()
+        .map(|(field, field_type)| column(field, *field_type, values))
+        .collect::<Vec<_>>();
+    Ok(RecordBatch::try_new(
+        Arc::new(Schema::new(fields)),
+        columns,
+    )?)
+}
@@ -0,0 +1,118 @@
+use anyhow::Error;
+use log::*;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The key of a blob reference, `{"$blob": "<id>"}`.
+pub const BLOB_KEY: &str = "$blob";
+
+/// Serialize `Vec<u8>` fields as base64 strings, keeping small binary values readable in
+/// the JSON file.
+///
+/// ```
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Icon {
+///     #[serde(with = "deeb::base64_bytes")]
+///     png: Vec<u8>,
+/// }
+///
+/// let value = serde_json::to_value(Icon { png: vec![137, 80, 78, 71] }).unwrap();
+/// assert_eq!(value["png"], "iVBORw==");
+/// ```
+pub mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(de::Error::custom)
+    }
+}
+
+/// The directory holding the blobs of an instance, next to its file.
+pub fn blob_dir(file_path: &str) -> PathBuf {
+    PathBuf::from(format!("{}.blobs", file_path))
+}
+
+/// Write a blob and get the reference to store in a document.
+pub fn write_blob(dir: &Path, bytes: &[u8]) -> Result<Value, Error> {
+    fs::create_dir_all(dir)?;
+    let id = uuid::Uuid::new_v4().to_string();
+    fs::write(dir.join(&id), bytes)?;
+    Ok(json!({ BLOB_KEY: id }))
+}
+
+pub fn read_blob(dir: &Path, reference: &Value) -> Result<Vec<u8>, Error> {
+    let id = blob_id(reference)
+        .ok_or_else(|| Error::msg(format!("`{}` is not a blob reference", reference)))?;
+    Ok(fs::read(dir.join(id))?)
+}
+
+/// Collect the ids of the blobs referenced anywhere in the documents.
+pub fn blob_ids<'a, I>(documents: I) -> HashSet<String>
+where
+    I: IntoIterator<Item = &'a Value>,
+{
+    let mut ids = vec![];
+    for document in documents {
+        collect_blob_ids(document, &mut ids);
+    }
+    ids.into_iter().map(String::from).collect()
+}
+
+/// Remove the blobs, unless one of the remaining documents still references them.
+/// Documents may share a reference, so a blob is only removed with its last reference.
+pub fn remove_blobs<'a, R>(dir: &Path, ids: &HashSet<String>, remaining: R)
+where
+    R: IntoIterator<Item = &'a Value>,
+{
+    let mut referenced = vec![];
+    for document in remaining {
+        collect_blob_ids(document, &mut referenced);
+    }
+    let referenced = referenced.into_iter().collect::<HashSet<_>>();
+    for id in ids.iter().filter(|id| !referenced.contains(id.as_str())) {
+        if let Err(err) = fs::remove_file(dir.join(id)) {
+            warn!("Failed to remove blob `{}`: {:?}", id, err);
+        }
+    }
+}
+
+fn blob_id(value: &Value) -> Option<&str> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    let id = object.get(BLOB_KEY)?.as_str()?;
+    // Ids are generated, so anything that could leave the blob directory is not one.
+    uuid::Uuid::parse_str(id).ok()?;
+    Some(id)
+}
+
+fn collect_blob_ids<'a>(value: &'a Value, ids: &mut Vec<&'a str>) {
+    if let Some(id) = blob_id(value) {
+        ids.push(id);
+        return;
+    }
+    match value {
+        Value::Object(object) => object
+            .values()
+            .for_each(|value| collect_blob_ids(value, ids)),
+        Value::Array(values) => values.iter().for_each(|value| collect_blob_ids(value, ids)),
+        _ => {}
+    }
+}
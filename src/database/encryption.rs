@@ -0,0 +1,94 @@
+use anyhow::Error;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde_json::Value;
+
+use super::entity::{Entity, EntityName};
+use super::path;
+
+const PREFIX: &str = "enc:";
+const NONCE_LENGTH: usize = 24;
+
+/// Provides the keys used to encrypt and decrypt entity fields.
+///
+/// Fields declared with [Entity::encrypted_fields](crate::Entity::encrypted_fields) are
+/// encrypted with XChaCha20-Poly1305 before being written to disk, and decrypted when the
+/// instance is loaded.
+///
+/// ```
+/// use deeb::*;
+/// use anyhow::Error;
+///
+/// struct EnvKeyProvider;
+///
+/// impl KeyProvider for EnvKeyProvider {
+///     fn key(&self, _entity: &EntityName) -> Result<[u8; 32], Error> {
+///         Ok([7; 32])
+///     }
+/// }
+/// ```
+pub trait KeyProvider: Send + Sync {
+    /// Return the 256 bit key used for the fields of the entity.
+    fn key(&self, entity: &EntityName) -> Result<[u8; 32], Error>;
+}
+
+fn field_mut<'a>(value: &'a mut Value, field: &str) -> Option<&'a mut Value> {
+    path::get_mut(value, field)
+}
+
+/// Encrypt the encrypted fields of a document in place.
+pub fn encrypt_fields(
+    entity: &Entity,
+    value: &mut Value,
+    key_provider: &dyn KeyProvider,
+) -> Result<(), Error> {
+    let cipher = XChaCha20Poly1305::new(&key_provider.key(&entity.name)?.into());
+    for field in entity.encrypted_fields.iter() {
+        let Some(field_value) = field_mut(value, field) else {
+            continue;
+        };
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = serde_json::to_vec(field_value)?;
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| Error::msg(format!("Failed to encrypt `{}.{}`", entity.name, field)))?;
+        let mut payload = nonce.to_vec();
+        payload.extend(ciphertext);
+        *field_value = Value::String(format!("{}{}", PREFIX, STANDARD.encode(payload)));
+    }
+    Ok(())
+}
+
+/// Decrypt the encrypted fields of a document in place. Fields that are not encrypted,
+/// such as documents written before encryption was enabled, are left as is.
+pub fn decrypt_fields(
+    entity: &Entity,
+    value: &mut Value,
+    key_provider: &dyn KeyProvider,
+) -> Result<(), Error> {
+    let cipher = XChaCha20Poly1305::new(&key_provider.key(&entity.name)?.into());
+    for field in entity.encrypted_fields.iter() {
+        let Some(field_value) = field_mut(value, field) else {
+            continue;
+        };
+        let Some(encoded) = field_value.as_str().and_then(|s| s.strip_prefix(PREFIX)) else {
+            continue;
+        };
+        let payload = STANDARD.decode(encoded)?;
+        if payload.len() < NONCE_LENGTH {
+            return Err(Error::msg(format!(
+                "Invalid encrypted value for `{}.{}`",
+                entity.name, field
+            )));
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+        let plaintext = cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::msg(format!("Failed to decrypt `{}.{}`", entity.name, field)))?;
+        *field_value = serde_json::from_slice(&plaintext)?;
+    }
+    Ok(())
+}
@@ -1,4 +1,10 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use super::field_default::DefaultValue;
+use super::field_type::FieldType;
+use super::time_series::TimeSeries;
+use super::tree::Tree;
 
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Serialize, Deserialize)]
 pub struct EntityName(pub String);
@@ -34,6 +40,21 @@ pub struct Entity {
     pub primary_key: Option<String>,
     pub associations: Vec<EntityAssociation>,
     pub indexes: Vec<Index>,
+    #[serde(default)]
+    pub encrypted_fields: Vec<String>,
+    #[serde(default)]
+    pub redacted_fields: Vec<String>,
+    #[serde(default)]
+    pub defaults: BTreeMap<String, DefaultValue>,
+    #[serde(default)]
+    pub time_series: Option<TimeSeries>,
+    #[serde(default)]
+    pub tree: Option<Tree>,
+    #[serde(default)]
+    pub field_types: BTreeMap<String, FieldType>,
+    /// Convert mismatched values of typed fields instead of rejecting the write.
+    #[serde(default)]
+    pub coerce_types: bool,
 }
 
 impl Entity {
@@ -49,6 +70,13 @@ impl Entity {
             primary_key: None,
             associations: vec![],
             indexes: vec![],
+            encrypted_fields: vec![],
+            redacted_fields: vec![],
+            defaults: BTreeMap::new(),
+            time_series: None,
+            tree: None,
+            field_types: BTreeMap::new(),
+            coerce_types: false,
         }
     }
 
@@ -57,6 +85,110 @@ impl Entity {
         self.clone()
     }
 
+    /// Encrypt fields before they are written to disk. Requires a
+    /// [KeyProvider](crate::KeyProvider) to be set on the Deeb instance.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// let user = Entity::new("user").encrypted_fields(vec!["email", "address.street"]);
+    /// ```
+    pub fn encrypted_fields(&mut self, fields: Vec<&str>) -> Self {
+        self.encrypted_fields = fields.iter().map(|f| f.to_string()).collect();
+        self.clone()
+    }
+
+    /// Hide fields from documents returned by reads and writes, including the documents
+    /// returned by updates, deletes, and pops. Use
+    /// [Deeb::unredacted](crate::Deeb::unredacted) to read them, such as when the reader
+    /// owns the document.
+    ///
+    /// Redaction only hides values. Queries, sorting, and SQL `WHERE` clauses can still use
+    /// redacted fields, so a caller who controls the query can test their values. Do not
+    /// pass untrusted queries to an entity with redacted fields.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// let user = Entity::new("user").redacted_fields(vec!["password", "email"]);
+    /// ```
+    pub fn redacted_fields(&mut self, fields: Vec<&str>) -> Self {
+        self.redacted_fields = fields.iter().map(|f| f.to_string()).collect();
+        self.clone()
+    }
+
+    /// Set a default for a field that is missing when a document is inserted. Use
+    /// [DefaultValue::Now](crate::DefaultValue::Now) or
+    /// [DefaultValue::Uuid](crate::DefaultValue::Uuid) to compute the value at insert.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// use serde_json::json;
+    /// let post = Entity::new("post")
+    ///     .default_value("created_at", DefaultValue::Now)
+    ///     .default_value("status", json!("draft"));
+    /// ```
+    pub fn default_value<V>(&mut self, field: &str, value: V) -> Self
+    where
+        V: Into<DefaultValue>,
+    {
+        self.defaults.insert(field.to_string(), value.into());
+        self.clone()
+    }
+
+    /// Store the entity as a time series. Inserted points are kept sorted by timestamp and
+    /// old points are dropped once they fall out of retention.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// use std::time::Duration;
+    /// let reading = Entity::new("reading").time_series(TimeSeries::new("at", Duration::from_secs(60)));
+    /// ```
+    pub fn time_series(&mut self, time_series: TimeSeries) -> Self {
+        self.time_series = Some(time_series);
+        self.clone()
+    }
+
+    /// Treat the entity as a tree, where each document points at its parent. Requires a
+    /// primary key.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// let comment = Entity::new("comment").primary_key("id").tree(Tree::new("reply_to"));
+    /// ```
+    pub fn tree(&mut self, tree: Tree) -> Self {
+        self.tree = Some(tree);
+        self.clone()
+    }
+
+    /// Require a field to have a type when a document is inserted or updated. Missing and
+    /// `null` fields are allowed. Mismatched values are rejected unless
+    /// [coerce_types](Entity::coerce_types) is set.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// let product = Entity::new("product")
+    ///     .field_type("sku", FieldType::String)
+    ///     .field_type("stock", FieldType::Int)
+    ///     .field_type("restocked_at", FieldType::DateTime);
+    /// ```
+    pub fn field_type(&mut self, field: &str, field_type: FieldType) -> Self {
+        self.field_types.insert(field.to_string(), field_type);
+        self.clone()
+    }
+
+    /// Convert mismatched values of typed fields when nothing is lost, such as `"42"` to
+    /// `42` for an [Int](FieldType::Int) field, and reject the rest.
+    /// # Example
+    /// ```rust
+    /// use deeb::*;
+    /// let product = Entity::new("product")
+    ///     .field_type("stock", FieldType::Int)
+    ///     .coerce_types();
+    /// ```
+    pub fn coerce_types(&mut self) -> Self {
+        self.coerce_types = true;
+        self.clone()
+    }
+
     pub fn add_index(&mut self, name: &str, columns: Vec<&str>) -> &mut Self {
         self.indexes.push(Index {
             name: name.to_string(),
@@ -65,9 +197,9 @@ impl Entity {
         self
     }
 
-    pub fn associate<'a, N>(
+    pub fn associate<N>(
         &mut self,
-        entity: &'a mut Entity,
+        entity: &mut Entity,
         from: &str,
         alias: Option<N>,
     ) -> Result<Self, String>
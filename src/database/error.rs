@@ -0,0 +1,103 @@
+use std::fmt;
+
+use super::entity::{Entity, EntityName};
+use super::name::Name;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    InstanceNotFound,
+    EntityNotFound,
+    DataNotFound,
+    ValueNotFound,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ErrorKind::InstanceNotFound => "Instance not found",
+            ErrorKind::EntityNotFound => "Entity not found",
+            ErrorKind::DataNotFound => "Data not found",
+            ErrorKind::ValueNotFound => "Value not found",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// An error raised by a database operation, with the entity, instance, and file it
+/// happened in when they are known.
+///
+/// Operations return [anyhow::Error], so use `downcast_ref` to read the context.
+///
+/// ```
+/// # use deeb::*;
+/// # use anyhow::Error;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// # let user = Entity::new("user");
+/// # let db = Deeb::new();
+/// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+/// let err = db
+///     .find_one(&user, Query::eq("name", "Nobody"), None)
+///     .await
+///     .unwrap_err();
+/// let err = err.downcast_ref::<DeebError>().unwrap();
+/// assert_eq!(err.kind, ErrorKind::ValueNotFound);
+/// assert_eq!(err.instance.as_deref(), Some("test"));
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeebError {
+    pub kind: ErrorKind,
+    pub entity: Option<EntityName>,
+    pub instance: Option<String>,
+    pub file_path: Option<String>,
+}
+
+impl DeebError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            entity: None,
+            instance: None,
+            file_path: None,
+        }
+    }
+
+    pub fn entity(mut self, entity: &Entity) -> Self {
+        self.entity = Some(entity.name.clone());
+        self
+    }
+
+    pub fn instance(mut self, name: &Name) -> Self {
+        self.instance = Some(name.to_string());
+        self
+    }
+
+    pub fn file_path(mut self, file_path: &str) -> Self {
+        self.file_path = Some(file_path.to_string());
+        self
+    }
+}
+
+impl fmt::Display for DeebError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        let mut context = vec![];
+        if let Some(entity) = &self.entity {
+            context.push(format!("entity `{}`", entity));
+        }
+        if let Some(instance) = &self.instance {
+            context.push(format!("instance `{}`", instance));
+        }
+        if let Some(file_path) = &self.file_path {
+            context.push(format!("file `{}`", file_path));
+        }
+        if !context.is_empty() {
+            write!(f, " ({})", context.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeebError {}
@@ -0,0 +1,88 @@
+use anyhow::Error;
+use chrono::{SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::hash::{Hash, Hasher};
+
+use super::{entity::Entity, path};
+
+/// A value computed for a field that is missing from an inserted document.
+///
+/// In configuration, `"now()"` and `"uuid()"` are expressions and every other JSON value
+/// is used as a literal.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+/// assert_eq!(DefaultValue::from(json!("now()")), DefaultValue::Now);
+/// assert_eq!(DefaultValue::from(json!(0)), DefaultValue::Literal(json!(0)));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Value", into = "Value")]
+pub enum DefaultValue {
+    /// The current time as an RFC 3339 timestamp in UTC.
+    Now,
+    /// A random v4 UUID.
+    Uuid,
+    Literal(Value),
+}
+
+impl DefaultValue {
+    pub fn resolve(&self) -> Value {
+        match self {
+            DefaultValue::Now => {
+                Value::String(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true))
+            }
+            DefaultValue::Uuid => Value::String(uuid::Uuid::new_v4().to_string()),
+            DefaultValue::Literal(value) => value.clone(),
+        }
+    }
+}
+
+impl From<Value> for DefaultValue {
+    fn from(value: Value) -> Self {
+        match value.as_str() {
+            Some("now()") => DefaultValue::Now,
+            Some("uuid()") => DefaultValue::Uuid,
+            _ => DefaultValue::Literal(value),
+        }
+    }
+}
+
+impl From<DefaultValue> for Value {
+    fn from(default_value: DefaultValue) -> Self {
+        match default_value {
+            DefaultValue::Now => Value::from("now()"),
+            DefaultValue::Uuid => Value::from("uuid()"),
+            DefaultValue::Literal(value) => value,
+        }
+    }
+}
+
+impl Hash for DefaultValue {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Value::from(self.clone()).to_string().hash(state);
+    }
+}
+
+/// Set the entity's default values on fields missing from the document. Nested fields use
+/// the same paths as queries, such as `address.zip` or `items[0].sku`, and missing parent
+/// objects are created.
+pub fn apply_defaults(entity: &Entity, value: &mut Value) -> Result<(), Error> {
+    for (field, default_value) in entity.defaults.iter() {
+        let segments = path::parse(field);
+        let mut existing = vec![];
+        path::collect(value, &segments, &mut existing);
+        if !existing.is_empty() || path::get(value, field).is_some() {
+            continue;
+        }
+        if path::has_conflict(value, &segments) {
+            return Err(Error::msg(format!(
+                "Can not set default for `{}` on a non object",
+                field
+            )));
+        }
+        path::set(value, &segments, default_value.resolve());
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use anyhow::Error;
+use chrono::{DateTime, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+
+use super::{entity::Entity, path};
+
+/// The JSON type a field of an entity must have. Missing and `null` fields are allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    String,
+    /// A number without a fraction.
+    Int,
+    /// Any number.
+    Float,
+    Bool,
+    /// An RFC 3339 timestamp string.
+    DateTime,
+}
+
+impl FieldType {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            FieldType::String => value.is_string(),
+            FieldType::Int => value.is_i64() || value.is_u64(),
+            FieldType::Float => value.is_number(),
+            FieldType::Bool => value.is_boolean(),
+            FieldType::DateTime => value
+                .as_str()
+                .is_some_and(|value| DateTime::parse_from_rfc3339(value).is_ok()),
+        }
+    }
+
+    /// Convert a value to the type when no information is lost, such as `"42"` to `42`.
+    fn coerce(&self, value: &Value) -> Option<Value> {
+        match (self, value) {
+            (FieldType::String, Value::Number(number)) => Some(Value::from(number.to_string())),
+            (FieldType::String, Value::Bool(value)) => Some(Value::from(value.to_string())),
+            (FieldType::Int, Value::String(value)) => {
+                value.trim().parse::<i64>().ok().map(Value::from)
+            }
+            (FieldType::Int, Value::Number(number)) => number
+                .as_f64()
+                .filter(|number| number.fract() == 0.0 && number.abs() < i64::MAX as f64)
+                .map(|number| Value::from(number as i64)),
+            (FieldType::Float, Value::String(value)) => value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(Number::from_f64)
+                .map(Value::Number),
+            (FieldType::Bool, Value::String(value)) => match value.trim() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            // Integers are read as milliseconds since the Unix epoch.
+            (FieldType::DateTime, Value::Number(number)) => number
+                .as_i64()
+                .and_then(DateTime::from_timestamp_millis)
+                .map(|at| Value::from(at.to_rfc3339_opts(SecondsFormat::Millis, true))),
+            _ => None,
+        }
+    }
+}
+
+/// Check the typed fields of a document, converting mismatched values when the entity
+/// coerces types. Nested fields use dot notation.
+pub fn apply_field_types(entity: &Entity, value: &mut Value) -> Result<(), Error> {
+    for (field, field_type) in entity.field_types.iter() {
+        let Some(field_value) = path::get_mut(value, field) else {
+            continue;
+        };
+        if field_value.is_null() || field_type.matches(field_value) {
+            continue;
+        }
+        match field_type
+            .coerce(field_value)
+            .filter(|_| entity.coerce_types)
+        {
+            Some(coerced) => *field_value = coerced,
+            None => {
+                return Err(Error::msg(format!(
+                    "Field `{}` of `{}` must be {:?}, found {}",
+                    field, entity.name, field_type, field_value
+                )))
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use super::{number, path};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderDirection {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+/// Where documents with a `null` or missing value are placed, regardless of direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NullsOrder {
+    First,
+    #[default]
+    Last,
+}
+
+/// Sort documents by a property. Use dot notation for nested properties.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FindManyOrder {
+    pub property: String,
+    #[serde(default)]
+    pub direction: OrderDirection,
+    #[serde(default)]
+    pub nulls: NullsOrder,
+}
+
+impl FindManyOrder {
+    pub fn new(property: &str, direction: OrderDirection) -> Self {
+        Self {
+            property: property.to_string(),
+            direction,
+            nulls: NullsOrder::default(),
+        }
+    }
+
+    pub fn nulls_first(mut self) -> Self {
+        self.nulls = NullsOrder::First;
+        self
+    }
+
+    pub fn nulls_last(mut self) -> Self {
+        self.nulls = NullsOrder::Last;
+        self
+    }
+}
+
+/// Options for [Deeb::find_many_with_options](crate::Deeb::find_many_with_options).
+///
+/// Documents are sorted by every order at once, comparing later orders only when the
+/// earlier ones are equal. Skip and limit are applied after sorting.
+///
+/// ```
+/// use deeb::*;
+/// use OrderDirection::*;
+///
+/// let options = FindManyOptions::builder()
+///     .limit(10)
+///     .skip(20)
+///     .sort("name", Ascending)
+///     .sort_desc("age")
+///     .build();
+/// assert_eq!(
+///     options,
+///     FindManyOptions {
+///         skip: Some(20),
+///         limit: Some(10),
+///         order: Some(vec![
+///             FindManyOrder::new("name", Ascending),
+///             FindManyOrder::new("age", Descending),
+///         ]),
+///     }
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FindManyOptions {
+    pub skip: Option<usize>,
+    pub limit: Option<usize>,
+    pub order: Option<Vec<FindManyOrder>>,
+}
+
+impl FindManyOptions {
+    pub fn builder() -> FindManyOptionsBuilder {
+        FindManyOptionsBuilder::default()
+    }
+
+    pub(crate) fn apply(&self, mut values: Vec<Value>) -> Vec<Value> {
+        if let Some(order) = &self.order {
+            // A stable sort keeps insertion order for documents that compare equal.
+            values.sort_by(|a, b| compare_documents(a, b, order));
+        }
+        values
+            .into_iter()
+            .skip(self.skip.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Builds [FindManyOptions] one setting at a time. Orders are applied in the order they
+/// are added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FindManyOptionsBuilder {
+    options: FindManyOptions,
+}
+
+impl FindManyOptionsBuilder {
+    pub fn skip(mut self, skip: usize) -> Self {
+        self.options.skip = Some(skip);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.options.limit = Some(limit);
+        self
+    }
+
+    /// Sort by a property in the direction, with nulls last.
+    pub fn sort(self, property: &str, direction: OrderDirection) -> Self {
+        self.order(FindManyOrder::new(property, direction))
+    }
+
+    /// Sort by a property in ascending order.
+    pub fn sort_asc(self, property: &str) -> Self {
+        self.sort(property, OrderDirection::Ascending)
+    }
+
+    /// Sort by a property in descending order.
+    pub fn sort_desc(self, property: &str) -> Self {
+        self.sort(property, OrderDirection::Descending)
+    }
+
+    /// Sort by an order, for control over where nulls are placed.
+    pub fn order(mut self, order: FindManyOrder) -> Self {
+        self.options.order.get_or_insert_with(Vec::new).push(order);
+        self
+    }
+
+    pub fn build(self) -> FindManyOptions {
+        self.options
+    }
+}
+
+pub(crate) fn compare_documents(a: &Value, b: &Value, order: &[FindManyOrder]) -> Ordering {
+    for order in order {
+        let a = property(a, &order.property);
+        let b = property(b, &order.property);
+        let ordering = match (a, b) {
+            (None, None) => Ordering::Equal,
+            (None, Some(_)) => nulls_ordering(order.nulls),
+            (Some(_), None) => nulls_ordering(order.nulls).reverse(),
+            (Some(a), Some(b)) => match order.direction {
+                OrderDirection::Ascending => compare_values(a, b),
+                OrderDirection::Descending => compare_values(a, b).reverse(),
+            },
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn nulls_ordering(nulls: NullsOrder) -> Ordering {
+    match nulls {
+        NullsOrder::First => Ordering::Less,
+        NullsOrder::Last => Ordering::Greater,
+    }
+}
+
+/// Get a property by dot path, treating `null` the same as a missing value.
+fn property<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    let value = path::get(value, path)?;
+    (!value.is_null()).then_some(value)
+}
+
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            number::compare_numbers(a, b).unwrap_or(Ordering::Equal)
+        }
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b.iter())
+            .map(|(a, b)| compare_values(a, b))
+            .find(|ordering| *ordering != Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
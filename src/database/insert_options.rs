@@ -0,0 +1,36 @@
+use serde::{Deserialize, Serialize};
+
+/// Options for [Deeb::insert_many_with_options](crate::Deeb::insert_many_with_options).
+///
+/// ```
+/// use deeb::*;
+/// let options = InsertOptions::new().return_document(false);
+/// assert!(!options.return_document);
+/// assert!(InsertOptions::default().return_document);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InsertOptions {
+    /// Return the inserted documents, with their defaults applied. Turn this off for bulk
+    /// ingestion to skip copying every document back to the caller.
+    pub return_document: bool,
+}
+
+impl Default for InsertOptions {
+    fn default() -> Self {
+        Self {
+            return_document: true,
+        }
+    }
+}
+
+impl InsertOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn return_document(mut self, return_document: bool) -> Self {
+        self.return_document = return_document;
+        self
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::Error;
+use serde_json::{json, Value};
+
+use super::entity::Entity;
+
+/// Merge instances and their entities into an instance config, a JSON array of instances
+/// with an `entities` array each, as read by
+/// [entities_from_config](crate::entities_from_config).
+///
+/// Instances are matched by `name` and entities by `name` within their instance. The
+/// fields of each entity are replaced by its definition, and every other key of the
+/// config is kept, so settings only a server reads survive. Instances and entities that
+/// are not in the config yet are appended.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+///
+/// let mut config = json!([{"name": "app", "cache": "60s", "entities": [{"name": "user", "public": true}]}]);
+/// let user = Entity::new("user").primary_key("id");
+/// merge_instance_config(&mut config, &[("app", "./app.json", vec![user])]).unwrap();
+/// assert_eq!(config[0]["cache"], json!("60s"));
+/// assert_eq!(config[0]["entities"][0]["public"], json!(true));
+/// assert_eq!(config[0]["entities"][0]["primary_key"], json!("id"));
+/// ```
+pub fn merge_instance_config(
+    config: &mut Value,
+    instances: &[(&str, &str, Vec<Entity>)],
+) -> Result<(), Error> {
+    let config = config
+        .as_array_mut()
+        .ok_or_else(|| Error::msg("Instance config must be a JSON array"))?;
+    for (name, file_path, entities) in instances {
+        let position = config
+            .iter()
+            .position(|instance| instance["name"].as_str() == Some(name));
+        let instance = match position {
+            Some(position) => &mut config[position],
+            None => {
+                config.push(json!({"name": name, "file_path": file_path, "entities": []}));
+                config.last_mut().unwrap()
+            }
+        };
+        let instance = instance
+            .as_object_mut()
+            .ok_or_else(|| Error::msg(format!("Instance `{}` must be an object", name)))?;
+        let config_entities = instance
+            .entry("entities")
+            .or_insert_with(|| Value::Array(vec![]))
+            .as_array_mut()
+            .ok_or_else(|| {
+                Error::msg(format!("Entities of instance `{}` must be an array", name))
+            })?;
+        for entity in entities {
+            let Value::Object(definition) = serde_json::to_value(entity)? else {
+                continue;
+            };
+            let position = config_entities
+                .iter()
+                .position(|config_entity| config_entity["name"] == definition["name"]);
+            match position {
+                Some(position) => config_entities[position]
+                    .as_object_mut()
+                    .ok_or_else(|| {
+                        Error::msg(format!("Entity `{}` must be an object", entity.name))
+                    })?
+                    .extend(definition),
+                None => config_entities.push(Value::Object(definition)),
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::entity::{Entity, EntityName};
+
+/// A problem found by [verify_integrity].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum IntegrityIssue {
+    /// A document has no value for the primary key of its entity.
+    MissingPrimaryKey { entity: EntityName, position: usize },
+    /// More than one document has the same primary key.
+    DuplicatePrimaryKey {
+        entity: EntityName,
+        key: Value,
+        count: usize,
+    },
+    /// A document references a document of an associated entity that does not exist.
+    MissingReference {
+        entity: EntityName,
+        field: String,
+        value: Value,
+        target: EntityName,
+    },
+    /// An association names an entity that is not defined.
+    UnknownEntity {
+        entity: EntityName,
+        target: EntityName,
+    },
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntegrityIssue::MissingPrimaryKey { entity, position } => {
+                write!(f, "{}: document {} has no primary key", entity, position)
+            }
+            IntegrityIssue::DuplicatePrimaryKey { entity, key, count } => {
+                write!(f, "{}: primary key {} is used {} times", entity, key, count)
+            }
+            IntegrityIssue::MissingReference {
+                entity,
+                field,
+                value,
+                target,
+            } => write!(
+                f,
+                "{}: `{}` {} references a missing {}",
+                entity, field, value, target
+            ),
+            IntegrityIssue::UnknownEntity { entity, target } => {
+                write!(f, "{}: associated entity {} is not defined", entity, target)
+            }
+        }
+    }
+}
+
+/// The result of [verify_integrity].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    pub entities: usize,
+    pub documents: usize,
+    pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for IntegrityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for issue in self.issues.iter() {
+            writeln!(f, "{}", issue)?;
+        }
+        writeln!(
+            f,
+            "Checked {} documents in {} entities, found {} issues",
+            self.documents,
+            self.entities,
+            self.issues.len()
+        )
+    }
+}
+
+/// Check the documents of each entity against its definition.
+///
+/// - Every document has a primary key, and no two documents share one.
+/// - Association fields that reference the primary key of another entity point at an
+///   existing document. Documents without the field are skipped.
+///
+/// Indexes only record their columns and hold no entries, so there is nothing to check
+/// for them.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+/// use std::collections::HashMap;
+///
+/// let mut comment = Entity::new("comment").primary_key("id");
+/// let user = Entity::new("user")
+///     .primary_key("id")
+///     .associate(&mut comment, "user_id", None::<&str>)
+///     .unwrap();
+/// let data = HashMap::from([
+///     (EntityName::from("user"), vec![json!({"id": 1})]),
+///     (EntityName::from("comment"), vec![json!({"id": 1, "user_id": 2})]),
+/// ]);
+/// let report = verify_integrity(&[user, comment], &data);
+/// assert_eq!(report.issues.len(), 1);
+/// ```
+pub fn verify_integrity(
+    entities: &[Entity],
+    data: &HashMap<EntityName, Vec<Value>>,
+) -> IntegrityReport {
+    check(entities, |entity| {
+        data.get(&entity.name)
+            .map(|documents| documents.iter().collect())
+            .unwrap_or_default()
+    })
+}
+
+pub(crate) fn check<'a, F>(entities: &[Entity], documents: F) -> IntegrityReport
+where
+    F: Fn(&Entity) -> Vec<&'a Value>,
+{
+    let mut report = IntegrityReport::default();
+    for entity in entities {
+        let values = documents(entity);
+        report.entities += 1;
+        report.documents += values.len();
+
+        if let Some(primary_key) = &entity.primary_key {
+            // Keys are compared by their JSON text, in the order they first appear.
+            let mut keys: Vec<(&Value, usize)> = vec![];
+            let mut positions = HashMap::<String, usize>::new();
+            for (position, value) in values.iter().enumerate() {
+                match value.get(primary_key).filter(|key| !key.is_null()) {
+                    None => report.issues.push(IntegrityIssue::MissingPrimaryKey {
+                        entity: entity.name.clone(),
+                        position,
+                    }),
+                    Some(key) => match positions.get(&key.to_string()) {
+                        Some(index) => keys[*index].1 += 1,
+                        None => {
+                            positions.insert(key.to_string(), keys.len());
+                            keys.push((key, 1));
+                        }
+                    },
+                }
+            }
+            for (key, count) in keys.into_iter().filter(|(_, count)| *count > 1) {
+                report.issues.push(IntegrityIssue::DuplicatePrimaryKey {
+                    entity: entity.name.clone(),
+                    key: key.clone(),
+                    count,
+                });
+            }
+        }
+
+        for association in entity.associations.iter() {
+            let Some(target) = entities
+                .iter()
+                .find(|target| target.name == association.entity_name)
+            else {
+                report.issues.push(IntegrityIssue::UnknownEntity {
+                    entity: entity.name.clone(),
+                    target: association.entity_name.clone(),
+                });
+                continue;
+            };
+            // Only the side holding the foreign key references the other entity.
+            if target.primary_key.as_ref() != Some(&association.to)
+                || entity.primary_key.as_ref() == Some(&association.from)
+            {
+                continue;
+            }
+            let targets = documents(target)
+                .into_iter()
+                .filter_map(|target| target.get(&association.to))
+                .map(Value::to_string)
+                .collect::<HashSet<_>>();
+            for value in values.iter() {
+                let Some(reference) = value.get(&association.from).filter(|key| !key.is_null())
+                else {
+                    continue;
+                };
+                if !targets.contains(&reference.to_string()) {
+                    report.issues.push(IntegrityIssue::MissingReference {
+                        entity: entity.name.clone(),
+                        field: association.from.clone(),
+                        value: reference.clone(),
+                        target: target.name.clone(),
+                    });
+                }
+            }
+        }
+    }
+    report
+}
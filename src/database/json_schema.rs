@@ -0,0 +1,191 @@
+use anyhow::Error;
+use serde_json::{Map, Value};
+
+use super::entity::Entity;
+
+/// Find the object schemas in a JSON Schema or OpenAPI document.
+///
+/// OpenAPI documents use `components.schemas`. JSON Schema documents use `$defs` or
+/// `definitions`, falling back to the root schema when it is an object with a `title`.
+/// References and `allOf` compositions are resolved so each definition has its full list
+/// of properties.
+pub fn json_schema_definitions(document: &Value) -> Result<Vec<(String, Value)>, Error> {
+    let definitions = document
+        .pointer("/components/schemas")
+        .or_else(|| document.get("$defs"))
+        .or_else(|| document.get("definitions"))
+        .and_then(Value::as_object);
+
+    let definitions = match definitions {
+        Some(definitions) => definitions
+            .iter()
+            .map(|(name, schema)| Ok((name.clone(), resolve(document, schema, 0)?)))
+            .collect::<Result<Vec<_>, Error>>()?,
+        None => {
+            let title = document
+                .get("title")
+                .and_then(Value::as_str)
+                .ok_or_else(|| Error::msg("Schema has no definitions and no title"))?;
+            vec![(title.to_string(), resolve(document, document, 0)?)]
+        }
+    };
+
+    Ok(definitions
+        .into_iter()
+        .filter(|(_, schema)| is_object(schema))
+        .collect())
+}
+
+/// Create an entity from a single object schema.
+///
+/// - The entity name is the schema name in snake case.
+/// - The primary key is `x-deeb-primary-key`, or `id` / `_id` when the schema has one.
+/// - Properties marked `writeOnly` or `x-deeb-redacted` become redacted fields.
+/// - Properties marked `x-deeb-encrypted` become encrypted fields.
+pub fn entity_from_json_schema(name: &str, schema: &Value) -> Entity {
+    let mut entity = Entity::new(&to_snake_case(name));
+    let properties = schema.get("properties").and_then(Value::as_object);
+
+    let primary_key = schema
+        .get("x-deeb-primary-key")
+        .and_then(Value::as_str)
+        .or_else(|| {
+            ["id", "_id"]
+                .into_iter()
+                .find(|key| properties.is_some_and(|properties| properties.contains_key(*key)))
+        });
+    if let Some(primary_key) = primary_key {
+        entity.primary_key(primary_key);
+    }
+
+    let mut redacted_fields = vec![];
+    let mut encrypted_fields = vec![];
+    if let Some(properties) = properties {
+        collect_flagged_fields(properties, "", &mut redacted_fields, &mut encrypted_fields);
+    }
+    entity.redacted_fields = redacted_fields;
+    entity.encrypted_fields = encrypted_fields;
+    entity
+}
+
+/// Create entities for every object schema in a JSON Schema or OpenAPI document.
+///
+/// ```
+/// # use deeb::*;
+/// # use serde_json::json;
+/// let document = json!({
+///     "components": {
+///         "schemas": {
+///             "User": {
+///                 "type": "object",
+///                 "properties": {
+///                     "id": {"type": "integer"},
+///                     "password": {"type": "string", "writeOnly": true}
+///                 }
+///             }
+///         }
+///     }
+/// });
+/// let entities = entities_from_json_schema(&document).unwrap();
+/// assert_eq!(entities[0].name.to_string(), "user");
+/// assert_eq!(entities[0].redacted_fields, vec!["password"]);
+/// ```
+pub fn entities_from_json_schema(document: &Value) -> Result<Vec<Entity>, Error> {
+    Ok(json_schema_definitions(document)?
+        .iter()
+        .map(|(name, schema)| entity_from_json_schema(name, schema))
+        .collect())
+}
+
+fn collect_flagged_fields(
+    properties: &Map<String, Value>,
+    prefix: &str,
+    redacted_fields: &mut Vec<String>,
+    encrypted_fields: &mut Vec<String>,
+) {
+    for (key, property) in properties {
+        let path = format!("{}{}", prefix, key);
+        let flag = |name: &str| property.get(name).and_then(Value::as_bool) == Some(true);
+        if flag("writeOnly") || flag("x-deeb-redacted") {
+            redacted_fields.push(path.clone());
+        }
+        if flag("x-deeb-encrypted") {
+            encrypted_fields.push(path.clone());
+        }
+        if let Some(nested) = property.get("properties").and_then(Value::as_object) {
+            collect_flagged_fields(
+                nested,
+                &format!("{}.", path),
+                redacted_fields,
+                encrypted_fields,
+            );
+        }
+    }
+}
+
+fn is_object(schema: &Value) -> bool {
+    schema.get("type").and_then(Value::as_str) == Some("object")
+        || schema.get("properties").is_some()
+}
+
+const MAX_REFERENCE_DEPTH: usize = 32;
+
+/// Follow local `$ref`s and merge `allOf` members into a single schema.
+fn resolve(document: &Value, schema: &Value, depth: usize) -> Result<Value, Error> {
+    if depth > MAX_REFERENCE_DEPTH {
+        return Err(Error::msg("Schema references are nested too deeply"));
+    }
+    if let Some(reference) = schema.get("$ref").and_then(Value::as_str) {
+        let target = reference
+            .strip_prefix('#')
+            .and_then(|pointer| document.pointer(pointer))
+            .ok_or_else(|| Error::msg(format!("Unresolved schema reference `{}`", reference)))?;
+        return resolve(document, target, depth + 1);
+    }
+    let Some(all_of) = schema.get("allOf").and_then(Value::as_array) else {
+        return Ok(schema.clone());
+    };
+
+    let mut merged = schema.as_object().cloned().unwrap_or_default();
+    merged.remove("allOf");
+    merged.insert("type".to_string(), Value::from("object"));
+    for member in all_of {
+        let member = resolve(document, member, depth + 1)?;
+        for key in ["properties", "required"] {
+            match (merged.get_mut(key), member.get(key)) {
+                (Some(Value::Object(target)), Some(Value::Object(source))) => {
+                    target.extend(source.clone());
+                }
+                (Some(Value::Array(target)), Some(Value::Array(source))) => {
+                    target.extend(source.clone());
+                }
+                (None, Some(source)) => {
+                    merged.insert(key.to_string(), source.clone());
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(Value::Object(merged))
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    let mut previous_lowercase = false;
+    for character in name.chars() {
+        if character.is_uppercase() {
+            if previous_lowercase {
+                snake.push('_');
+            }
+            snake.extend(character.to_lowercase());
+            previous_lowercase = false;
+        } else if character == '-' || character == ' ' {
+            snake.push('_');
+            previous_lowercase = false;
+        } else {
+            snake.push(character);
+            previous_lowercase = character.is_lowercase() || character.is_ascii_digit();
+        }
+    }
+    snake
+}
@@ -1,30 +1,82 @@
+use add_key::{AddKeyReport, AddKeyStrategy};
 use anyhow::Error;
+use encryption::KeyProvider;
 use entity::Entity;
+use error::{DeebError, ErrorKind};
+use find_many_options::{FindManyOptions, FindManyOrder};
 use fs2::FileExt;
+use insert_options::InsertOptions;
+use integrity::IntegrityReport;
 use log::*;
 use name::Name;
+use outbox::{OutboxMessage, OUTBOX_ENTITY};
 use query::Query;
-use std::collections::HashMap;
+use reserved_fields::ReservedFields;
+use schema_log::{SchemaChange, SchemaOperation, SchemaReverse, SCHEMA_LOG_ENTITY};
+use slow_query::{SlowQuery, SlowQueryLog};
+use stats::{EntityStats, Stats};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, OpenOptions};
 use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use time_series::{Aggregation, TimeSeries};
+use tokio::sync::Notify;
+use tree::Tree;
+use update_mode::UpdateMode;
+use write_concern::WriteConcern;
 
 use serde_json::{json, Value};
 
 use self::entity::EntityName;
 
+pub mod add_key;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod blob;
+pub mod encryption;
 pub mod entity;
+pub mod error;
+pub mod field_default;
+pub mod field_type;
+pub mod find_many_options;
+pub mod insert_options;
+pub mod instance_config;
+pub mod integrity;
+pub mod json_schema;
 pub mod name;
+pub mod number;
+pub mod outbox;
+pub mod page;
+pub mod path;
+pub mod prepared_query;
 pub mod query;
+pub mod redaction;
+pub mod reserved_fields;
+pub mod schema_diff;
+pub mod schema_log;
+pub mod slow_query;
+#[cfg(feature = "sql")]
+pub mod sql;
+pub mod stats;
+pub mod time_series;
 pub mod transaction;
+pub mod tree;
+pub mod update_mode;
+pub mod write_concern;
 
 /// A database instance. Tpically, a database instance is a JSON file on disk.
 /// The `entities` field is a list of entities that are stored in the database used
 /// by Deeb to index the data.
 #[derive(Debug, Clone)]
 pub struct DatabaseInstance {
+    name: Name,
     file_path: String,
     entities: Vec<Entity>,
-    data: HashMap<EntityName, Vec<Value>>,
+    data: im::HashMap<EntityName, im::Vector<Value>>,
+    slow_query_log: Option<SlowQueryLog>,
+    reserved_fields: Option<ReservedFields>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -37,6 +89,8 @@ pub enum ExecutedValue {
     DeletedMany(Vec<Value>),
     UpdatedOne(Value),
     UpdatedMany(Vec<Value>),
+    PatchedOne(Value),
+    PublishedOutbox(OutboxMessage),
     DroppedKey,
     AddedKey,
 }
@@ -71,11 +125,22 @@ pub enum Operation {
         entity: Entity,
         query: Query,
         value: Value,
+        mode: UpdateMode,
     },
     UpdateMany {
         entity: Entity,
         query: Query,
         value: Value,
+        mode: UpdateMode,
+    },
+    PatchOne {
+        entity: Entity,
+        query: Query,
+        patch: Value,
+    },
+    PublishOutbox {
+        entity: Entity,
+        message: OutboxMessage,
     },
     DropKey {
         entity: Entity,
@@ -89,21 +154,46 @@ pub enum Operation {
 }
 
 /// A database that stores multiple instances of data.
+///
+/// Entity data is held in persistent collections, so cloning a `Database` is cheap
+/// and shares structure with the original. Writers produce new versions of the
+/// data while existing clones keep seeing the version they were taken from.
+#[derive(Clone)]
 pub struct Database {
     instances: HashMap<Name, DatabaseInstance>,
+    key_provider: Option<Arc<dyn KeyProvider>>,
+    write_behind: HashMap<EntityName, (Duration, u64)>,
+    write_behind_generation: u64,
+    dirty: HashSet<Name>,
+    /// Blobs of dirty instances to remove once the instance is written.
+    pending_blobs: HashMap<Name, HashSet<String>>,
+    stats: Stats,
+    changes: Arc<Notify>,
 }
 
 impl Database {
     pub fn new() -> Self {
         let meta = Entity::new("_meta");
         let meta_instance = DatabaseInstance {
+            name: Name::from("_meta"),
             file_path: "_meta.json".to_string(),
             entities: vec![meta],
-            data: HashMap::new(),
+            data: im::HashMap::new(),
+            slow_query_log: None,
+            reserved_fields: None,
         };
         let mut instances = HashMap::new();
         instances.insert(Name::from("_meta"), meta_instance);
-        let mut database = Database { instances };
+        let mut database = Database {
+            instances,
+            key_provider: None,
+            write_behind: HashMap::new(),
+            write_behind_generation: 0,
+            dirty: HashSet::new(),
+            pending_blobs: HashMap::new(),
+            stats: Stats::default(),
+            changes: Arc::new(Notify::new()),
+        };
         database.load_instance(&Name::from("_meta")).unwrap();
         database
     }
@@ -115,9 +205,12 @@ impl Database {
         entities: Vec<Entity>,
     ) -> &mut Self {
         let instance = DatabaseInstance {
+            name: name.clone(),
             file_path: file_path.to_string(),
             entities: entities.clone(),
-            data: HashMap::new(),
+            data: im::HashMap::new(),
+            slow_query_log: None,
+            reserved_fields: None,
         };
         self.instances.insert(name.clone(), instance);
 
@@ -127,7 +220,7 @@ impl Database {
             let data = meta_instance
                 .data
                 .entry(EntityName::from("_meta"))
-                .or_insert(Vec::new());
+                .or_default();
             let entity = json!({
                 "name": entity.name.to_string(),
                 "primary_key": entity.primary_key.clone(),
@@ -144,27 +237,103 @@ impl Database {
                         "columns": index.columns,
                     })
                 }).collect::<Vec<Value>>(),
+                "encrypted_fields": entity.encrypted_fields.clone(),
+                "redacted_fields": entity.redacted_fields.clone(),
+                "defaults": entity.defaults.clone(),
+                "time_series": entity.time_series.clone(),
+                "tree": entity.tree.clone(),
+                "field_types": entity.field_types.clone(),
+                "coerce_types": entity.coerce_types,
             });
             // Replace the entity if it already exists
             let index = data.iter().position(|value| {
-                value.get("name").unwrap().as_str().unwrap().to_string()
-                    == entity.get("name").unwrap().as_str().unwrap().to_string()
+                value.get("name").unwrap().as_str().unwrap()
+                    == entity.get("name").unwrap().as_str().unwrap()
             });
             if let Some(index) = index {
                 data.remove(index);
             }
-            data.push(entity);
+            data.push_back(entity);
         }
 
         self.commit(vec![Name::from("_meta")]).unwrap();
         self
     }
 
+    pub fn set_key_provider(&mut self, key_provider: Arc<dyn KeyProvider>) -> &mut Self {
+        self.key_provider = Some(key_provider);
+        self
+    }
+
+    pub fn set_slow_query_log(
+        &mut self,
+        name: &Name,
+        slow_query_log: Option<SlowQueryLog>,
+    ) -> Result<&mut Self, Error> {
+        let instance = self
+            .instances
+            .get_mut(name)
+            .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(name))?;
+        instance.slow_query_log = slow_query_log;
+        Ok(self)
+    }
+
+    pub fn get_slow_query_log(&self, entity: &Entity) -> Option<&SlowQueryLog> {
+        self.get_instance_by_entity(entity)?.slow_query_log.as_ref()
+    }
+
+    pub fn set_reserved_fields(
+        &mut self,
+        name: &Name,
+        reserved_fields: Option<ReservedFields>,
+    ) -> Result<&mut Self, Error> {
+        let instance = self
+            .instances
+            .get_mut(name)
+            .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(name))?;
+        instance.reserved_fields = reserved_fields;
+        Ok(self)
+    }
+
+    pub fn get_reserved_fields(&self, entity: &Entity) -> Option<&ReservedFields> {
+        self.get_instance_by_entity(entity)?
+            .reserved_fields
+            .as_ref()
+    }
+
+    /// Record a query that examined `scanned` documents against the slow query log of the
+    /// entity's instance, if enabled.
+    pub fn record_slow_query(
+        &self,
+        entity: &Entity,
+        operation: &str,
+        query: &Query,
+        scanned: usize,
+        duration: std::time::Duration,
+        lock_wait: std::time::Duration,
+    ) -> Result<(), Error> {
+        let Some(instance) = self.get_instance_by_entity(entity) else {
+            return Ok(());
+        };
+        let Some(slow_query_log) = instance.slow_query_log.as_ref() else {
+            return Ok(());
+        };
+        slow_query_log.record(&SlowQuery {
+            entity: entity.name.clone(),
+            operation: operation.to_string(),
+            shape: query.shape(),
+            scanned,
+            duration_ms: duration.as_secs_f64() * 1000.0,
+            lock_wait_ms: lock_wait.as_secs_f64() * 1000.0,
+        })
+    }
+
     pub fn load_instance(&mut self, name: &Name) -> Result<&mut Self, Error> {
+        let key_provider = self.key_provider.clone();
         let instance = self
             .instances
             .get_mut(name)
-            .ok_or_else(|| Error::msg("Instance not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(name))?;
         let file = fs::OpenOptions::new()
             .read(true)
             .write(true)
@@ -176,6 +345,31 @@ impl Database {
                 file.read_to_end(buf)?;
                 instance.data = serde_json::from_slice(buf)?;
                 file.unlock()?;
+                for entity in instance.entities.iter() {
+                    if entity.encrypted_fields.is_empty() {
+                        continue;
+                    }
+                    let key_provider = key_provider.as_deref().ok_or_else(|| {
+                        Error::msg(format!(
+                            "Entity `{}` has encrypted fields but no key provider is set",
+                            entity.name
+                        ))
+                    })?;
+                    if let Some(data) = instance.data.get_mut(&entity.name) {
+                        for value in data.iter_mut() {
+                            encryption::decrypt_fields(entity, value, key_provider)?;
+                        }
+                    }
+                }
+                // Files may have been edited outside of Deeb.
+                for entity in instance.entities.iter() {
+                    let Some(time_series) = &entity.time_series else {
+                        continue;
+                    };
+                    if let Some(data) = instance.data.get_mut(&entity.name) {
+                        time_series.sort(data);
+                    }
+                }
             }
             Err(_) => {
                 let mut file = fs::File::create(&instance.file_path)?;
@@ -195,6 +389,14 @@ impl Database {
         Ok(self)
     }
 
+    /// Take a point in time snapshot of the database for reading.
+    ///
+    /// The snapshot shares its data with the live database, so it is cheap to take
+    /// and can be scanned without holding any lock on the original.
+    pub fn snapshot(&self) -> Database {
+        self.clone()
+    }
+
     pub fn get_instance_by_entity(&self, entity: &Entity) -> Option<&DatabaseInstance> {
         self.instances
             .values()
@@ -207,85 +409,273 @@ impl Database {
             .find(|instance| instance.entities.contains(entity))
     }
 
+    /// Get the documents stored under an entity name in an instance, without requiring the
+    /// entity to be registered.
+    pub fn get_instance_data(
+        &self,
+        name: &Name,
+        entity_name: &EntityName,
+    ) -> Result<Option<&im::Vector<Value>>, Error> {
+        let instance = self
+            .instances
+            .get(name)
+            .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(name))?;
+        Ok(instance.data.get(entity_name))
+    }
+
+    /// Get the documents stored under an entity name in an instance for writing, creating
+    /// an empty list if there are none.
+    pub fn get_instance_data_mut(
+        &mut self,
+        name: &Name,
+        entity_name: &EntityName,
+    ) -> Result<&mut im::Vector<Value>, Error> {
+        let instance = self
+            .instances
+            .get_mut(name)
+            .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(name))?;
+        Ok(instance.data.entry(entity_name.clone()).or_default())
+    }
+
+    /// Get the name, file path, and entities of every instance except `_meta`, sorted by
+    /// name.
+    pub fn get_instances(&self) -> Vec<(String, String, Vec<Entity>)> {
+        let mut instances = self
+            .instances
+            .values()
+            .filter(|instance| instance.name != Name::from("_meta"))
+            .map(|instance| {
+                (
+                    instance.name.to_string(),
+                    instance.file_path.clone(),
+                    instance.entities.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        instances.sort_by(|a, b| a.0.cmp(&b.0));
+        instances
+    }
+
+    /// Get an entity of any instance by name.
+    pub fn get_entity(&self, name: &EntityName) -> Option<&Entity> {
+        self.instances
+            .values()
+            .flat_map(|instance| instance.entities.iter())
+            .find(|entity| entity.name == *name)
+    }
+
     pub fn get_instance_name_by_entity(&self, entity: &Entity) -> Result<Name, Error> {
         let name = self
             .instances
             .iter()
             .find(|(_, instance)| instance.entities.contains(entity))
             .map(|(name, _)| name);
-        let name = name.ok_or_else(|| Error::msg("Can't Find Entity Name"))?;
+        let name = name.ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
         Ok(name.clone())
     }
 
+    pub(crate) fn count_documents(&self, entity: &Entity) -> usize {
+        self.get_instance_by_entity(entity)
+            .and_then(|instance| instance.data.get(&entity.name))
+            .map_or(0, |data| data.len())
+    }
+
+    /// Get the directory holding the blobs of the entity's instance.
+    pub fn get_blob_dir(&self, entity: &Entity) -> Result<std::path::PathBuf, Error> {
+        let instance = self
+            .get_instance_by_entity(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        Ok(blob::blob_dir(&instance.file_path))
+    }
+
+    /// Get the documents of an entity if its instance has blobs, to find the blobs a write
+    /// stops referencing.
+    pub fn get_blob_documents(&self, entity: &Entity) -> Option<im::Vector<Value>> {
+        let instance = self.get_instance_by_entity(entity)?;
+        if !blob::blob_dir(&instance.file_path).exists() {
+            return None;
+        }
+        instance.data.get(&entity.name).cloned()
+    }
+
+    /// Remove the blobs referenced by documents that were deleted or replaced, unless a
+    /// document of the instance still references them. While the instance has changes
+    /// that are not written yet, the blobs are kept until it is, so the file on disk never
+    /// references a removed blob.
+    pub fn remove_blobs<'a, I>(&mut self, entity: &Entity, documents: I)
+    where
+        I: IntoIterator<Item = &'a Value>,
+    {
+        let ids = blob::blob_ids(documents);
+        if ids.is_empty() {
+            return;
+        }
+        let Some(instance) = self.get_instance_by_entity(entity) else {
+            error!(
+                "Failed to remove blobs of `{}`: entity not found",
+                entity.name
+            );
+            return;
+        };
+        let name = instance.name.clone();
+        if self.dirty.contains(&name) {
+            self.pending_blobs.entry(name).or_default().extend(ids);
+            return;
+        }
+        self.remove_unreferenced_blobs(&name, &ids);
+    }
+
+    /// Remove the blobs held back while the instances were dirty.
+    fn remove_pending_blobs(&mut self, names: &[Name]) {
+        for name in names {
+            if let Some(ids) = self.pending_blobs.remove(name) {
+                self.remove_unreferenced_blobs(name, &ids);
+            }
+        }
+    }
+
+    fn remove_unreferenced_blobs(&self, name: &Name, ids: &HashSet<String>) {
+        let Some(instance) = self.instances.get(name) else {
+            return;
+        };
+        let remaining = instance.data.values().flat_map(|data| data.iter());
+        blob::remove_blobs(&blob::blob_dir(&instance.file_path), ids, remaining);
+    }
+
+    /// Get the notifier woken after every write, shared by every snapshot.
+    pub fn get_changes(&self) -> Arc<Notify> {
+        self.changes.clone()
+    }
+
+    /// Get the operation counters of every entity that has been used.
+    pub fn get_stats(&self) -> HashMap<EntityName, EntityStats> {
+        self.stats.get()
+    }
+
     // Operations
-    pub fn insert(&mut self, entity: &Entity, insert_value: Value) -> Result<Value, Error> {
+    pub fn insert(&mut self, entity: &Entity, mut insert_value: Value) -> Result<Value, Error> {
+        self.stats.record(&entity.name, true, None);
         // Check insert_value, it needs to be a JSON object.
         // It can not have field or `_id`.
         if !insert_value.is_object() {
             return Err(Error::msg("Value must be a JSON object"));
         }
+        // Defaults are applied after the check so they may fill reserved fields.
+        if let Some(reserved_fields) = self.get_reserved_fields(entity) {
+            reserved_fields.check_insert(&mut insert_value)?;
+        }
+        field_default::apply_defaults(entity, &mut insert_value)?;
+        field_type::apply_field_types(entity, &mut insert_value)?;
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .entry(entity.name.clone())
-            .or_insert(Vec::new());
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.entry(entity.name.clone()).or_default();
+        if entity.tree.is_some() {
+            let (tree, primary_key) = Tree::of(entity)?;
+            tree.set_path(primary_key, data, &mut insert_value)?;
+        }
 
-        data.push(insert_value.clone());
+        match &entity.time_series {
+            Some(time_series) => time_series.append(data, insert_value.clone())?,
+            None => data.push_back(insert_value.clone()),
+        }
         Ok(insert_value)
     }
 
+    /// Insert documents, returning them unless `options` turns that off.
     pub fn insert_many(
         &mut self,
         entity: &Entity,
-        insert_values: Vec<Value>,
+        mut insert_values: Vec<Value>,
+        options: InsertOptions,
     ) -> Result<Vec<Value>, Error> {
-        for insert_value in insert_values.iter() {
+        self.stats.record(&entity.name, true, None);
+        let reserved_fields = self.get_reserved_fields(entity).cloned();
+        for insert_value in insert_values.iter_mut() {
             if !insert_value.is_object() {
                 return Err(Error::msg("Value must be a JSON object"));
             }
+            if let Some(reserved_fields) = &reserved_fields {
+                reserved_fields.check_insert(insert_value)?;
+            }
+            field_default::apply_defaults(entity, insert_value)?;
+            field_type::apply_field_types(entity, insert_value)?;
         }
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .entry(entity.name.clone())
-            .or_insert(Vec::new());
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.entry(entity.name.clone()).or_default();
+        // Insert into a copy so a document that fails leaves the entity unchanged.
+        let mut inserted = data.clone();
 
         let mut values = vec![];
-        for insert_value in insert_values {
-            data.push(insert_value.clone());
-            values.push(insert_value);
+        for mut insert_value in insert_values {
+            if entity.tree.is_some() {
+                let (tree, primary_key) = Tree::of(entity)?;
+                tree.set_path(primary_key, &inserted, &mut insert_value)?;
+            }
+            if options.return_document {
+                values.push(insert_value.clone());
+            }
+            match &entity.time_series {
+                Some(time_series) => time_series.append(&mut inserted, insert_value)?,
+                None => inserted.push_back(insert_value),
+            }
         }
+        *data = inserted;
         Ok(values)
     }
 
     pub fn find_one(&self, entity: &Entity, query: Query) -> Result<Value, Error> {
+        self.find_one_scanned(entity, query).map(|(value, _)| value)
+    }
+
+    /// Find the first matching document along with the number of documents examined.
+    pub(crate) fn find_one_scanned(
+        &self,
+        entity: &Entity,
+        query: Query,
+    ) -> Result<(Value, usize), Error> {
         let instance = self
             .get_instance_by_entity(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
-        let result = data
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let index = data
             .iter()
-            .find(|value| query.clone().matches(value).unwrap_or(false));
-        result
-            .map(|value| value.clone())
-            .ok_or_else(|| Error::msg("Value not found"))
+            .position(|value| query.clone().matches(value).unwrap_or(false));
+        let scanned = index.map_or(data.len(), |index| index + 1);
+        self.stats.record(&entity.name, false, Some(scanned));
+        let value = index.map(|index| data[index].clone()).ok_or_else(|| {
+            DeebError::new(ErrorKind::ValueNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        Ok((value, scanned))
     }
 
-    pub fn find_many(&self, entity: &Entity, query: Query) -> Result<Vec<Value>, Error> {
+    pub fn find_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        options: Option<FindManyOptions>,
+    ) -> Result<Vec<Value>, Error> {
+        self.stats
+            .record(&entity.name, false, Some(self.count_documents(entity)));
         let instance = self
             .get_instance_by_entity(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
         let associated_entities = query.associated_entities();
         let data = data
             .iter()
@@ -308,7 +698,7 @@ impl Database {
                                                                               //safely
                     );
                     let associated_data = self
-                        .find_many(associated_entity, association_query)
+                        .find_many(associated_entity, association_query, None)
                         .unwrap();
 
                     value.as_object_mut().unwrap().insert(
@@ -321,33 +711,204 @@ impl Database {
             .collect::<Vec<Value>>();
         let result = data
             .iter()
-            .filter(|value| query.clone().matches(value).unwrap_or(false));
-        Ok(result.cloned().collect())
+            .filter(|value| query.clone().matches(value).unwrap_or(false))
+            .cloned()
+            .collect::<Vec<Value>>();
+        match options {
+            Some(options) => Ok(options.apply(result)),
+            None => Ok(result),
+        }
+    }
+
+    /// Follow associations from the documents matching a query, one entity of the path
+    /// at a time, for at most `depth` steps. Returns the documents reached at each step,
+    /// starting with the matched documents.
+    pub fn traverse(
+        &self,
+        entity: &Entity,
+        query: Query,
+        path: &[Entity],
+        depth: Option<usize>,
+    ) -> Result<Vec<Vec<Value>>, Error> {
+        let mut levels = vec![self.find_many(entity, query, None)?];
+        let mut previous = entity;
+        for next in path.iter().take(depth.unwrap_or(usize::MAX)) {
+            let association = previous
+                .associations
+                .iter()
+                .find(|association| association.entity_name == next.name)
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "Entity `{}` has no association to `{}`",
+                        previous.name, next.name
+                    ))
+                })?;
+            let keys = levels
+                .last()
+                .unwrap()
+                .iter()
+                .filter_map(|value| value.get(&association.from))
+                .map(|key| key.to_string())
+                .collect::<HashSet<_>>();
+            let instance = self
+                .get_instance_by_entity(next)
+                .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(next))?;
+            let data = instance.data.get(&next.name).cloned().unwrap_or_default();
+            self.stats.record(&next.name, false, Some(data.len()));
+            let level = data
+                .into_iter()
+                .filter(|value| match value.get(&association.to) {
+                    Some(Value::Array(values)) => {
+                        values.iter().any(|value| keys.contains(&value.to_string()))
+                    }
+                    Some(value) => keys.contains(&value.to_string()),
+                    None => false,
+                })
+                .collect::<Vec<_>>();
+            levels.push(level);
+            previous = next;
+        }
+        Ok(levels)
+    }
+
+    /// Find every document below the one with the id in a tree entity.
+    pub fn find_descendants(&self, entity: &Entity, id: &Value) -> Result<Vec<Value>, Error> {
+        self.stats
+            .record(&entity.name, false, Some(self.count_documents(entity)));
+        let (tree, primary_key) = Tree::of(entity)?;
+        let instance = self
+            .get_instance_by_entity(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).cloned().unwrap_or_default();
+        Ok(tree.descendants(primary_key, &data, id))
+    }
+
+    /// Find the ancestors of the document with the id in a tree entity, parent first.
+    pub fn find_ancestors(&self, entity: &Entity, id: &Value) -> Result<Vec<Value>, Error> {
+        self.stats
+            .record(&entity.name, false, Some(self.count_documents(entity)));
+        let (tree, primary_key) = Tree::of(entity)?;
+        let instance = self
+            .get_instance_by_entity(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).cloned().unwrap_or_default();
+        tree.ancestors(primary_key, &data, id)
+    }
+
+    fn get_time_series<'a>(
+        &'a self,
+        entity: &'a Entity,
+    ) -> Result<(&'a TimeSeries, im::Vector<Value>), Error> {
+        let time_series = entity
+            .time_series
+            .as_ref()
+            .ok_or_else(|| Error::msg(format!("Entity `{}` is not a time series", entity.name)))?;
+        let instance = self
+            .get_instance_by_entity(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).cloned().unwrap_or_default();
+        Ok((time_series, data))
+    }
+
+    /// Find the points of a time series entity with a timestamp in `from..to`.
+    pub fn find_range(
+        &self,
+        entity: &Entity,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Vec<Value>, Error> {
+        self.stats.record(&entity.name, false, None);
+        let (time_series, data) = self.get_time_series(entity)?;
+        Ok(time_series.range(&data, from, to).into_iter().collect())
+    }
+
+    /// Roll the points of a time series entity in `from..to` up into buckets.
+    pub fn downsample(
+        &self,
+        entity: &Entity,
+        from: Option<i64>,
+        to: Option<i64>,
+        field: &str,
+        aggregation: Aggregation,
+    ) -> Result<Vec<Value>, Error> {
+        self.stats.record(&entity.name, false, None);
+        let (time_series, data) = self.get_time_series(entity)?;
+        time_series.downsample(&time_series.range(&data, from, to), field, aggregation)
+    }
+
+    /// Add a message to the outbox of the entity's instance.
+    pub fn publish_outbox(&mut self, entity: &Entity, message: OutboxMessage) -> Result<(), Error> {
+        let name = self.get_instance_name_by_entity(entity)?;
+        let data = self.get_instance_data_mut(&name, &EntityName::from(OUTBOX_ENTITY))?;
+        data.push_back(serde_json::to_value(message)?);
+        Ok(())
+    }
+
+    /// Claim up to `limit` outbox messages of an instance for `visibility`.
+    pub fn claim_outbox(
+        &mut self,
+        name: &Name,
+        limit: usize,
+        visibility: Duration,
+    ) -> Result<Vec<OutboxMessage>, Error> {
+        let data = self.get_instance_data_mut(name, &EntityName::from(OUTBOX_ENTITY))?;
+        outbox::claim(data, limit, visibility)
+    }
+
+    /// Remove delivered outbox messages of an instance, returning how many were removed.
+    pub fn ack_outbox(&mut self, name: &Name, ids: &[String]) -> Result<usize, Error> {
+        let data = self.get_instance_data_mut(name, &EntityName::from(OUTBOX_ENTITY))?;
+        Ok(outbox::remove(data, ids))
     }
 
     pub fn delete_one(&mut self, entity: &Entity, query: Query) -> Result<Value, Error> {
+        self.delete_one_scanned(entity, query)
+            .map(|(value, _)| value)
+    }
+
+    /// Delete the first matching document, returning it with the number of documents
+    /// examined.
+    pub(crate) fn delete_one_scanned(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+    ) -> Result<(Value, usize), Error> {
+        let stats = self.stats.clone();
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
         let index = data
             .iter()
-            .position(|value| query.clone().matches(value).unwrap_or(false))
-            .ok_or_else(|| Error::msg("Value not found"))?;
-        Ok(data.remove(index))
+            .position(|value| query.clone().matches(value).unwrap_or(false));
+        let scanned = index.map_or(data.len(), |index| index + 1);
+        stats.record(&entity.name, true, Some(scanned));
+        let index = index.ok_or_else(|| {
+            DeebError::new(ErrorKind::ValueNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        Ok((data.remove(index), scanned))
     }
 
     pub fn delete_many(&mut self, entity: &Entity, query: Query) -> Result<Vec<Value>, Error> {
+        self.stats
+            .record(&entity.name, true, Some(self.count_documents(entity)));
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
         let indexes = data
             .iter()
             .enumerate()
@@ -361,43 +922,199 @@ impl Database {
         Ok(values)
     }
 
+    /// Remove every document of an entity by replacing its data, returning the removed
+    /// documents.
+    pub fn truncate(&mut self, entity: &Entity) -> Result<im::Vector<Value>, Error> {
+        self.stats
+            .record(&entity.name, true, Some(self.count_documents(entity)));
+        let instance = self
+            .get_instance_by_entity_mut(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        Ok(std::mem::take(data))
+    }
+
+    /// Remove and return the first or last matching document. Without an order, documents
+    /// are taken in insertion order.
+    pub fn pop(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        order: Option<Vec<FindManyOrder>>,
+        last: bool,
+    ) -> Result<Option<Value>, Error> {
+        self.stats
+            .record(&entity.name, true, Some(self.count_documents(entity)));
+        let instance = self
+            .get_instance_by_entity_mut(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let matches = data
+            .iter()
+            .enumerate()
+            .filter(|(_, value)| query.matches(value).unwrap_or(false));
+        let index =
+            match (&order, last) {
+                (Some(order), false) => matches
+                    .min_by(|(_, a), (_, b)| find_many_options::compare_documents(a, b, order)),
+                (Some(order), true) => matches
+                    .max_by(|(_, a), (_, b)| find_many_options::compare_documents(a, b, order)),
+                (None, false) => matches.min_by_key(|(index, _)| *index),
+                (None, true) => matches.max_by_key(|(index, _)| *index),
+            }
+            .map(|(index, _)| index);
+        Ok(index.map(|index| data.remove(index)))
+    }
+
     pub fn update_one(
         &mut self,
         entity: &Entity,
         query: Query,
         update_value: Value,
+        mode: UpdateMode,
     ) -> Result<Value, Error> {
+        self.update_one_scanned(entity, query, update_value, mode)
+            .map(|(value, _)| value)
+    }
+
+    /// Update the first matching document, returning it with the number of documents
+    /// examined.
+    pub(crate) fn update_one_scanned(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        mode: UpdateMode,
+    ) -> Result<(Value, usize), Error> {
+        let stats = self.stats.clone();
+        let reparents = tree::reparents(entity, &update_value);
+        let reserved_fields = self.get_reserved_fields(entity).cloned();
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let original = data.clone();
         let index = data
             .iter()
-            .position(|value| query.clone().matches(value).unwrap_or(false))
-            .ok_or_else(|| Error::msg("Value not found"))?;
-        let value = data
-            .get_mut(index)
-            .ok_or_else(|| Error::msg("Value not found"))?;
-        // combine the values together, so that the updated values are merged with the existing values.
-        let new_value = match value {
-            Value::Object(value) => {
-                let update_value = match update_value {
-                    Value::Object(update_value) => update_value,
-                    _ => return Err(Error::msg("Update value must be a JSON object")),
-                };
-                let mut value = value.clone();
-                for (update_key, update_value) in update_value {
-                    value.insert(update_key, update_value);
-                }
-                Value::Object(value)
-            }
-            _ => return Err(Error::msg("Value must be a JSON object")),
-        };
+            .position(|value| query.clone().matches(value).unwrap_or(false));
+        let scanned = index.map_or(data.len(), |index| index + 1);
+        stats.record(&entity.name, true, Some(scanned));
+        let index = index.ok_or_else(|| {
+            DeebError::new(ErrorKind::ValueNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let value = data.get_mut(index).ok_or_else(|| {
+            DeebError::new(ErrorKind::ValueNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let mut new_value = mode.apply(value, &update_value)?;
+        field_type::apply_field_types(entity, &mut new_value)?;
+        if let Some(reserved_fields) = &reserved_fields {
+            reserved_fields.check_update(value, &mut new_value)?;
+        }
+        if let Some(time_series) = &entity.time_series {
+            time_series.timestamp(&new_value)?;
+        }
         *value = new_value.clone();
-        Ok(new_value)
+        if reparents {
+            tree::rebuild(entity, data, original)?;
+            new_value = data[index].clone();
+        }
+        if let Some(time_series) = &entity.time_series {
+            time_series.sort(data);
+        }
+        Ok((new_value, scanned))
+    }
+
+    /// Apply a JSON Patch (RFC 6902) to the first matching document. Either every
+    /// operation applies or the document is left unchanged.
+    pub fn patch_one(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        patch: &Value,
+    ) -> Result<Value, Error> {
+        self.patch_one_scanned(entity, query, patch)
+            .map(|(value, _)| value)
+    }
+
+    /// Patch the first matching document, returning it with the number of documents
+    /// examined.
+    pub(crate) fn patch_one_scanned(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        patch: &Value,
+    ) -> Result<(Value, usize), Error> {
+        let patch = serde_json::from_value::<json_patch::Patch>(patch.clone())
+            .map_err(|err| Error::msg(format!("Invalid JSON Patch: {}", err)))?;
+        let stats = self.stats.clone();
+        let reserved_fields = self.get_reserved_fields(entity).cloned();
+        let instance = self
+            .get_instance_by_entity_mut(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let original = data.clone();
+        let index = data
+            .iter()
+            .position(|value| query.matches(value).unwrap_or(false));
+        let scanned = index.map_or(data.len(), |index| index + 1);
+        stats.record(&entity.name, true, Some(scanned));
+        let index = index.ok_or_else(|| {
+            DeebError::new(ErrorKind::ValueNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let mut new_value = data[index].clone();
+        json_patch::patch(&mut new_value, &patch)?;
+        if !new_value.is_object() {
+            return Err(Error::msg("Patched value must be a JSON object"));
+        }
+        field_type::apply_field_types(entity, &mut new_value)?;
+        if let Some(reserved_fields) = &reserved_fields {
+            reserved_fields.check_update(&data[index], &mut new_value)?;
+        }
+        if let Some(time_series) = &entity.time_series {
+            time_series.timestamp(&new_value)?;
+        }
+        data[index] = new_value.clone();
+        if entity
+            .tree
+            .as_ref()
+            .is_some_and(|tree| tree.path_field.is_some())
+        {
+            tree::rebuild(entity, data, original)?;
+            new_value = data[index].clone();
+        }
+        if let Some(time_series) = &entity.time_series {
+            time_series.sort(data);
+        }
+        Ok((new_value, scanned))
     }
 
     pub fn update_many(
@@ -405,14 +1122,22 @@ impl Database {
         entity: &Entity,
         query: Query,
         update_value: Value,
+        mode: UpdateMode,
     ) -> Result<Vec<Value>, Error> {
+        self.stats
+            .record(&entity.name, true, Some(self.count_documents(entity)));
+        let reparents = tree::reparents(entity, &update_value);
+        let reserved_fields = self.get_reserved_fields(entity).cloned();
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let original = data.clone();
         let indexes = data
             .iter()
             .enumerate()
@@ -421,136 +1146,352 @@ impl Database {
             .collect::<Vec<_>>();
         let mut values = vec![];
         for index in indexes.iter() {
-            let value = data
-                .get_mut(*index)
-                .ok_or_else(|| Error::msg("Value not found"))?;
-            // combine the values together, so that the updated values are merged with the existing values.
-            let new_value = match value {
-                Value::Object(value) => {
-                    let update_value = match update_value.clone() {
-                        Value::Object(update_value) => update_value,
-                        _ => return Err(Error::msg("Value must be a JSON object")),
-                    };
-                    let mut value = value.clone();
-                    for (update_key, update_value) in update_value {
-                        value.insert(update_key, update_value);
-                    }
-                    Value::Object(value)
-                }
-                _ => return Err(Error::msg("Value must be a JSON object")),
-            };
+            let value = data.get_mut(*index).ok_or_else(|| {
+                DeebError::new(ErrorKind::ValueNotFound)
+                    .entity(entity)
+                    .instance(&instance.name)
+                    .file_path(&instance.file_path)
+            })?;
+            let mut new_value = mode.apply(value, &update_value)?;
+            let checked = field_type::apply_field_types(entity, &mut new_value)
+                .and_then(|_| match &reserved_fields {
+                    Some(reserved_fields) => reserved_fields.check_update(value, &mut new_value),
+                    None => Ok(()),
+                })
+                .and_then(|_| match &entity.time_series {
+                    Some(time_series) => time_series.timestamp(&new_value).map(|_| ()),
+                    None => Ok(()),
+                });
+            if let Err(err) = checked {
+                // Leave every document unchanged when one is rejected.
+                *data = original;
+                return Err(err);
+            }
             *value = new_value.clone();
             values.push(new_value);
         }
+        if reparents {
+            tree::rebuild(entity, data, original)?;
+            values = indexes.iter().map(|index| data[*index].clone()).collect();
+        }
+        if let Some(time_series) = &entity.time_series {
+            time_series.sort(data);
+        }
         Ok(values)
     }
 
+    /// Set or clear the write behind interval of an entity. Each new interval gets a new
+    /// generation, so a task flushing on the old interval can tell it was replaced.
+    pub fn set_write_behind(&mut self, entity: &Entity, interval: Option<Duration>) -> &mut Self {
+        match interval {
+            Some(interval) => {
+                self.write_behind_generation += 1;
+                let generation = self.write_behind_generation;
+                self.write_behind
+                    .insert(entity.name.clone(), (interval, generation))
+            }
+            None => self.write_behind.remove(&entity.name),
+        };
+        self
+    }
+
+    pub fn get_write_behind(&self, entity: &Entity) -> Option<Duration> {
+        self.write_behind
+            .get(&entity.name)
+            .map(|(interval, _)| *interval)
+    }
+
+    pub fn get_write_behind_generation(&self, entity: &Entity) -> Option<u64> {
+        self.write_behind
+            .get(&entity.name)
+            .map(|(_, generation)| *generation)
+    }
+
+    /// Commit an instance after writing to an entity. Writes made with
+    /// [WriteConcern::Memory], or through a write behind entity without an explicit
+    /// [WriteConcern::Fsynced], mark the instance dirty and are left for [Database::flush].
+    pub fn commit_entity(
+        &mut self,
+        entity: &Entity,
+        name: Name,
+        write_concern: WriteConcern,
+    ) -> Result<(), Error> {
+        let write_behind = self.write_behind.contains_key(&entity.name);
+        self.changes.notify_waiters();
+        match write_concern {
+            WriteConcern::Memory => {
+                self.dirty.insert(name);
+                Ok(())
+            }
+            WriteConcern::Flushed if write_behind => {
+                self.dirty.insert(name);
+                Ok(())
+            }
+            WriteConcern::Flushed => {
+                self.dirty.remove(&name);
+                self.commit(vec![name.clone()])?;
+                self.remove_pending_blobs(&[name]);
+                Ok(())
+            }
+            WriteConcern::Fsynced => {
+                self.dirty.remove(&name);
+                self.commit_durable(vec![name.clone()])?;
+                self.remove_pending_blobs(&[name]);
+                Ok(())
+            }
+        }
+    }
+
+    /// Commit every instance with changes that have not been written yet.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let names = self.dirty.drain().collect::<Vec<_>>();
+        if names.is_empty() {
+            return Ok(());
+        }
+        trace!("Flushing instances: {:?}", names);
+        self.commit(names.clone()).inspect_err(|_| {
+            // Keep the instances dirty so the next flush tries again.
+            self.dirty.extend(names.clone());
+        })?;
+        self.remove_pending_blobs(&names);
+        Ok(())
+    }
+
     pub fn commit(&self, name: Vec<Name>) -> Result<(), Error> {
+        self.changes.notify_waiters();
+        self.write_instances(name, false)
+    }
+
+    /// Commit the instances and wait for the data to reach the disk.
+    pub fn commit_durable(&self, name: Vec<Name>) -> Result<(), Error> {
+        self.changes.notify_waiters();
+        self.write_instances(name, true)
+    }
+
+    fn write_instances(&self, name: Vec<Name>, sync: bool) -> Result<(), Error> {
         for name in name {
             let instance = self
                 .instances
                 .get(&name)
-                .ok_or_else(|| Error::msg("Instance not found"))?;
+                .ok_or_else(|| DeebError::new(ErrorKind::InstanceNotFound).instance(&name))?;
             let mut file = OpenOptions::new()
                 .read(true)
                 .write(true)
                 .open(&instance.file_path)?;
+            let data = self.encrypt_instance_data(instance)?;
             file.lock_exclusive()?;
             file.set_len(0)?;
-            file.write_all(serde_json::to_string(&instance.data)?.as_bytes())?;
+            file.write_all(serde_json::to_string(&data)?.as_bytes())?;
+            if sync {
+                file.sync_all()?;
+            }
             file.unlock()?;
         }
         Ok(())
     }
 
+    fn encrypt_instance_data<'a>(
+        &self,
+        instance: &'a DatabaseInstance,
+    ) -> Result<Cow<'a, im::HashMap<EntityName, im::Vector<Value>>>, Error> {
+        let encrypted_entities = instance
+            .entities
+            .iter()
+            .filter(|entity| !entity.encrypted_fields.is_empty())
+            .collect::<Vec<_>>();
+        if encrypted_entities.is_empty() {
+            return Ok(Cow::Borrowed(&instance.data));
+        }
+        let mut data = instance.data.clone();
+        for entity in encrypted_entities {
+            let key_provider = self.key_provider.as_deref().ok_or_else(|| {
+                Error::msg(format!(
+                    "Entity `{}` has encrypted fields but no key provider is set",
+                    entity.name
+                ))
+            })?;
+            if let Some(values) = data.get_mut(&entity.name) {
+                for value in values.iter_mut() {
+                    encryption::encrypt_fields(entity, value, key_provider)?;
+                }
+            }
+        }
+        Ok(Cow::Owned(data))
+    }
+
+    /// Check the documents of every instance against their entities. See
+    /// [integrity::verify_integrity] for the checks.
+    pub fn verify_integrity(&self) -> IntegrityReport {
+        let entities = self
+            .instances
+            .values()
+            .filter(|instance| instance.name != Name::from("_meta"))
+            .flat_map(|instance| instance.entities.iter().cloned())
+            .collect::<Vec<_>>();
+        integrity::check(&entities, |entity| {
+            self.get_instance_name_by_entity(entity)
+                .ok()
+                .and_then(|name| self.instances.get(&name))
+                .and_then(|instance| instance.data.get(&entity.name))
+                .map(|data| data.iter().collect())
+                .unwrap_or_default()
+        })
+    }
+
     // Management
-    pub fn drop_key(&mut self, entity: &Entity, key: &str) -> Result<(), Error> {
+    pub fn drop_key(&mut self, entity: &Entity, key: &str) -> Result<SchemaChange, Error> {
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        let segments = path::parse(key);
+        let wildcard = segments.contains(&path::Segment::Wildcard);
+        // Values reached through a wildcard can not be set back one by one, and protected
+        // values must not be copied into the schema log.
+        let primary_key = entity
+            .primary_key
+            .clone()
+            .filter(|_| !wildcard && !schema_log::is_protected(entity, key));
+        let mut dropped = vec![];
+        let mut documents = 0;
         // Iterate through the entities
         for value in data.iter_mut() {
-            match value {
-                Value::Object(value) => {
-                    if key.contains('.') {
-                        let keys = key.split('.').collect::<Vec<&str>>();
-                        let mut current = value.clone();
-                        let mut key_exists = true;
-                        for key in keys.iter().take(keys.len() - 1) {
-                            current = match current.get_mut(*key) {
-                                Some(Value::Object(current)) => current.clone(),
-                                _ => {
-                                    key_exists = false;
-                                    break;
-                                }
-                            };
-                        }
-                        if key_exists {
-                            let mut current = value;
-                            for key in keys.iter().take(keys.len() - 1) {
-                                current = match current.get_mut(*key) {
-                                    Some(Value::Object(current)) => current,
-                                    _ => {
-                                        error!("Value must be a JSON object");
-                                        return Err(Error::msg("Value must be a JSON object"));
-                                    }
-                                };
-                            }
-                            let key = keys.last().unwrap().to_owned();
-                            current.remove(key);
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        value.remove(key);
-                    }
+            if !value.is_object() {
+                return Err(Error::msg("Value must be a JSON object"));
+            }
+            let old = match wildcard {
+                true => {
+                    let mut removed = vec![];
+                    path::collect(value, &segments, &mut removed);
+                    (!removed.is_empty()).then_some(Value::Array(removed))
                 }
-                _ => return Err(Error::msg("Value must be a JSON object")),
+                false => path::get(value, key).cloned(),
+            };
+            let Some(old) = old else {
+                continue;
+            };
+            documents += 1;
+            if let Some(primary_key) = &primary_key {
+                let id = value.get(primary_key).cloned().unwrap_or_default();
+                dropped.push((id, old));
             }
+            path::remove(value, &segments);
         }
+        let reverse = primary_key.map(|primary_key| SchemaReverse::RestoreKey {
+            key: key.to_string(),
+            primary_key,
+            values: dropped,
+        });
+        let operation = SchemaOperation::DropKey {
+            key: key.to_string(),
+        };
+        Ok(SchemaChange::new(
+            &entity.name,
+            operation,
+            documents,
+            reverse,
+        ))
+    }
+
+    /// Append a change to the schema log of the `_meta` instance.
+    pub fn record_schema_change(&mut self, change: SchemaChange) -> Result<(), Error> {
+        let data =
+            self.get_instance_data_mut(&Name::from("_meta"), &EntityName::from(SCHEMA_LOG_ENTITY))?;
+        data.push_back(serde_json::to_value(change)?);
         Ok(())
     }
 
+    /// Undo the last change of the schema log and remove it from the log, returning the
+    /// change and the instance it was undone on.
+    pub fn undo_last_schema_change(&mut self) -> Result<Option<(SchemaChange, Name)>, Error> {
+        let log =
+            self.get_instance_data_mut(&Name::from("_meta"), &EntityName::from(SCHEMA_LOG_ENTITY))?;
+        let Some(last) = log.last() else {
+            return Ok(None);
+        };
+        let change = serde_json::from_value::<SchemaChange>(last.clone())?;
+        let Some(reverse) = &change.reverse else {
+            return Err(Error::msg(format!(
+                "The last schema change of `{}` can not be undone",
+                change.entity
+            )));
+        };
+        let entity = self
+            .get_entity(&change.entity)
+            .cloned()
+            .ok_or_else(|| Error::msg(format!("Entity `{}` not found", change.entity)))?;
+        let name = self.get_instance_name_by_entity(&entity)?;
+        match reverse {
+            SchemaReverse::DropKey { key } => {
+                self.drop_key(&entity, key)?;
+            }
+            SchemaReverse::RestoreKey {
+                key,
+                primary_key,
+                values,
+            } => {
+                let data = self.get_instance_data_mut(&name, &entity.name)?;
+                schema_log::restore_key(data, key, primary_key, values);
+            }
+        }
+        self.get_instance_data_mut(&Name::from("_meta"), &EntityName::from(SCHEMA_LOG_ENTITY))?
+            .pop_back();
+        Ok(Some((change, name)))
+    }
+
+    pub fn add_key_report(&self, entity: &Entity, key: &str) -> Result<AddKeyReport, Error> {
+        let instance = self
+            .get_instance_by_entity(entity)
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
+        Ok(AddKeyReport {
+            documents: data.len(),
+            conflicts: data
+                .iter()
+                .filter(|value| add_key::has_conflict(value, key))
+                .count(),
+        })
+    }
+
     pub fn add_key(
         &mut self,
         entity: &Entity,
         key: &str,
         default_value: Value,
-    ) -> Result<(), Error> {
+        strategy: AddKeyStrategy,
+    ) -> Result<AddKeyReport, Error> {
+        let report = self.add_key_report(entity, key)?;
+        if strategy == AddKeyStrategy::Fail && report.conflicts > 0 {
+            return Err(Error::msg(format!(
+                "Can not add key `{}`, {} documents already have a value in the way",
+                key, report.conflicts
+            )));
+        }
         let instance = self
             .get_instance_by_entity_mut(entity)
-            .ok_or_else(|| Error::msg("Entity not found"))?;
-        let data = instance
-            .data
-            .get_mut(&entity.name)
-            .ok_or_else(|| Error::msg("Data not found"))?;
+            .ok_or_else(|| DeebError::new(ErrorKind::EntityNotFound).entity(entity))?;
+        let data = instance.data.get_mut(&entity.name).ok_or_else(|| {
+            DeebError::new(ErrorKind::DataNotFound)
+                .entity(entity)
+                .instance(&instance.name)
+                .file_path(&instance.file_path)
+        })?;
         for current in data.iter_mut() {
-            let keys = key.split('.').collect::<Vec<&str>>();
-            let mut json = json!({});
-            let mut current = current;
-            for key in keys.iter().take(keys.len() - 1) {
-                json.as_object_mut()
-                    .unwrap()
-                    .insert(key.to_string(), json!({}));
-                let has_key = current.as_object().unwrap().contains_key(*key);
-                if !has_key {
-                    current
-                        .as_object_mut()
-                        .unwrap()
-                        .insert(key.to_string(), json!({}));
-                }
-                current = current.get_mut(*key).unwrap();
+            if strategy == AddKeyStrategy::Skip && add_key::has_conflict(current, key) {
+                continue;
             }
-            let key = keys.last().unwrap().to_owned();
-            current
-                .as_object_mut()
-                .unwrap()
-                .insert(key.to_string(), default_value.clone());
+            add_key::set_key(current, key, default_value.clone());
         }
-        Ok(())
+        Ok(report)
     }
 }
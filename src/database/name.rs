@@ -6,3 +6,9 @@ impl From<&str> for Name {
         Self(s.to_string())
     }
 }
+
+impl std::fmt::Display for Name {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
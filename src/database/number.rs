@@ -0,0 +1,96 @@
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+/// Compare two JSON values as numbers, or get `None` if either is not a number.
+///
+/// Integers are compared exactly, so large ids and amounts keep their order even where
+/// `f64` can not tell them apart. Other numbers are compared by their decimal text, which
+/// is also exact for the arbitrary precision numbers of the `arbitrary_precision` feature.
+pub fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        _ => None,
+    }
+}
+
+pub fn compare_numbers(a: &Number, b: &Number) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        return Some(a.cmp(&b));
+    }
+    if let (Some(a), Some(b)) = (a.as_u64(), b.as_u64()) {
+        return Some(a.cmp(&b));
+    }
+    let a = Decimal::parse(&a.to_string())?;
+    let b = Decimal::parse(&b.to_string())?;
+    Some(a.cmp(&b))
+}
+
+/// A decimal number as `0.digits * 10^exponent`, with no leading or trailing zero digits.
+#[derive(Debug, PartialEq, Eq)]
+struct Decimal {
+    negative: bool,
+    digits: Vec<u8>,
+    exponent: i64,
+}
+
+impl Decimal {
+    fn parse(text: &str) -> Option<Self> {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text),
+        };
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(index) => (&text[..index], text[index + 1..].parse::<i64>().ok()?),
+            None => (text, 0),
+        };
+        let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits = integer
+            .bytes()
+            .chain(fraction.bytes())
+            .map(|digit| digit.is_ascii_digit().then(|| digit - b'0'))
+            .collect::<Option<Vec<u8>>>()?;
+        let leading = digits.iter().take_while(|digit| **digit == 0).count();
+        let exponent = exponent
+            .checked_add(integer.len() as i64)?
+            .checked_sub(leading as i64)?;
+        let mut digits = digits[leading..].to_vec();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Some(Self {
+            // Negative zero is zero.
+            negative: negative && !digits.is_empty(),
+            digits,
+            exponent,
+        })
+    }
+
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        match (self.digits.is_empty(), other.digits.is_empty()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => self
+                .exponent
+                .cmp(&other.exponent)
+                .then_with(|| self.digits.cmp(&other.digits)),
+        }
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
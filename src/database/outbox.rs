@@ -0,0 +1,86 @@
+use anyhow::Error;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+use super::entity::EntityName;
+
+/// The entity of an instance holding its outbox messages.
+pub const OUTBOX_ENTITY: &str = "_outbox";
+
+/// A message in the `_outbox` entity of an instance, written with the domain change it
+/// describes so both commit together.
+///
+/// Messages are delivered at least once. Polling claims a message until its visibility
+/// timeout passes, and a claimed message that is not acknowledged in time is delivered
+/// again, so consumers should handle duplicates, for example by the message id.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub id: String,
+    /// The entity the message was published for.
+    pub entity: EntityName,
+    pub topic: String,
+    pub payload: Value,
+    /// When the message was published, in milliseconds since the Unix epoch.
+    pub created_at: i64,
+    /// How many times the message has been claimed by a poll.
+    pub attempts: u32,
+    /// When the current claim expires, in milliseconds since the Unix epoch.
+    pub claimed_until: Option<i64>,
+}
+
+impl OutboxMessage {
+    pub fn new(entity: &EntityName, topic: &str, payload: Value) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            entity: entity.clone(),
+            topic: topic.to_string(),
+            payload,
+            created_at: Utc::now().timestamp_millis(),
+            attempts: 0,
+            claimed_until: None,
+        }
+    }
+}
+
+/// Claim up to `limit` messages that are not claimed, or whose claim expired, in the
+/// order they were published.
+pub fn claim(
+    data: &mut im::Vector<Value>,
+    limit: usize,
+    visibility: Duration,
+) -> Result<Vec<OutboxMessage>, Error> {
+    let now = Utc::now().timestamp_millis();
+    let claimed_until =
+        now.saturating_add(i64::try_from(visibility.as_millis()).unwrap_or(i64::MAX));
+    let mut claimed = vec![];
+    for value in data.iter_mut() {
+        if claimed.len() >= limit {
+            break;
+        }
+        let mut message = serde_json::from_value::<OutboxMessage>(value.clone())?;
+        if message
+            .claimed_until
+            .is_some_and(|claimed_until| claimed_until > now)
+        {
+            continue;
+        }
+        message.attempts += 1;
+        message.claimed_until = Some(claimed_until);
+        *value = serde_json::to_value(&message)?;
+        claimed.push(message);
+    }
+    Ok(claimed)
+}
+
+/// Remove the messages with the ids, returning how many were removed.
+pub fn remove(data: &mut im::Vector<Value>, ids: &[String]) -> usize {
+    let before = data.len();
+    data.retain(|value| {
+        !value["id"]
+            .as_str()
+            .is_some_and(|id| ids.iter().any(|acked| acked == id))
+    });
+    before - data.len()
+}
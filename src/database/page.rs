@@ -0,0 +1,114 @@
+use anyhow::Error;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use super::entity::Entity;
+use super::find_many_options::{compare_documents, FindManyOrder, OrderDirection};
+use super::path;
+
+/// A request for one page of [Deeb::find_page](crate::Deeb::find_page).
+///
+/// Start without a cursor and pass the `next_cursor` of each [Page] to get the next one.
+/// Pages are ordered by `order` and then by the entity's primary key, and a cursor resumes
+/// after the last document of its page, so writes between requests do not skip or repeat
+/// documents. Without a primary key, order by fields that are unique together.
+///
+/// ```
+/// use deeb::*;
+///
+/// let request = PageRequest::new(20).order(FindManyOrder::new("name", OrderDirection::Ascending));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRequest {
+    pub limit: usize,
+    /// The `next_cursor` of the previous page.
+    pub cursor: Option<String>,
+    pub order: Option<Vec<FindManyOrder>>,
+}
+
+impl PageRequest {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    pub fn cursor(mut self, cursor: &str) -> Self {
+        self.cursor = Some(cursor.to_string());
+        self
+    }
+
+    /// Sort by an order. Orders are applied in the order they are added.
+    pub fn order(mut self, order: FindManyOrder) -> Self {
+        self.order.get_or_insert_with(Vec::new).push(order);
+        self
+    }
+}
+
+/// The orders a page is sorted by: the requested ones, then the primary key as a tie
+/// breaker.
+pub(crate) fn page_order(
+    entity: &Entity,
+    order: Option<Vec<FindManyOrder>>,
+) -> Result<Vec<FindManyOrder>, Error> {
+    let mut order = order.unwrap_or_default();
+    if let Some(primary_key) = &entity.primary_key {
+        if !order.iter().any(|order| order.property == *primary_key) {
+            order.push(FindManyOrder::new(primary_key, OrderDirection::Ascending));
+        }
+    }
+    if order.is_empty() {
+        return Err(Error::msg(format!(
+            "Paging `{}` needs an order or a primary key",
+            entity.name
+        )));
+    }
+    Ok(order)
+}
+
+/// Encode the ordered values of the last document of a page as the cursor of the next.
+pub(crate) fn encode_cursor(document: &Value, order: &[FindManyOrder]) -> String {
+    let values = order
+        .iter()
+        .map(|order| {
+            path::get(document, &order.property)
+                .cloned()
+                .unwrap_or(Value::Null)
+        })
+        .collect::<Vec<_>>();
+    URL_SAFE_NO_PAD.encode(Value::Array(values).to_string())
+}
+
+/// The position of the first document sorted after the cursor.
+pub(crate) fn cursor_position(
+    cursor: &str,
+    documents: &[Value],
+    order: &[FindManyOrder],
+) -> Result<usize, Error> {
+    let invalid = || Error::msg(format!("Invalid page cursor `{}`", cursor));
+    let decoded = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid())?;
+    let values = serde_json::from_slice::<Vec<Value>>(&decoded).map_err(|_| invalid())?;
+    if values.len() != order.len() {
+        return Err(invalid());
+    }
+    let mut after = Value::Object(Default::default());
+    for (order, value) in order.iter().zip(values) {
+        path::set(&mut after, &path::parse(&order.property), value);
+    }
+    Ok(documents.partition_point(|document| {
+        compare_documents(document, &after, order) != Ordering::Greater
+    }))
+}
+
+/// A page of documents with the total number of matching documents, counted from the same
+/// snapshot as the items.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: usize,
+    /// The cursor of the next page, or `None` on the last page. Treat it as opaque.
+    pub next_cursor: Option<String>,
+}
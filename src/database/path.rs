@@ -0,0 +1,229 @@
+use serde_json::{Map, Value};
+
+/// A segment of a dot path such as `items.0.sku`, `items[0].sku`, or `attributes.*.value`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    /// A field of an object. On an array, the field of every element.
+    Key(String),
+    /// An element of an array, or the field of the same name on an object. The text is
+    /// kept as written, so `007` is the key `007` on an object and index 7 on an array.
+    Index(usize, String),
+    /// Every value of an object or element of an array, written `*` or `[*]`.
+    Wildcard,
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Segment::Key(key) => write!(f, "{}", key),
+            Segment::Index(_, key) => write!(f, "{}", key),
+            Segment::Wildcard => write!(f, "*"),
+        }
+    }
+}
+
+/// Parse a dot path into segments. Brackets may follow a key, as in `items[0]` or
+/// `items[*]`, so `items[*].sizes.0` and `items.*.sizes[0]` are the same path.
+pub fn parse(path: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    for part in path.split('.') {
+        let (key, mut brackets) = match part.find('[') {
+            Some(start) if part.ends_with(']') => (&part[..start], &part[start..]),
+            _ => (part, ""),
+        };
+        if !key.is_empty() || brackets.is_empty() {
+            segments.push(segment(key));
+        }
+        while let Some(rest) = brackets.strip_prefix('[') {
+            let Some(end) = rest.find(']') else {
+                break;
+            };
+            segments.push(segment(&rest[..end]));
+            brackets = &rest[end + 1..];
+        }
+    }
+    segments
+}
+
+fn segment(part: &str) -> Segment {
+    if part == "*" {
+        return Segment::Wildcard;
+    }
+    match part.parse::<usize>() {
+        Ok(index) if !part.starts_with('+') => Segment::Index(index, part.to_string()),
+        _ => Segment::Key(part.to_string()),
+    }
+}
+
+/// Check if a path is only keys, so it needs none of the wildcard or index handling.
+pub fn is_plain(segments: &[Segment]) -> bool {
+    segments
+        .iter()
+        .all(|segment| matches!(segment, Segment::Key(_)))
+}
+
+fn child<'a>(value: &'a Value, segment: &Segment) -> Option<&'a Value> {
+    match (value, segment) {
+        (Value::Object(object), Segment::Key(key)) => object.get(key),
+        (Value::Object(object), Segment::Index(_, key)) => object.get(key),
+        (Value::Array(values), Segment::Index(index, _)) => values.get(*index),
+        _ => None,
+    }
+}
+
+fn child_mut<'a>(value: &'a mut Value, segment: &Segment) -> Option<&'a mut Value> {
+    match (value, segment) {
+        (Value::Object(object), Segment::Key(key)) => object.get_mut(key),
+        (Value::Object(object), Segment::Index(_, key)) => object.get_mut(key),
+        (Value::Array(values), Segment::Index(index, _)) => values.get_mut(*index),
+        _ => None,
+    }
+}
+
+/// Get the single value at a path. Wildcards never match here.
+pub fn get<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    parse(path)
+        .iter()
+        .try_fold(value, |value, segment| child(value, segment))
+}
+
+/// Get the single value at a path mutably. Wildcards never match here.
+pub fn get_mut<'a>(value: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    parse(path)
+        .iter()
+        .try_fold(value, |value, segment| child_mut(value, segment))
+}
+
+/// Collect every value a path reaches. Keys are looked up on each element of an array,
+/// and arrays at the end of the path are flattened so each element is its own value.
+pub fn collect(value: &Value, segments: &[Segment], values: &mut Vec<Value>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        match value {
+            Value::Array(elements) => values.extend(elements.iter().cloned()),
+            value => values.push(value.clone()),
+        }
+        return;
+    };
+    match (value, segment) {
+        (Value::Object(object), Segment::Wildcard) => object
+            .values()
+            .for_each(|value| collect(value, rest, values)),
+        (Value::Array(elements), Segment::Wildcard) => elements
+            .iter()
+            .for_each(|value| collect(value, rest, values)),
+        (Value::Array(elements), Segment::Key(_)) => elements
+            .iter()
+            .for_each(|value| collect(value, segments, values)),
+        (value, segment) => {
+            if let Some(value) = child(value, segment) {
+                collect(value, rest, values);
+            }
+        }
+    }
+}
+
+/// Check if setting the path would replace an existing value, or a value on the way is
+/// not an object.
+pub fn has_conflict(value: &Value, segments: &[Segment]) -> bool {
+    let Some((segment, rest)) = segments.split_first() else {
+        return true;
+    };
+    match (value, segment) {
+        (Value::Object(object), Segment::Wildcard) => {
+            object.values().any(|value| has_conflict(value, rest))
+        }
+        (Value::Array(elements), Segment::Wildcard) => {
+            elements.iter().any(|value| has_conflict(value, rest))
+        }
+        (Value::Array(elements), Segment::Key(_)) => {
+            elements.iter().any(|value| has_conflict(value, segments))
+        }
+        // An index past the end of an array can not be set.
+        (Value::Array(elements), Segment::Index(index, _)) => elements
+            .get(*index)
+            .is_none_or(|value| has_conflict(value, rest)),
+        (Value::Object(_), segment) => {
+            child(value, segment).is_some_and(|value| has_conflict(value, rest))
+        }
+        _ => true,
+    }
+}
+
+/// Set the path, replacing any value in the way with an object. Keys and wildcards set
+/// every element of an array they reach, and indices past the end of an array are left
+/// alone.
+pub fn set(value: &mut Value, segments: &[Segment], new_value: Value) {
+    let Some((segment, rest)) = segments.split_first() else {
+        *value = new_value;
+        return;
+    };
+    match (value, segment) {
+        (Value::Object(object), Segment::Wildcard) => object
+            .values_mut()
+            .for_each(|value| set(value, rest, new_value.clone())),
+        (Value::Array(elements), Segment::Wildcard) => elements
+            .iter_mut()
+            .for_each(|value| set(value, rest, new_value.clone())),
+        (Value::Array(elements), Segment::Index(index, _)) => {
+            if let Some(value) = elements.get_mut(*index) {
+                set(value, rest, new_value);
+            }
+        }
+        (Value::Array(elements), Segment::Key(_)) => elements
+            .iter_mut()
+            .for_each(|value| set(value, segments, new_value.clone())),
+        (_, Segment::Wildcard) => {}
+        (value, segment) => {
+            if !value.is_object() {
+                *value = Value::Object(Map::new());
+            }
+            let child = value
+                .as_object_mut()
+                .unwrap()
+                .entry(segment.to_string())
+                .or_insert(Value::Null);
+            set(child, rest, new_value);
+        }
+    }
+}
+
+/// Remove the value at the path. Keys are removed from each element of an array, and
+/// wildcards remove from every value they reach.
+pub fn remove(value: &mut Value, segments: &[Segment]) {
+    let Some((segment, rest)) = segments.split_first() else {
+        return;
+    };
+    if rest.is_empty() {
+        match (value, segment) {
+            (Value::Object(object), Segment::Wildcard) => object.clear(),
+            (Value::Array(elements), Segment::Wildcard) => elements.clear(),
+            (Value::Array(elements), Segment::Index(index, _)) if *index < elements.len() => {
+                elements.remove(*index);
+            }
+            (Value::Array(elements), Segment::Key(_)) => elements
+                .iter_mut()
+                .for_each(|value| remove(value, segments)),
+            (Value::Object(object), segment) => {
+                object.remove(&segment.to_string());
+            }
+            _ => {}
+        }
+        return;
+    }
+    match (value, segment) {
+        (Value::Object(object), Segment::Wildcard) => {
+            object.values_mut().for_each(|value| remove(value, rest))
+        }
+        (Value::Array(elements), Segment::Wildcard) => {
+            elements.iter_mut().for_each(|value| remove(value, rest))
+        }
+        (Value::Array(elements), Segment::Key(_)) => elements
+            .iter_mut()
+            .for_each(|value| remove(value, segments)),
+        (value, segment) => {
+            if let Some(value) = child_mut(value, segment) {
+                remove(value, rest);
+            }
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use anyhow::Error;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use super::query::Query;
+
+const PARAM_KEY: &str = "$param";
+
+/// A named placeholder for the value of an `eq`, `ne`, `lt`, `lte`, `gt`, or `gte` query,
+/// bound when a [PreparedQuery] is executed.
+pub fn param(name: &str) -> Value {
+    json!({ PARAM_KEY: name })
+}
+
+fn param_name(value: &Value) -> Option<&str> {
+    let object = value.as_object()?;
+    if object.len() != 1 {
+        return None;
+    }
+    object.get(PARAM_KEY)?.as_str()
+}
+
+/// A query with placeholders that is checked once and bound to new values for each
+/// execution, for queries run over and over with different values.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+///
+/// let by_name_and_age = PreparedQuery::new(Query::and(vec![
+///     Query::eq("name", param("name")),
+///     Query::gte("age", param("age")),
+/// ]));
+/// let query = by_name_and_age.bind([("name", json!("Joey")), ("age", json!(10))]).unwrap();
+/// assert!(query.matches(&json!({"name": "Joey", "age": 12})).unwrap());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedQuery {
+    query: Query,
+    params: Vec<String>,
+}
+
+impl PreparedQuery {
+    pub fn new(query: Query) -> Self {
+        let mut params = vec![];
+        visit_values(&query, &mut |value| {
+            if let Some(name) = param_name(value) {
+                if !params.iter().any(|param| param == name) {
+                    params.push(name.to_string());
+                }
+            }
+        });
+        Self { query, params }
+    }
+
+    /// The names of the placeholders, in the order they first appear.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// Get the query with every placeholder replaced by its value. Every placeholder must
+    /// be bound, and every value must belong to a placeholder.
+    pub fn bind<'a, I, V>(&self, values: I) -> Result<Query, Error>
+    where
+        I: IntoIterator<Item = (&'a str, V)>,
+        V: Into<Value>,
+    {
+        let values = values
+            .into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect::<HashMap<_, _>>();
+        if let Some(name) = values
+            .keys()
+            .find(|name| !self.params.iter().any(|param| param == *name))
+        {
+            return Err(Error::msg(format!("Unknown query parameter `{}`", name)));
+        }
+        if let Some(param) = self
+            .params
+            .iter()
+            .find(|param| !values.contains_key(param.as_str()))
+        {
+            return Err(Error::msg(format!("Missing query parameter `{}`", param)));
+        }
+        let mut query = self.query.clone();
+        if !self.params.is_empty() {
+            visit_values_mut(&mut query, &mut |value| {
+                if let Some(bound) = param_name(value).and_then(|name| values.get(name)) {
+                    *value = bound.clone();
+                }
+            });
+        }
+        Ok(query)
+    }
+}
+
+fn visit_values<F>(query: &Query, visit: &mut F)
+where
+    F: FnMut(&Value),
+{
+    match query {
+        Query::Eq(_, value)
+        | Query::Ne(_, value)
+        | Query::Lt(_, value)
+        | Query::Lte(_, value)
+        | Query::Gt(_, value)
+        | Query::Gte(_, value) => visit(value),
+        Query::And(queries) | Query::Or(queries) => {
+            queries.iter().for_each(|query| visit_values(query, visit))
+        }
+        Query::Associated(_, query) => visit_values(query, visit),
+        Query::Like(..) | Query::LikeWith(..) | Query::HasKey(..) | Query::All => {}
+    }
+}
+
+fn visit_values_mut<F>(query: &mut Query, visit: &mut F)
+where
+    F: FnMut(&mut Value),
+{
+    match query {
+        Query::Eq(_, value)
+        | Query::Ne(_, value)
+        | Query::Lt(_, value)
+        | Query::Lte(_, value)
+        | Query::Gt(_, value)
+        | Query::Gte(_, value) => visit(value),
+        Query::And(queries) | Query::Or(queries) => queries
+            .iter_mut()
+            .for_each(|query| visit_values_mut(query, visit)),
+        Query::Associated(_, query) => visit_values_mut(query, visit),
+        Query::Like(..) | Query::LikeWith(..) | Query::HasKey(..) | Query::All => {}
+    }
+}
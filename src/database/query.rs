@@ -1,8 +1,12 @@
-use serde_json::Value;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::cmp::Ordering;
+
+use super::{number, path};
 
 use crate::Entity;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Key(String);
 
 impl std::fmt::Display for Key {
@@ -17,11 +21,137 @@ impl From<&str> for Key {
     }
 }
 
-#[derive(Debug, Clone, PartialEq)]
+/// How a [Query::LikeWith] pattern is compared against a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LikeMode {
+    /// The value contains the pattern. This is the behavior of [Query::Like].
+    #[default]
+    Contains,
+    /// The value starts with the pattern.
+    StartsWith,
+    /// The value ends with the pattern.
+    EndsWith,
+    /// The whole value matches a SQL style pattern, where `%` matches any number of
+    /// characters and `_` matches a single character. Use `\` to escape `%`, `_` or `\`.
+    Wildcard,
+}
+
+/// Options for [Query::like_with].
+///
+/// ```
+/// use deeb::*;
+/// let options = LikeOptions::new().case_insensitive(true).mode(LikeMode::StartsWith);
+/// let query = Query::like_with("name", "jo", options);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LikeOptions {
+    pub case_insensitive: bool,
+    pub mode: LikeMode,
+}
+
+impl LikeOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn case_insensitive(mut self, case_insensitive: bool) -> Self {
+        self.case_insensitive = case_insensitive;
+        self
+    }
+
+    pub fn mode(mut self, mode: LikeMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Check if the value matches the pattern with these options.
+    ///
+    /// ```
+    /// use deeb::*;
+    /// let options = LikeOptions::new().mode(LikeMode::Wildcard);
+    /// assert!(options.is_match("Joey", "J_e%"));
+    /// assert!(!options.is_match("Joey", "J_e"));
+    /// assert!(options.is_match("100%", "100\\%"));
+    /// ```
+    pub fn is_match(&self, value: &str, pattern: &str) -> bool {
+        let (value, pattern) = if self.case_insensitive {
+            (value.to_lowercase(), pattern.to_lowercase())
+        } else {
+            (value.to_string(), pattern.to_string())
+        };
+        match self.mode {
+            LikeMode::Contains => value.contains(&pattern),
+            LikeMode::StartsWith => value.starts_with(&pattern),
+            LikeMode::EndsWith => value.ends_with(&pattern),
+            LikeMode::Wildcard => {
+                let value = value.chars().collect::<Vec<_>>();
+                wildcard_match(&value, &parse_wildcard(&pattern))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum WildcardToken {
+    Literal(char),
+    AnyOne,
+    AnyMany,
+}
+
+fn parse_wildcard(pattern: &str) -> Vec<WildcardToken> {
+    let mut tokens = vec![];
+    let mut chars = pattern.chars();
+    while let Some(character) = chars.next() {
+        let token = match character {
+            '\\' => WildcardToken::Literal(chars.next().unwrap_or('\\')),
+            '%' => WildcardToken::AnyMany,
+            '_' => WildcardToken::AnyOne,
+            character => WildcardToken::Literal(character),
+        };
+        tokens.push(token);
+    }
+    tokens
+}
+
+fn wildcard_match(value: &[char], tokens: &[WildcardToken]) -> bool {
+    // matched[i] is true when the tokens so far match the first i characters.
+    let mut matched = vec![false; value.len() + 1];
+    matched[0] = true;
+    for token in tokens {
+        let mut next = vec![false; value.len() + 1];
+        for i in 0..=value.len() {
+            next[i] = match token {
+                WildcardToken::AnyMany => matched[i] || (i > 0 && next[i - 1]),
+                WildcardToken::AnyOne => i > 0 && matched[i - 1],
+                WildcardToken::Literal(character) => {
+                    i > 0 && matched[i - 1] && value[i - 1] == *character
+                }
+            };
+        }
+        matched = next;
+    }
+    matched[value.len()]
+}
+
+/// A query used to match documents.
+///
+/// Queries serialize to JSON, allowing them to be built outside of Rust.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+/// let query: Query = serde_json::from_value(json!({"Eq": ["name", "John"]})).unwrap();
+/// assert_eq!(query, Query::eq("name", "John"));
+/// ```
+// Boxing the entity of `Associated` would break code matching on the variant.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Query {
     Eq(Key, Value),
     Ne(Key, Value),
     Like(Key, String),
+    LikeWith(Key, String, LikeOptions),
     Lt(Key, Value),
     Lte(Key, Value),
     Gt(Key, Value),
@@ -29,6 +159,7 @@ pub enum Query {
     And(Vec<Query>),
     Or(Vec<Query>),
     Associated(Entity, Box<Query>),
+    HasKey(Key, String),
     All,
 }
 
@@ -111,6 +242,42 @@ impl Query {
         Self::Like(key.into(), value.into())
     }
 
+    /// Create a new query that matches documents based on like match with options for
+    /// case insensitive matching, anchors, and `%` / `_` wildcards.
+    ///
+    /// ```
+    /// use deeb::*;
+    /// use serde_json::json;
+    /// let query = Query::like_with("name", "jo%", LikeOptions::new().case_insensitive(true).mode(LikeMode::Wildcard));
+    /// assert!(query.matches(&json!({"name": "Joey"})).unwrap());
+    /// ```
+    #[allow(dead_code)]
+    pub fn like_with<K, V>(key: K, value: V, options: LikeOptions) -> Self
+    where
+        K: Into<Key>,
+        V: Into<String>,
+    {
+        Self::LikeWith(key.into(), value.into(), options)
+    }
+
+    /// Create a new query that matches documents where the field is an object with the
+    /// key, for maps with dynamic keys.
+    ///
+    /// ```
+    /// use deeb::*;
+    /// use serde_json::json;
+    /// let query = Query::has_key("attributes", "color");
+    /// assert!(query.matches(&json!({"attributes": {"color": {"value": "red"}}})).unwrap());
+    /// assert!(!query.matches(&json!({"attributes": {"size": {"value": "L"}}})).unwrap());
+    /// ```
+    #[allow(dead_code)]
+    pub fn has_key<K>(key: K, name: &str) -> Self
+    where
+        K: Into<Key>,
+    {
+        Self::HasKey(key.into(), name.to_string())
+    }
+
     /// Create a new query that matches documents based on less than match.
     ///
     /// ```
@@ -194,28 +361,180 @@ impl Query {
     }
 
     fn get_kv(&self, value: &Value, key: &str) -> Option<(Key, Value)> {
-        if !key.contains('.') {
-            let value = value.get(key);
-            if value.is_none() {
+        let segments = path::parse(key);
+        if !path::is_plain(&segments) {
+            let mut values = vec![];
+            path::collect(value, &segments, &mut values);
+            if values.is_empty() {
                 return None;
             }
-            return Some((Key(key.to_string()), value.cloned().unwrap()));
+            let last = segments.last().map(ToString::to_string).unwrap_or_default();
+            return Some((Key(last), Value::Array(values)));
         }
-        let mut keys = key.split('.').peekable();
+        // Stop at the first array or scalar on the way, and return it with the key to look
+        // for inside it.
         let mut value = value;
         let mut current_key = None;
-        while let Some(key) = keys.next() {
-            current_key = Some(key.to_string());
+        for segment in segments.iter() {
+            current_key = Some(segment.to_string());
             if !value.is_object() {
                 break;
             }
-            if let Some(nested) = value.get(key) {
-                value = nested;
+            value = value.get(segment.to_string())?;
+        }
+        Some((Key(current_key?), value.clone()))
+    }
+
+    fn like_matches<F>(&self, value: &Value, key: &Key, is_match: F) -> bool
+    where
+        F: Fn(&str) -> bool,
+    {
+        let kv = self.get_kv(value, &key.0);
+        if let Some((key, value)) = kv {
+            if value.is_array() {
+                let value = value.as_array().unwrap();
+                for v in value {
+                    if v.is_object() {
+                        let v = v.as_object().unwrap();
+                        for (k, v) in v.iter() {
+                            if let Some(value) = v.as_str() {
+                                if is_match(value) && k == &key.to_string() {
+                                    return true;
+                                }
+                            }
+                        }
+                    }
+                    if let Some(value) = v.as_str() {
+                        if is_match(value) {
+                            return true;
+                        }
+                    }
+                }
+                return false;
+            }
+            if let Some(value) = value.as_str() {
+                is_match(value)
             } else {
-                return None;
+                false
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Compare the value at the key with the query value as numbers. Arrays match when any
+    /// element, or the field of the same name in an object element, matches.
+    fn compare_matches<F>(&self, value: &Value, key: &Key, query_value: &Value, is_match: F) -> bool
+    where
+        F: Fn(Ordering) -> bool,
+    {
+        let matches = |value: &Value| number::compare(value, query_value).is_some_and(&is_match);
+        let kv = self.get_kv(value, &key.0);
+        if let Some((key, value)) = kv {
+            if let Some(value) = value.as_array() {
+                return value.iter().any(|v| match v.as_object() {
+                    Some(v) => v.iter().any(|(k, v)| k == &key.0 && matches(v)),
+                    None => matches(v),
+                });
+            }
+            matches(&value)
+        } else {
+            false
+        }
+    }
+
+    /// The shape of the query, with values replaced by `?`. Queries with the same shape
+    /// differ only by the values they match.
+    ///
+    /// ```
+    /// use deeb::*;
+    /// use serde_json::json;
+    /// let query = Query::and(vec![Query::eq("name", "John"), Query::gt("age", 30)]);
+    /// assert_eq!(query.shape(), json!({"And": [{"Eq": ["name", "?"]}, {"Gt": ["age", "?"]}]}));
+    /// ```
+    pub fn shape(&self) -> Value {
+        let (variant, shape) = match self {
+            Self::Eq(key, _) => ("Eq", json!([key.0, "?"])),
+            Self::Ne(key, _) => ("Ne", json!([key.0, "?"])),
+            Self::Like(key, _) => ("Like", json!([key.0, "?"])),
+            Self::LikeWith(key, _, options) => ("LikeWith", json!([key.0, "?", options])),
+            Self::Lt(key, _) => ("Lt", json!([key.0, "?"])),
+            Self::Lte(key, _) => ("Lte", json!([key.0, "?"])),
+            Self::Gt(key, _) => ("Gt", json!([key.0, "?"])),
+            Self::Gte(key, _) => ("Gte", json!([key.0, "?"])),
+            Self::And(queries) => ("And", queries.iter().map(Query::shape).collect()),
+            Self::Or(queries) => ("Or", queries.iter().map(Query::shape).collect()),
+            Self::Associated(entity, query) => (
+                "Associated",
+                json!([entity.name.to_string(), query.shape()]),
+            ),
+            Self::HasKey(key, name) => ("HasKey", json!([key.0, name])),
+            Self::All => return json!("All"),
+        };
+        json!({ variant: shape })
+    }
+
+    /// A rough estimate of how expensive the query is to check against a document, used by
+    /// [order_by_cost](Query::order_by_cost).
+    ///
+    /// ```
+    /// use deeb::*;
+    /// assert!(Query::eq("name", "John").cost() < Query::like("bio", "rust").cost());
+    /// ```
+    pub fn cost(&self) -> usize {
+        let path_cost = |key: &Key| match path::is_plain(&path::parse(&key.0)) {
+            true => 0,
+            false => 2,
+        };
+        match self {
+            Self::All => 0,
+            Self::Eq(key, _) | Self::Ne(key, _) => 1 + path_cost(key),
+            Self::HasKey(key, _) => 1 + path_cost(key),
+            Self::Lt(key, _) | Self::Lte(key, _) | Self::Gt(key, _) | Self::Gte(key, _) => {
+                2 + path_cost(key)
+            }
+            Self::Like(key, _) => 3 + path_cost(key),
+            Self::LikeWith(key, _, _) => 4 + path_cost(key),
+            Self::And(queries) | Self::Or(queries) => {
+                1 + queries.iter().map(Query::cost).sum::<usize>()
+            }
+            // Associated documents are arrays, so every one of them may be checked.
+            Self::Associated(_, query) => 4 * query.cost(),
+        }
+    }
+
+    /// Reorder the queries of every `And` and `Or` cheapest first, so matching can stop
+    /// before reaching the expensive ones. Queries of equal cost keep their written order.
+    ///
+    /// `And` and `Or` check their queries in the order they are written, so call this once
+    /// when building a query unless the written order is already the best one.
+    ///
+    /// ```
+    /// use deeb::*;
+    /// let query = Query::or(vec![Query::like("bio", "rust"), Query::eq("name", "John")]);
+    /// assert_eq!(
+    ///     query.order_by_cost(),
+    ///     Query::or(vec![Query::eq("name", "John"), Query::like("bio", "rust")])
+    /// );
+    /// ```
+    pub fn order_by_cost(self) -> Self {
+        match self {
+            Self::And(queries) => Self::And(Self::sort_by_cost(queries)),
+            Self::Or(queries) => Self::Or(Self::sort_by_cost(queries)),
+            Self::Associated(entity, query) => {
+                Self::Associated(entity, Box::new(query.order_by_cost()))
             }
+            query => query,
         }
-        Some((Key(current_key.unwrap()), value.clone()))
+    }
+
+    fn sort_by_cost(queries: Vec<Query>) -> Vec<Query> {
+        let mut queries = queries
+            .into_iter()
+            .map(Query::order_by_cost)
+            .collect::<Vec<_>>();
+        queries.sort_by_cached_key(Query::cost);
+        queries
     }
 
     pub fn associated_entities(&self) -> Vec<Entity> {
@@ -303,293 +622,45 @@ impl Query {
                 }
             }
             Self::Like(key, query_value) => {
-                let kv = self.get_kv(value, &key.0);
-                if let Some((key, value)) = kv {
-                    if value.is_array() {
-                        let value = value.as_array().unwrap();
-                        for v in value {
-                            if v.is_object() {
-                                let v = v.as_object().unwrap();
-                                for (k, v) in v.iter() {
-                                    if let Some(value) = v.as_str() {
-                                        if value.contains(query_value) && k == &key.to_string() {
-                                            return Ok(true);
-                                        }
-                                    }
-                                }
-                            }
-                            if let Some(value) = v.as_str() {
-                                if value.contains(query_value) {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        return Ok(false);
-                    }
-                    if let Some(value) = value.as_str() {
-                        value.contains(query_value)
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
+                self.like_matches(value, key, |value| value.contains(query_value))
+            }
+            Self::LikeWith(key, query_value, options) => {
+                self.like_matches(value, key, |value| options.is_match(value, query_value))
             }
             Self::Lt(key, query_value) => {
-                let kv = self.get_kv(value, &key.0);
-                if let Some((key, value)) = kv {
-                    // Handle Array
-                    if value.is_array() {
-                        let value = value.as_array().unwrap();
-                        for v in value {
-                            if v.is_object() {
-                                let v = v.as_object().unwrap();
-                                for (k, v) in v.iter() {
-                                    if let Some(value) = v.as_f64() {
-                                        let query_value = query_value.as_f64();
-                                        if query_value.is_none() {
-                                            continue;
-                                        }
-                                        let is_lt = value < query_value.unwrap() && k == &key.0;
-                                        if is_lt {
-                                            return Ok(true);
-                                        }
-                                    }
-                                }
-                            }
-                            if let Some(value) = v.as_f64() {
-                                let query_value = query_value.as_f64();
-                                if query_value.is_none() {
-                                    continue;
-                                }
-                                let is_lt = value < query_value.unwrap();
-                                if is_lt {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        return Ok(false);
-                    }
-                    // Handle primitive types
-                    if let Some(value) = value.as_f64() {
-                        let query_value = query_value.as_f64();
-                        match query_value {
-                            Some(query_value) => value < query_value,
-                            None => false,
-                        }
-                    } else if let Some(value) = value.as_i64() {
-                        let query_value = query_value.as_i64();
-                        match query_value {
-                            Some(query_value) => value < query_value,
-                            None => false,
-                        }
-                    } else if let Some(value) = value.as_u64() {
-                        let query_value = query_value.as_u64();
-                        match query_value {
-                            Some(query_value) => value < query_value,
-                            None => false,
-                        }
-                    } else {
-                        false
-                    }
-                } else {
-                    false
-                }
+                self.compare_matches(value, key, query_value, |ordering| {
+                    ordering == Ordering::Less
+                })
             }
             Self::Lte(key, query_value) => {
-                let kv = self.get_kv(value, &key.0);
-                if let Some((key, value)) = kv {
-                    // Handle Array
-                    if value.is_array() {
-                        let value = value.as_array().unwrap();
-                        for v in value {
-                            if v.is_object() {
-                                let v = v.as_object().unwrap();
-                                for (k, v) in v.iter() {
-                                    if let Some(value) = v.as_f64() {
-                                        let query_value = query_value.as_f64();
-                                        if query_value.is_none() {
-                                            continue;
-                                        }
-                                        let is_lte = value <= query_value.unwrap() && k == &key.0;
-                                        if is_lte {
-                                            return Ok(true);
-                                        }
-                                    }
-                                }
-                            }
-                            if let Some(value) = v.as_f64() {
-                                let query_value = query_value.as_f64();
-                                if query_value.is_none() {
-                                    continue;
-                                }
-                                let is_lte = value <= query_value.unwrap();
-                                if is_lte {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        return Ok(false);
-                    }
-
-                    // Handle Primitivves
-                    if let Some(value) = value.as_f64() {
-                        let query_value = query_value.as_f64();
-                        match query_value {
-                            Some(query_value) => return Ok(value <= query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_i64() {
-                        let query_value = query_value.as_i64();
-                        match query_value {
-                            Some(query_value) => return Ok(value <= query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_u64() {
-                        let query_value = query_value.as_u64();
-                        match query_value {
-                            Some(query_value) => return Ok(value <= query_value),
-                            None => return Ok(false),
-                        }
-                    } else {
-                        return Ok(false);
-                    }
-                } else {
-                    return Ok(false);
-                }
+                self.compare_matches(value, key, query_value, |ordering| {
+                    ordering != Ordering::Greater
+                })
             }
             Self::Gt(key, query_value) => {
-                let kv = self.get_kv(value, &key.0);
-                if let Some((key, value)) = kv {
-                    // handle array
-                    if value.is_array() {
-                        let value = value.as_array().unwrap();
-                        for v in value {
-                            if v.is_object() {
-                                let v = v.as_object().unwrap();
-                                for (k, v) in v.iter() {
-                                    if let Some(value) = v.as_f64() {
-                                        let query_value = query_value.as_f64();
-                                        match query_value {
-                                            Some(query_value) => {
-                                                return Ok(value > query_value && k == &key.0)
-                                            }
-                                            None => return Ok(false),
-                                        };
-                                    }
-                                }
-                                return Ok(false);
-                            }
-                            if let Some(value) = v.as_f64() {
-                                let query_value = query_value.as_f64();
-                                let is_gt = value > query_value.unwrap();
-                                if is_gt {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        return Ok(false);
-                    }
-
-                    // handle primitives
-                    if let Some(value) = value.as_f64() {
-                        let query_value = query_value.as_f64();
-                        match query_value {
-                            Some(query_value) => return Ok(value > query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_i64() {
-                        let query_value = query_value.as_i64();
-                        match query_value {
-                            Some(query_value) => return Ok(value > query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_u64() {
-                        let query_value = query_value.as_u64();
-                        match query_value {
-                            Some(query_value) => return Ok(value > query_value),
-                            None => return Ok(false),
-                        }
-                    } else {
-                        return Ok(false);
-                    }
-                } else {
-                    return Ok(false);
-                }
+                self.compare_matches(value, key, query_value, |ordering| {
+                    ordering == Ordering::Greater
+                })
             }
             Self::Gte(key, query_value) => {
-                let kv = self.get_kv(value, &key.0);
-                if let Some((key, value)) = kv {
-                    // handle array
-                    if value.is_array() {
-                        let value = value.as_array().unwrap();
-                        for v in value {
-                            println!("V: {:?}", v);
-                            if v.is_object() {
-                                let v = v.as_object().unwrap();
-                                for (k, v) in v.iter() {
-                                    if let Some(value) = v.as_f64() {
-                                        let query_value = query_value.as_f64();
-                                        match query_value {
-                                            Some(query_value) => {
-                                                return Ok(value >= query_value && k == &key.0)
-                                            }
-                                            None => return Ok(false),
-                                        };
-                                    }
-                                }
-                                return Ok(false);
-                            }
-                            if let Some(value) = v.as_f64() {
-                                let query_value = query_value.as_f64();
-                                if query_value.is_none() {
-                                    continue;
-                                }
-                                let is_gte = value >= query_value.unwrap();
-                                if is_gte {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                        return Ok(false);
-                    }
-
-                    // handle primitives
-                    if let Some(value) = value.as_f64() {
-                        let query_value = query_value.as_f64();
-                        match query_value {
-                            Some(query_value) => return Ok(value >= query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_i64() {
-                        let query_value = query_value.as_i64();
-                        match query_value {
-                            Some(query_value) => return Ok(value >= query_value),
-                            None => return Ok(false),
-                        }
-                    } else if let Some(value) = value.as_u64() {
-                        let query_value = query_value.as_u64();
-                        match query_value {
-                            Some(query_value) => return Ok(value >= query_value),
-                            None => return Ok(false),
-                        }
-                    } else {
-                        return Ok(false);
-                    }
-                } else {
-                    return Ok(false);
-                }
+                self.compare_matches(value, key, query_value, |ordering| {
+                    ordering != Ordering::Less
+                })
             }
             Self::And(queries) => queries
                 .iter()
-                .all(|query| query.matches(value).unwrap_or_else(|_| false)),
+                .all(|query| query.matches(value).unwrap_or(false)),
             Self::Or(queries) => queries
                 .iter()
-                .any(|query| query.matches(value).unwrap_or_else(|_| false)),
-            Self::Associated(_entity, query) => {
-                let is_match = query.matches(value).unwrap_or_else(|_| false);
-                is_match
-            }
+                .any(|query| query.matches(value).unwrap_or(false)),
+            Self::Associated(_entity, query) => query.matches(value).unwrap_or(false),
+            Self::HasKey(key, name) => match self.get_kv(value, &key.0) {
+                Some((_key, Value::Object(object))) => object.contains_key(name),
+                Some((_key, Value::Array(values))) => values
+                    .iter()
+                    .any(|v| v.as_object().is_some_and(|v| v.contains_key(name))),
+                _ => false,
+            },
             Self::All => true,
         };
         Ok(is_match)
@@ -0,0 +1,32 @@
+use serde_json::Value;
+
+use super::entity::Entity;
+use super::path;
+
+fn remove_field(value: &mut Value, field: &str) {
+    path::remove(value, &path::parse(field));
+}
+
+/// Remove the redacted fields of the entity, and of any associated entities that have been
+/// joined onto the document, from a document.
+pub fn redact(entity: &Entity, associated_entities: &[Entity], value: &mut Value) {
+    for field in entity.redacted_fields.iter() {
+        remove_field(value, field);
+    }
+    for associated_entity in associated_entities.iter() {
+        let association = entity
+            .associations
+            .iter()
+            .find(|association| association.entity_name == associated_entity.name);
+        let Some(association) = association else {
+            continue;
+        };
+        if let Some(Value::Array(values)) = value.get_mut(association.alias.to_string()) {
+            for value in values.iter_mut() {
+                for field in associated_entity.redacted_fields.iter() {
+                    remove_field(value, field);
+                }
+            }
+        }
+    }
+}
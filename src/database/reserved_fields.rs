@@ -0,0 +1,136 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What happens when a write sets a reserved field.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReservedFieldCollision {
+    /// Fail the write.
+    #[default]
+    Reject,
+    /// Drop the reserved fields from inserted documents, and keep their stored value on
+    /// updates.
+    Strip,
+}
+
+/// Field names on an instance that documents may not set themselves, kept for metadata
+/// such as ids, timestamps, and versions.
+///
+/// Checked on every insert and update of the instance's entities, including JSON Patch
+/// updates. Only top level fields are checked.
+///
+/// ```
+/// use deeb::*;
+///
+/// let reserved_fields = ReservedFields::new()
+///     .prefix("_")
+///     .collision(ReservedFieldCollision::Strip);
+/// assert!(reserved_fields.is_reserved("_etag"));
+/// assert!(!reserved_fields.is_reserved("name"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReservedFields {
+    /// Reserved field names, `_id`, `_created_at`, `_version`, and `_deleted_at` by
+    /// default.
+    pub fields: Vec<String>,
+    /// Reserve every field starting with the prefix.
+    pub prefix: Option<String>,
+    pub collision: ReservedFieldCollision,
+}
+
+impl Default for ReservedFields {
+    fn default() -> Self {
+        Self {
+            fields: ["_id", "_created_at", "_version", "_deleted_at"]
+                .map(String::from)
+                .to_vec(),
+            prefix: None,
+            collision: ReservedFieldCollision::default(),
+        }
+    }
+}
+
+impl ReservedFields {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reserve another field name.
+    pub fn field(mut self, field: &str) -> Self {
+        self.fields.push(field.to_string());
+        self
+    }
+
+    /// Reserve every field starting with the prefix.
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn collision(mut self, collision: ReservedFieldCollision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn is_reserved(&self, field: &str) -> bool {
+        self.fields.iter().any(|reserved| reserved == field)
+            || self
+                .prefix
+                .as_deref()
+                .is_some_and(|prefix| field.starts_with(prefix))
+    }
+
+    /// Check a document about to be inserted.
+    pub(crate) fn check_insert(&self, value: &mut Value) -> Result<(), Error> {
+        let Some(object) = value.as_object_mut() else {
+            return Ok(());
+        };
+        let reserved = object
+            .keys()
+            .filter(|key| self.is_reserved(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        match (reserved.first(), self.collision) {
+            (None, _) => {}
+            (Some(key), ReservedFieldCollision::Reject) => {
+                return Err(Error::msg(format!("Field `{}` is reserved", key)));
+            }
+            (Some(_), ReservedFieldCollision::Strip) => {
+                for key in reserved {
+                    object.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Check an updated document against the stored one. Reserved fields may not be
+    /// added, changed, or removed.
+    pub(crate) fn check_update(&self, old: &Value, new: &mut Value) -> Result<(), Error> {
+        let (Some(old), Some(new)) = (old.as_object(), new.as_object_mut()) else {
+            return Ok(());
+        };
+        let changed = old
+            .keys()
+            .chain(new.keys())
+            .filter(|key| self.is_reserved(key) && old.get(*key) != new.get(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        match (changed.first(), self.collision) {
+            (None, _) => {}
+            (Some(key), ReservedFieldCollision::Reject) => {
+                return Err(Error::msg(format!("Field `{}` is reserved", key)));
+            }
+            (Some(_), ReservedFieldCollision::Strip) => {
+                for key in changed {
+                    match old.get(&key) {
+                        Some(value) => new.insert(key, value.clone()),
+                        None => new.remove(&key),
+                    };
+                }
+            }
+        }
+        Ok(())
+    }
+}
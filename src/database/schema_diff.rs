@@ -0,0 +1,457 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use super::entity::{Entity, EntityAssociation, EntityName, Index};
+use super::field_default::DefaultValue;
+use super::field_type::FieldType;
+use super::time_series::TimeSeries;
+use super::tree::Tree;
+
+/// Read the entities of an instance config. Accepts a JSON array of entities, an array of
+/// instances with an `entities` array each, or a `_meta.json` file written by Deeb.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+/// let config = json!({"_meta": [
+///     {"name": "user", "primary_key": "id", "associations": [], "indexes": []}
+/// ]});
+/// let entities = entities_from_config(&config).unwrap();
+/// assert_eq!(entities[0].name, EntityName::from("user"));
+/// ```
+pub fn entities_from_config(config: &Value) -> Result<Vec<Entity>, Error> {
+    let values = match config {
+        Value::Object(object) => object
+            .get("_meta")
+            .and_then(Value::as_array)
+            .ok_or_else(|| Error::msg("Config object must have a `_meta` array"))?,
+        Value::Array(values) => values,
+        _ => return Err(Error::msg("Config must be a JSON array or object")),
+    };
+    let mut entities = vec![];
+    for value in values {
+        match value.get("entities").and_then(Value::as_array) {
+            Some(instance_entities) => {
+                for entity in instance_entities {
+                    entities.push(entity_from_config(entity)?);
+                }
+            }
+            None => entities.push(entity_from_config(value)?),
+        }
+    }
+    Ok(entities)
+}
+
+fn entity_from_config(value: &Value) -> Result<Entity, Error> {
+    let mut value = value.clone();
+    let name = value
+        .get("name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::msg("Entity must have a `name`"))?
+        .to_string();
+    let Some(object) = value.as_object_mut() else {
+        return Err(Error::msg(format!("Entity `{}` must be an object", name)));
+    };
+    for key in ["associations", "indexes"] {
+        object.entry(key).or_insert_with(|| Value::Array(vec![]));
+    }
+    object.entry("primary_key").or_insert(Value::Null);
+    // `_meta.json` leaves out aliases, which default to the associated entity name.
+    if let Some(associations) = object.get_mut("associations").and_then(Value::as_array_mut) {
+        for association in associations.iter_mut().filter_map(Value::as_object_mut) {
+            if !association.contains_key("alias") {
+                let entity_name = association.get("entity_name").cloned();
+                association.insert("alias".to_string(), entity_name.unwrap_or_default());
+            }
+        }
+    }
+    serde_json::from_value(value)
+        .map_err(|err| Error::msg(format!("Invalid entity `{}`: {}", name, err)))
+}
+
+/// The changes to a single entity that exists in both configs.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityDiff {
+    pub name: EntityName,
+    /// The old and new primary key, when it changed.
+    pub primary_key: Option<(Option<String>, Option<String>)>,
+    pub added_indexes: Vec<Index>,
+    pub removed_indexes: Vec<Index>,
+    pub added_associations: Vec<EntityAssociation>,
+    pub removed_associations: Vec<EntityAssociation>,
+    /// Fields that gained a default value.
+    pub added_defaults: BTreeMap<String, DefaultValue>,
+    /// Fields that lost their default value.
+    pub removed_defaults: Vec<String>,
+    pub added_encrypted_fields: Vec<String>,
+    pub removed_encrypted_fields: Vec<String>,
+    pub added_redacted_fields: Vec<String>,
+    pub removed_redacted_fields: Vec<String>,
+    /// The old and new time series settings, when they changed.
+    pub time_series: Option<(Option<TimeSeries>, Option<TimeSeries>)>,
+    /// The old and new tree settings, when they changed.
+    pub tree: Option<(Option<Tree>, Option<Tree>)>,
+    /// The old and new type of each field whose declared type was added, removed, or
+    /// changed.
+    pub field_types: BTreeMap<String, (Option<FieldType>, Option<FieldType>)>,
+}
+
+impl EntityDiff {
+    fn new(old: &Entity, new: &Entity) -> Self {
+        Self {
+            name: new.name.clone(),
+            primary_key: (old.primary_key != new.primary_key)
+                .then(|| (old.primary_key.clone(), new.primary_key.clone())),
+            added_indexes: missing_from(&new.indexes, &old.indexes),
+            removed_indexes: missing_from(&old.indexes, &new.indexes),
+            added_associations: missing_from(&new.associations, &old.associations),
+            removed_associations: missing_from(&old.associations, &new.associations),
+            added_defaults: new
+                .defaults
+                .iter()
+                .filter(|(field, _)| !old.defaults.contains_key(*field))
+                .map(|(field, value)| (field.clone(), value.clone()))
+                .collect(),
+            removed_defaults: old
+                .defaults
+                .keys()
+                .filter(|field| !new.defaults.contains_key(*field))
+                .cloned()
+                .collect(),
+            added_encrypted_fields: missing_from(&new.encrypted_fields, &old.encrypted_fields),
+            removed_encrypted_fields: missing_from(&old.encrypted_fields, &new.encrypted_fields),
+            added_redacted_fields: missing_from(&new.redacted_fields, &old.redacted_fields),
+            removed_redacted_fields: missing_from(&old.redacted_fields, &new.redacted_fields),
+            time_series: (old.time_series != new.time_series)
+                .then(|| (old.time_series.clone(), new.time_series.clone())),
+            tree: (old.tree != new.tree).then(|| (old.tree.clone(), new.tree.clone())),
+            field_types: old
+                .field_types
+                .keys()
+                .chain(new.field_types.keys())
+                .filter_map(|field| {
+                    let old_type = old.field_types.get(field).copied();
+                    let new_type = new.field_types.get(field).copied();
+                    (old_type != new_type).then(|| (field.clone(), (old_type, new_type)))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primary_key.is_none()
+            && self.added_indexes.is_empty()
+            && self.removed_indexes.is_empty()
+            && self.added_associations.is_empty()
+            && self.removed_associations.is_empty()
+            && self.added_defaults.is_empty()
+            && self.removed_defaults.is_empty()
+            && self.added_encrypted_fields.is_empty()
+            && self.removed_encrypted_fields.is_empty()
+            && self.added_redacted_fields.is_empty()
+            && self.removed_redacted_fields.is_empty()
+            && self.time_series.is_none()
+            && self.tree.is_none()
+            && self.field_types.is_empty()
+    }
+}
+
+fn missing_from<T: Clone + PartialEq>(values: &[T], other: &[T]) -> Vec<T> {
+    values
+        .iter()
+        .filter(|value| !other.contains(value))
+        .cloned()
+        .collect()
+}
+
+/// The differences between two versions of an instance config, for reviewing a deploy
+/// and planning the migration it needs.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+///
+/// let old = vec![Entity::new("user").primary_key("id")];
+/// let new = vec![
+///     Entity::new("user")
+///         .primary_key("id")
+///         .default_value("status", json!("active"))
+///         .add_index("by_email", vec!["email"])
+///         .clone(),
+///     Entity::new("comment"),
+/// ];
+/// let diff = SchemaDiff::new(&old, &new);
+/// assert_eq!(diff.added_entities, vec![EntityName::from("comment")]);
+/// assert_eq!(diff.migration_plan().len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SchemaDiff {
+    pub added_entities: Vec<EntityName>,
+    pub removed_entities: Vec<EntityName>,
+    pub changed_entities: Vec<EntityDiff>,
+}
+
+impl SchemaDiff {
+    pub fn new(old: &[Entity], new: &[Entity]) -> Self {
+        let find = |entities: &'_ [Entity], name: &EntityName| {
+            entities.iter().find(|entity| entity.name == *name).cloned()
+        };
+        let mut diff = Self::default();
+        for entity in new {
+            match find(old, &entity.name) {
+                Some(old_entity) => {
+                    let entity_diff = EntityDiff::new(&old_entity, entity);
+                    if !entity_diff.is_empty() {
+                        diff.changed_entities.push(entity_diff);
+                    }
+                }
+                None => diff.added_entities.push(entity.name.clone()),
+            }
+        }
+        for entity in old {
+            if find(new, &entity.name).is_none() {
+                diff.removed_entities.push(entity.name.clone());
+            }
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_entities.is_empty()
+            && self.removed_entities.is_empty()
+            && self.changed_entities.is_empty()
+    }
+
+    /// The operations that bring existing data in line with the new config. Fields that
+    /// gained a default are backfilled, skipping documents that already have a value.
+    /// Fields that gained or lost encryption are rewritten, and existing documents are
+    /// checked against new field types, time series timestamps, and tree parents, which
+    /// are otherwise only enforced on writes. Redacted fields need no migration.
+    ///
+    /// Entities do not declare their other fields, so no `drop_key` is planned. Removed
+    /// entities are reported by the diff but their data is left in place.
+    pub fn migration_plan(&self) -> Vec<MigrationStep> {
+        let mut steps = vec![];
+        for entity in self.changed_entities.iter() {
+            for index in entity.removed_indexes.iter() {
+                steps.push(MigrationStep::DropIndex {
+                    entity: entity.name.clone(),
+                    name: index.name.clone(),
+                });
+            }
+            for index in entity.added_indexes.iter() {
+                steps.push(MigrationStep::AddIndex {
+                    entity: entity.name.clone(),
+                    name: index.name.clone(),
+                    columns: index.columns.clone(),
+                });
+            }
+            for (field, value) in entity.added_defaults.iter() {
+                steps.push(MigrationStep::AddKey {
+                    entity: entity.name.clone(),
+                    key: field.clone(),
+                    value: value.clone(),
+                });
+            }
+            for field in entity.added_encrypted_fields.iter() {
+                steps.push(MigrationStep::EncryptField {
+                    entity: entity.name.clone(),
+                    field: field.clone(),
+                });
+            }
+            for field in entity.removed_encrypted_fields.iter() {
+                steps.push(MigrationStep::DecryptField {
+                    entity: entity.name.clone(),
+                    field: field.clone(),
+                });
+            }
+            for (field, (_, field_type)) in entity.field_types.iter() {
+                if let Some(field_type) = field_type {
+                    steps.push(MigrationStep::CheckFieldType {
+                        entity: entity.name.clone(),
+                        field: field.clone(),
+                        field_type: *field_type,
+                    });
+                }
+            }
+            if let Some((old, Some(time_series))) = &entity.time_series {
+                let old_field = old.as_ref().map(|old| &old.timestamp_field);
+                if old_field != Some(&time_series.timestamp_field) {
+                    steps.push(MigrationStep::CheckTimestamps {
+                        entity: entity.name.clone(),
+                        field: time_series.timestamp_field.clone(),
+                    });
+                }
+            }
+            if let Some((_, Some(tree))) = &entity.tree {
+                steps.push(MigrationStep::CheckTree {
+                    entity: entity.name.clone(),
+                    parent_field: tree.parent_field.clone(),
+                });
+            }
+        }
+        steps
+    }
+}
+
+impl fmt::Display for SchemaDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes");
+        }
+        for name in self.added_entities.iter() {
+            writeln!(f, "+ entity {}", name)?;
+        }
+        for name in self.removed_entities.iter() {
+            writeln!(f, "- entity {}", name)?;
+        }
+        for entity in self.changed_entities.iter() {
+            writeln!(f, "~ entity {}", entity.name)?;
+            if let Some((old, new)) = &entity.primary_key {
+                writeln!(f, "    primary key {:?} -> {:?}", old, new)?;
+            }
+            for index in entity.added_indexes.iter() {
+                writeln!(f, "  + index {} {:?}", index.name, index.columns)?;
+            }
+            for index in entity.removed_indexes.iter() {
+                writeln!(f, "  - index {} {:?}", index.name, index.columns)?;
+            }
+            for association in entity.added_associations.iter() {
+                writeln!(f, "  + association {}", describe(association))?;
+            }
+            for association in entity.removed_associations.iter() {
+                writeln!(f, "  - association {}", describe(association))?;
+            }
+            for (field, value) in entity.added_defaults.iter() {
+                writeln!(f, "  + default {} = {}", field, Value::from(value.clone()))?;
+            }
+            for field in entity.removed_defaults.iter() {
+                writeln!(f, "  - default {}", field)?;
+            }
+            for field in entity.added_encrypted_fields.iter() {
+                writeln!(f, "  + encrypted {}", field)?;
+            }
+            for field in entity.removed_encrypted_fields.iter() {
+                writeln!(f, "  - encrypted {}", field)?;
+            }
+            for field in entity.added_redacted_fields.iter() {
+                writeln!(f, "  + redacted {}", field)?;
+            }
+            for field in entity.removed_redacted_fields.iter() {
+                writeln!(f, "  - redacted {}", field)?;
+            }
+            if let Some((old, new)) = &entity.time_series {
+                writeln!(f, "    time series {:?} -> {:?}", old, new)?;
+            }
+            if let Some((old, new)) = &entity.tree {
+                writeln!(f, "    tree {:?} -> {:?}", old, new)?;
+            }
+            for (field, (old, new)) in entity.field_types.iter() {
+                writeln!(f, "    type {} {:?} -> {:?}", field, old, new)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn describe(association: &EntityAssociation) -> String {
+    format!(
+        "{} -> {}.{} as {}",
+        association.from, association.entity_name, association.to, association.alias
+    )
+}
+
+/// A step of a [SchemaDiff::migration_plan].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MigrationStep {
+    /// Backfill a field that gained a default with
+    /// [AddKeyStrategy::Skip](crate::AddKeyStrategy::Skip).
+    AddKey {
+        entity: EntityName,
+        key: String,
+        value: DefaultValue,
+    },
+    AddIndex {
+        entity: EntityName,
+        name: String,
+        columns: Vec<String>,
+    },
+    DropIndex {
+        entity: EntityName,
+        name: String,
+    },
+    /// Rewrite the instance so existing plaintext values of the field are encrypted.
+    /// Plaintext is still read after the field is declared encrypted, so committing the
+    /// instance under the new config is enough.
+    EncryptField {
+        entity: EntityName,
+        field: String,
+    },
+    /// Decrypt the field under the old config before deploying. The new config reads the
+    /// stored ciphertext as a plain string.
+    DecryptField {
+        entity: EntityName,
+        field: String,
+    },
+    /// Check or convert existing values of a field that gained or changed its type.
+    CheckFieldType {
+        entity: EntityName,
+        field: String,
+        field_type: FieldType,
+    },
+    /// Check that every existing document has a timestamp in the field.
+    CheckTimestamps {
+        entity: EntityName,
+        field: String,
+    },
+    /// Check that existing documents form a tree through the parent field.
+    CheckTree {
+        entity: EntityName,
+        parent_field: String,
+    },
+}
+
+impl fmt::Display for MigrationStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MigrationStep::AddKey { entity, key, value } => {
+                write!(
+                    f,
+                    "add_key {} {} {}",
+                    entity,
+                    key,
+                    Value::from(value.clone())
+                )
+            }
+            MigrationStep::AddIndex {
+                entity,
+                name,
+                columns,
+            } => write!(f, "add_index {} {} {:?}", entity, name, columns),
+            MigrationStep::DropIndex { entity, name } => {
+                write!(f, "drop_index {} {}", entity, name)
+            }
+            MigrationStep::EncryptField { entity, field } => {
+                write!(f, "encrypt_field {} {}", entity, field)
+            }
+            MigrationStep::DecryptField { entity, field } => {
+                write!(f, "decrypt_field {} {}", entity, field)
+            }
+            MigrationStep::CheckFieldType {
+                entity,
+                field,
+                field_type,
+            } => write!(f, "check_field_type {} {} {:?}", entity, field, field_type),
+            MigrationStep::CheckTimestamps { entity, field } => {
+                write!(f, "check_timestamps {} {}", entity, field)
+            }
+            MigrationStep::CheckTree {
+                entity,
+                parent_field,
+            } => write!(f, "check_tree {} {}", entity, parent_field),
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{
+    add_key::{AddKeyReport, AddKeyStrategy},
+    entity::{Entity, EntityName},
+    path::{self, Segment},
+};
+
+/// The entity of the `_meta` instance holding the schema log.
+pub const SCHEMA_LOG_ENTITY: &str = "_schema_log";
+
+/// A data management operation recorded in the schema log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SchemaOperation {
+    AddKey {
+        key: String,
+        value: Value,
+        strategy: AddKeyStrategy,
+    },
+    DropKey {
+        key: String,
+    },
+}
+
+/// The operation that undoes a [SchemaChange].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SchemaReverse {
+    /// Drop a key that no document had before it was added.
+    DropKey { key: String },
+    /// Set the dropped values back on the documents with these primary keys.
+    RestoreKey {
+        key: String,
+        primary_key: String,
+        values: Vec<(Value, Value)>,
+    },
+}
+
+/// An `add_key` or `drop_key` recorded in the `_schema_log` entity of the `_meta`
+/// instance, for [Deeb::undo_last_schema_change](crate::Deeb::undo_last_schema_change).
+///
+/// Adding a key can be undone when no document had a value in the way and the key is not
+/// nested, since dropping it then restores every document. Dropping a key can be undone
+/// when the entity has a primary key and the key has no wildcards, so each value can be
+/// set back on its document. Other changes are recorded without a reverse.
+///
+/// The log is stored in the clear, so dropping an encrypted or redacted field, or an
+/// object holding one, is recorded without its values and can not be undone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SchemaChange {
+    pub id: String,
+    pub entity: EntityName,
+    pub operation: SchemaOperation,
+    /// How many documents the operation changed.
+    pub documents: usize,
+    pub reverse: Option<SchemaReverse>,
+    /// When the operation ran, in milliseconds since the Unix epoch.
+    pub created_at: i64,
+}
+
+impl SchemaChange {
+    pub fn new(
+        entity: &EntityName,
+        operation: SchemaOperation,
+        documents: usize,
+        reverse: Option<SchemaReverse>,
+    ) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            entity: entity.clone(),
+            operation,
+            documents,
+            reverse,
+            created_at: Utc::now().timestamp_millis(),
+        }
+    }
+
+    /// Record an `add_key` that changed the documents counted by the report.
+    pub fn add_key(
+        entity: &EntityName,
+        key: &str,
+        value: Value,
+        strategy: AddKeyStrategy,
+        report: AddKeyReport,
+    ) -> Self {
+        let reverse = (report.conflicts == 0 && path::parse(key).len() == 1).then(|| {
+            SchemaReverse::DropKey {
+                key: key.to_string(),
+            }
+        });
+        let operation = SchemaOperation::AddKey {
+            key: key.to_string(),
+            value,
+            strategy,
+        };
+        Self::new(entity, operation, report.touched(strategy), reverse)
+    }
+}
+
+/// Check if a key is, holds, or is held by an encrypted or redacted field of the entity.
+pub fn is_protected(entity: &Entity, key: &str) -> bool {
+    let key = path::parse(key);
+    entity
+        .encrypted_fields
+        .iter()
+        .chain(entity.redacted_fields.iter())
+        .any(|field| {
+            path::parse(field)
+                .iter()
+                .zip(key.iter())
+                .all(|(field, key)| {
+                    field == key || *field == Segment::Wildcard || *key == Segment::Wildcard
+                })
+        })
+}
+
+/// Set dropped values back on the documents whose primary key matches.
+pub fn restore_key(
+    data: &mut im::Vector<Value>,
+    key: &str,
+    primary_key: &str,
+    values: &[(Value, Value)],
+) {
+    let segments = path::parse(key);
+    let values = values
+        .iter()
+        .map(|(id, value)| (id.to_string(), value))
+        .collect::<HashMap<_, _>>();
+    for document in data.iter_mut() {
+        let Some(value) = document
+            .get(primary_key)
+            .and_then(|id| values.get(&id.to_string()))
+        else {
+            continue;
+        };
+        let value = (*value).clone();
+        path::set(document, &segments, value);
+    }
+}
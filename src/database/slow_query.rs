@@ -0,0 +1,75 @@
+use anyhow::Error;
+use fs2::FileExt;
+use log::*;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::time::Duration;
+
+use super::entity::EntityName;
+
+/// Configuration for logging slow queries on an instance.
+///
+/// Queries taking longer than the threshold are logged as warnings to the
+/// `deeb::slow_query` log target, and appended as JSON lines to the file when one is set.
+///
+/// ```
+/// use deeb::*;
+/// use std::time::Duration;
+///
+/// let slow_query_log = SlowQueryLog::new(Duration::from_millis(50)).file_path("./slow.log");
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowQueryLog {
+    pub threshold: Duration,
+    pub file_path: Option<String>,
+}
+
+impl SlowQueryLog {
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            file_path: None,
+        }
+    }
+
+    /// Append slow queries to a file as JSON lines.
+    pub fn file_path(mut self, file_path: &str) -> Self {
+        self.file_path = Some(file_path.to_string());
+        self
+    }
+}
+
+/// A query that exceeded the slow query threshold.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowQuery {
+    pub entity: EntityName,
+    pub operation: String,
+    /// The query with its values replaced by `?`.
+    pub shape: Value,
+    /// The number of documents the query examined before it finished.
+    pub scanned: usize,
+    pub duration_ms: f64,
+    pub lock_wait_ms: f64,
+}
+
+impl SlowQueryLog {
+    pub fn record(&self, slow_query: &SlowQuery) -> Result<(), Error> {
+        if slow_query.duration_ms < self.threshold.as_secs_f64() * 1000.0 {
+            return Ok(());
+        }
+        let line = serde_json::to_string(slow_query)?;
+        warn!(target: "deeb::slow_query", "{}", line);
+        if let Some(file_path) = &self.file_path {
+            let mut file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path)?;
+            file.lock_exclusive()?;
+            writeln!(file, "{}", line)?;
+            file.unlock()?;
+        }
+        Ok(())
+    }
+}
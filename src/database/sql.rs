@@ -0,0 +1,632 @@
+use anyhow::Error;
+use serde_json::{json, Map, Value};
+
+use super::{
+    entity::EntityName,
+    find_many_options::{FindManyOptions, FindManyOrder, OrderDirection},
+    path,
+    query::{LikeMode, LikeOptions, Query},
+    redaction,
+    time_series::Aggregation,
+    Database,
+};
+
+/// A parsed SQL `SELECT` statement over an entity.
+///
+/// Supports a read only subset of SQL: selected fields or `*`, `COUNT`, `SUM`, `AVG`,
+/// `MIN` and `MAX` without `GROUP BY`, `JOIN` on declared associations, `WHERE` with
+/// comparisons, `LIKE`, `ILIKE`, `IN`, `IS [NOT] NULL`, `AND`, `OR` and parentheses,
+/// `ORDER BY`, `LIMIT` and `OFFSET`. Fields are dot paths, and may be prefixed with the
+/// entity name. Quote identifiers with `"` and strings with `'`. `IS NULL` matches fields
+/// set to `null`, not missing fields.
+///
+/// ```
+/// use deeb::*;
+///
+/// let select = Select::parse(
+///     "SELECT name, age FROM user WHERE age >= 18 AND name LIKE 'J%' ORDER BY age DESC LIMIT 10",
+/// )
+/// .unwrap();
+/// assert_eq!(select.from(), &EntityName::from("user"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    columns: Vec<Column>,
+    from: EntityName,
+    joins: Vec<Join>,
+    filter: Option<Condition>,
+    order: Vec<(String, OrderDirection)>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    /// `*`, every field of the document.
+    All,
+    Field(String),
+    /// An aggregate over the matched documents. `COUNT(*)` has no field.
+    Aggregate(Aggregation, Option<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Join {
+    entity: EntityName,
+    /// The fields of an `ON a = b` clause, which must match the association.
+    on: Option<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Condition {
+    Compare(String, &'static str, Value),
+    Like(String, String, bool),
+    In(String, Vec<Value>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+}
+
+impl Condition {
+    fn to_query(&self, key: &dyn Fn(&str) -> String) -> Query {
+        match self {
+            Condition::Compare(field, operator, value) => {
+                let field = key(field);
+                let field = field.as_str();
+                match *operator {
+                    "=" => Query::eq(field, value.clone()),
+                    "<" => Query::lt(field, value.clone()),
+                    "<=" => Query::lte(field, value.clone()),
+                    ">" => Query::gt(field, value.clone()),
+                    ">=" => Query::gte(field, value.clone()),
+                    _ => Query::ne(field, value.clone()),
+                }
+            }
+            Condition::Like(field, pattern, case_insensitive) => {
+                let options = LikeOptions::new()
+                    .mode(LikeMode::Wildcard)
+                    .case_insensitive(*case_insensitive);
+                Query::like_with(key(field).as_str(), pattern.as_str(), options)
+            }
+            Condition::In(field, values) => Query::or(
+                values
+                    .iter()
+                    .map(|value| Query::eq(key(field).as_str(), value.clone()))
+                    .collect(),
+            ),
+            Condition::And(conditions) => {
+                Query::and(conditions.iter().map(|c| c.to_query(key)).collect())
+            }
+            Condition::Or(conditions) => {
+                Query::or(conditions.iter().map(|c| c.to_query(key)).collect())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Quoted(String),
+    String(String),
+    Number(Value),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: &[&str] = &[
+    "<=", ">=", "!=", "<>", "=", "<", ">", "(", ")", ",", "*", ";",
+];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = vec![];
+    let chars = sql.char_indices().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '\'' || c == '"' {
+            // A doubled quote inside a quoted string is an escaped quote.
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    Some((_, next)) if *next == c => {
+                        if chars.get(i + 1).is_some_and(|(_, after)| *after == c) {
+                            text.push(c);
+                            i += 2;
+                        } else {
+                            i += 1;
+                            break;
+                        }
+                    }
+                    Some((_, next)) => {
+                        text.push(*next);
+                        i += 1;
+                    }
+                    None => return Err(Error::msg("Unterminated quote in SQL")),
+                }
+            }
+            tokens.push(match c {
+                '\'' => Token::String(text),
+                _ => Token::Quoted(text),
+            });
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(i + 1).is_some_and(|(_, d)| d.is_ascii_digit()))
+        {
+            let mut end = i + 1;
+            while chars
+                .get(end)
+                .is_some_and(|(_, c)| c.is_ascii_alphanumeric() || *c == '.')
+            {
+                end += 1;
+            }
+            let text = &sql[start..chars.get(end).map_or(sql.len(), |(index, _)| *index)];
+            let number = serde_json::from_str::<Value>(text)
+                .ok()
+                .filter(Value::is_number)
+                .ok_or_else(|| Error::msg(format!("Invalid number `{}` in SQL", text)))?;
+            tokens.push(Token::Number(number));
+            i = end;
+        } else if c.is_alphanumeric() || c == '_' || c == '$' {
+            let mut end = i + 1;
+            while chars.get(end).is_some_and(|(_, c)| {
+                c.is_alphanumeric() || *c == '_' || *c == '$' || *c == '.' || *c == '[' || *c == ']'
+            }) {
+                end += 1;
+            }
+            let text = &sql[start..chars.get(end).map_or(sql.len(), |(index, _)| *index)];
+            tokens.push(Token::Ident(text.to_string()));
+            i = end;
+        } else if let Some(symbol) = SYMBOLS
+            .iter()
+            .find(|symbol| sql[start..].starts_with(**symbol))
+        {
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        } else {
+            return Err(Error::msg(format!("Unexpected `{}` in SQL", c)));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.keyword(keyword) {
+            return Ok(());
+        }
+        Err(Error::msg(format!("Expected `{}` in SQL", keyword)))
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(known)) if *known == symbol) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), Error> {
+        if self.symbol(symbol) {
+            return Ok(());
+        }
+        Err(Error::msg(format!("Expected `{}` in SQL", symbol)))
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        match self.next() {
+            Some(Token::Ident(ident) | Token::Quoted(ident)) => Ok(ident),
+            token => Err(Error::msg(format!(
+                "Expected an identifier in SQL, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Value, Error> {
+        match self.next() {
+            Some(Token::String(string)) => Ok(Value::String(string)),
+            Some(Token::Number(number)) => Ok(number),
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("true") => Ok(json!(true)),
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("false") => Ok(json!(false)),
+            Some(Token::Ident(ident)) if ident.eq_ignore_ascii_case("null") => Ok(Value::Null),
+            token => Err(Error::msg(format!(
+                "Expected a value in SQL, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn number(&mut self) -> Result<usize, Error> {
+        match self.next() {
+            Some(Token::Number(number)) => number
+                .as_u64()
+                .and_then(|number| usize::try_from(number).ok())
+                .ok_or_else(|| Error::msg(format!("Expected a count in SQL, found {}", number))),
+            token => Err(Error::msg(format!(
+                "Expected a count in SQL, found {:?}",
+                token
+            ))),
+        }
+    }
+
+    fn column(&mut self) -> Result<Column, Error> {
+        if self.symbol("*") {
+            return Ok(Column::All);
+        }
+        let ident = self.ident()?;
+        let aggregation = match ident.to_ascii_lowercase().as_str() {
+            "count" => Some(Aggregation::Count),
+            "sum" => Some(Aggregation::Sum),
+            "avg" => Some(Aggregation::Avg),
+            "min" => Some(Aggregation::Min),
+            "max" => Some(Aggregation::Max),
+            _ => None,
+        };
+        match aggregation {
+            Some(aggregation) if self.symbol("(") => {
+                let field = match self.symbol("*") {
+                    true if aggregation == Aggregation::Count => None,
+                    true => return Err(Error::msg("Only COUNT accepts `*` in SQL")),
+                    false => Some(self.ident()?),
+                };
+                self.expect_symbol(")")?;
+                Ok(Column::Aggregate(aggregation, field))
+            }
+            _ => Ok(Column::Field(ident)),
+        }
+    }
+
+    fn or(&mut self) -> Result<Condition, Error> {
+        let mut conditions = vec![self.and()?];
+        while self.keyword("or") {
+            conditions.push(self.and()?);
+        }
+        Ok(match conditions.len() {
+            1 => conditions.remove(0),
+            _ => Condition::Or(conditions),
+        })
+    }
+
+    fn and(&mut self) -> Result<Condition, Error> {
+        let mut conditions = vec![self.condition()?];
+        while self.keyword("and") {
+            conditions.push(self.condition()?);
+        }
+        Ok(match conditions.len() {
+            1 => conditions.remove(0),
+            _ => Condition::And(conditions),
+        })
+    }
+
+    fn condition(&mut self) -> Result<Condition, Error> {
+        if self.symbol("(") {
+            let condition = self.or()?;
+            self.expect_symbol(")")?;
+            return Ok(condition);
+        }
+        let field = self.ident()?;
+        if self.keyword("is") {
+            let operator = if self.keyword("not") { "!=" } else { "=" };
+            self.expect_keyword("null")?;
+            return Ok(Condition::Compare(field, operator, Value::Null));
+        }
+        if self.keyword("like") || self.keyword("ilike") {
+            let case_insensitive = matches!(
+                &self.tokens[self.position - 1],
+                Token::Ident(ident) if ident.eq_ignore_ascii_case("ilike")
+            );
+            return match self.next() {
+                Some(Token::String(pattern)) => {
+                    Ok(Condition::Like(field, pattern, case_insensitive))
+                }
+                _ => Err(Error::msg("Expected a pattern after LIKE in SQL")),
+            };
+        }
+        if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut values = vec![self.literal()?];
+            while self.symbol(",") {
+                values.push(self.literal()?);
+            }
+            self.expect_symbol(")")?;
+            return Ok(Condition::In(field, values));
+        }
+        let operator = match self.next() {
+            Some(Token::Symbol(operator))
+                if ["=", "!=", "<>", "<", "<=", ">", ">="].contains(&operator) =>
+            {
+                operator
+            }
+            token => {
+                return Err(Error::msg(format!(
+                    "Expected a comparison in SQL, found {:?}",
+                    token
+                )))
+            }
+        };
+        Ok(Condition::Compare(field, operator, self.literal()?))
+    }
+}
+
+impl Select {
+    /// The entity the statement selects from.
+    pub fn from(&self) -> &EntityName {
+        &self.from
+    }
+
+    pub fn parse(sql: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            tokens: tokenize(sql)?,
+            position: 0,
+        };
+        if !parser.keyword("select") {
+            return Err(Error::msg("Only SELECT statements are supported"));
+        }
+        let mut columns = vec![parser.column()?];
+        while parser.symbol(",") {
+            columns.push(parser.column()?);
+        }
+        parser.expect_keyword("from")?;
+        let from = EntityName::from(parser.ident()?.as_str());
+
+        let mut joins = vec![];
+        loop {
+            let joined = parser.keyword("inner") || parser.keyword("left");
+            if !parser.keyword("join") {
+                if joined {
+                    return Err(Error::msg("Expected `join` in SQL"));
+                }
+                break;
+            }
+            let entity = EntityName::from(parser.ident()?.as_str());
+            let on = match parser.keyword("on") {
+                true => {
+                    let left = parser.ident()?;
+                    parser.expect_symbol("=")?;
+                    Some((left, parser.ident()?))
+                }
+                false => None,
+            };
+            joins.push(Join { entity, on });
+        }
+
+        let filter = match parser.keyword("where") {
+            true => Some(parser.or()?),
+            false => None,
+        };
+
+        let mut order = vec![];
+        if parser.keyword("order") {
+            parser.expect_keyword("by")?;
+            loop {
+                let field = parser.ident()?;
+                let direction = if parser.keyword("desc") {
+                    OrderDirection::Descending
+                } else {
+                    parser.keyword("asc");
+                    OrderDirection::Ascending
+                };
+                order.push((field, direction));
+                if !parser.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let limit = match parser.keyword("limit") {
+            true => Some(parser.number()?),
+            false => None,
+        };
+        let offset = match parser.keyword("offset") {
+            true => Some(parser.number()?),
+            false => None,
+        };
+        parser.symbol(";");
+        if let Some(token) = parser.peek() {
+            return Err(Error::msg(format!("Unexpected {:?} in SQL", token)));
+        }
+
+        let aggregates = columns
+            .iter()
+            .filter(|column| matches!(column, Column::Aggregate(..)))
+            .count();
+        if aggregates > 0 && aggregates < columns.len() {
+            return Err(Error::msg(
+                "Aggregates can not be selected with fields, GROUP BY is not supported",
+            ));
+        }
+
+        Ok(Self {
+            columns,
+            from,
+            joins,
+            filter,
+            order,
+            limit,
+            offset,
+        })
+    }
+
+    /// Run the statement against the database. With `redact`, redacted fields are removed
+    /// before columns are selected.
+    pub fn run(&self, db: &Database, redact: bool) -> Result<Vec<Value>, Error> {
+        let entity = db
+            .get_entity(&self.from)
+            .ok_or_else(|| Error::msg(format!("Entity `{}` not found", self.from)))?;
+
+        // Joined fields live under the alias of the association.
+        let mut prefixes = vec![(format!("{}.", entity.name), String::new())];
+        let mut queries = vec![];
+        let mut joined = vec![];
+        for join in self.joins.iter() {
+            let association = entity
+                .associations
+                .iter()
+                .find(|association| association.entity_name == join.entity)
+                .ok_or_else(|| {
+                    Error::msg(format!(
+                        "Entity `{}` is not associated with `{}`",
+                        join.entity, entity.name
+                    ))
+                })?;
+            let joined_entity = db
+                .get_entity(&join.entity)
+                .ok_or_else(|| Error::msg(format!("Entity `{}` not found", join.entity)))?;
+            if let Some((left, right)) = &join.on {
+                let from = format!("{}.{}", entity.name, association.from);
+                let to = format!("{}.{}", join.entity, association.to);
+                if !((*left == from && *right == to) || (*left == to && *right == from)) {
+                    return Err(Error::msg(format!(
+                        "JOIN {} must be ON {} = {}",
+                        join.entity, from, to
+                    )));
+                }
+            }
+            prefixes.push((
+                format!("{}.", join.entity),
+                format!("{}.", association.alias),
+            ));
+            queries.push(Query::associated(joined_entity.clone(), Query::All));
+            joined.push(joined_entity.clone());
+        }
+        let key = |field: &str| {
+            prefixes
+                .iter()
+                .find_map(|(prefix, alias)| {
+                    field
+                        .strip_prefix(prefix.as_str())
+                        .map(|rest| format!("{}{}", alias, rest))
+                })
+                .unwrap_or_else(|| field.to_string())
+        };
+        if let Some(filter) = &self.filter {
+            queries.push(filter.to_query(&key));
+        }
+        let query = match queries.len() {
+            0 => Query::All,
+            1 => queries.remove(0),
+            _ => Query::and(queries),
+        };
+
+        if self
+            .columns
+            .iter()
+            .all(|column| matches!(column, Column::Aggregate(..)))
+        {
+            let mut values = db.find_many(entity, query, None)?;
+            if redact {
+                for value in values.iter_mut() {
+                    redaction::redact(entity, &joined, value);
+                }
+            }
+            let row = self
+                .columns
+                .iter()
+                .map(|column| {
+                    let Column::Aggregate(aggregation, field) = column else {
+                        unreachable!()
+                    };
+                    let name = format!(
+                        "{}({})",
+                        format!("{:?}", aggregation).to_lowercase(),
+                        field.as_deref().unwrap_or("*")
+                    );
+                    (
+                        name,
+                        aggregate(&values, *aggregation, field.as_deref().map(&key)),
+                    )
+                })
+                .collect::<Map<_, _>>();
+            let rows = vec![Value::Object(row)];
+            return Ok(rows
+                .into_iter()
+                .skip(self.offset.unwrap_or(0))
+                .take(self.limit.unwrap_or(usize::MAX))
+                .collect());
+        }
+
+        let options = FindManyOptions {
+            skip: self.offset,
+            limit: self.limit,
+            order: (!self.order.is_empty()).then(|| {
+                self.order
+                    .iter()
+                    .map(|(field, direction)| FindManyOrder::new(&key(field), *direction))
+                    .collect()
+            }),
+        };
+        let mut values = db.find_many(entity, query, Some(options))?;
+        for value in values.iter_mut() {
+            if redact {
+                redaction::redact(entity, &joined, value);
+            }
+            if !self.columns.contains(&Column::All) {
+                *value = project(value, &self.columns, &key);
+            }
+        }
+        Ok(values)
+    }
+}
+
+fn project(value: &Value, columns: &[Column], key: &dyn Fn(&str) -> String) -> Value {
+    let mut row = Map::new();
+    for column in columns {
+        if let Column::Field(field) = column {
+            row.insert(field.clone(), field_value(value, &key(field)));
+        }
+    }
+    Value::Object(row)
+}
+
+/// Get a field, collecting it from every element when the path runs through an array.
+fn field_value(value: &Value, field: &str) -> Value {
+    if let Some(value) = path::get(value, field) {
+        return value.clone();
+    }
+    let mut values = vec![];
+    path::collect(value, &path::parse(field), &mut values);
+    match values.is_empty() {
+        true => Value::Null,
+        false => Value::Array(values),
+    }
+}
+
+fn aggregate(values: &[Value], aggregation: Aggregation, field: Option<String>) -> Value {
+    let Some(field) = field else {
+        return json!(values.len());
+    };
+    let fields = values
+        .iter()
+        .map(|value| field_value(value, &field))
+        .filter(|value| !value.is_null())
+        .collect::<Vec<_>>();
+    match aggregation {
+        Aggregation::Count => json!(fields.len()),
+        _ => aggregation.apply(&fields.iter().filter_map(Value::as_f64).collect::<Vec<_>>()),
+    }
+}
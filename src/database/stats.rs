@@ -0,0 +1,44 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use super::entity::EntityName;
+
+/// Operation counters for an entity, kept since the database was created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct EntityStats {
+    /// Find operations.
+    pub reads: u64,
+    /// Insert, update, and delete operations.
+    pub writes: u64,
+    /// Operations that scanned the entity to match a query.
+    pub scans: u64,
+    /// Documents examined by those scans.
+    pub scanned: u64,
+}
+
+/// Counters shared by every snapshot of a database, so reads made against a snapshot
+/// are still counted.
+#[derive(Debug, Clone, Default)]
+pub struct Stats(Arc<Mutex<HashMap<EntityName, EntityStats>>>);
+
+impl Stats {
+    /// Count an operation. Pass the number of documents scanned when it matched a query.
+    pub fn record(&self, entity: &EntityName, write: bool, scanned: Option<usize>) {
+        let mut stats = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        let stats = stats.entry(entity.clone()).or_default();
+        if write {
+            stats.writes += 1;
+        } else {
+            stats.reads += 1;
+        }
+        if let Some(scanned) = scanned {
+            stats.scans += 1;
+            stats.scanned += scanned as u64;
+        }
+    }
+
+    pub fn get(&self) -> HashMap<EntityName, EntityStats> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+}
@@ -0,0 +1,210 @@
+use anyhow::Error;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use super::path;
+
+/// Configuration for an entity holding time series data, such as metrics or sensor
+/// readings.
+///
+/// Points are kept sorted by their timestamp, so appending the newest point is cheap
+/// and range queries use a binary search instead of scanning every document. Timestamps
+/// are milliseconds since the Unix epoch or RFC 3339 strings.
+///
+/// ```
+/// use deeb::*;
+/// use std::time::Duration;
+///
+/// let reading = Entity::new("reading").time_series(
+///     TimeSeries::new("at", Duration::from_secs(60)).retention(Duration::from_secs(86_400)),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TimeSeries {
+    pub timestamp_field: String,
+    /// The width of the buckets points are rolled up into when downsampling.
+    pub granularity: Duration,
+    /// How long points are kept, measured back from the newest point.
+    pub retention: Option<Duration>,
+}
+
+impl TimeSeries {
+    pub fn new(timestamp_field: &str, granularity: Duration) -> Self {
+        Self {
+            timestamp_field: timestamp_field.to_string(),
+            granularity,
+            retention: None,
+        }
+    }
+
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Get the timestamp of a point in milliseconds since the Unix epoch.
+    pub fn timestamp(&self, value: &Value) -> Result<i64, Error> {
+        let timestamp = path::get(value, &self.timestamp_field)
+            .ok_or_else(|| Error::msg(format!("Point is missing `{}`", self.timestamp_field)))?;
+        match timestamp {
+            Value::Number(number) => number
+                .as_i64()
+                .ok_or_else(|| Error::msg(format!("`{}` is not an integer", self.timestamp_field))),
+            Value::String(string) => Ok(DateTime::parse_from_rfc3339(string)?.timestamp_millis()),
+            _ => Err(Error::msg(format!(
+                "`{}` is not a timestamp",
+                self.timestamp_field
+            ))),
+        }
+    }
+
+    fn granularity_millis(&self) -> i64 {
+        i64::try_from(self.granularity.as_millis())
+            .unwrap_or(i64::MAX)
+            .max(1)
+    }
+
+    fn retention_millis(&self) -> Option<i64> {
+        self.retention
+            .map(|retention| i64::try_from(retention.as_millis()).unwrap_or(i64::MAX))
+    }
+
+    /// Insert a point in timestamp order and drop the points that fell out of retention.
+    pub fn append(&self, data: &mut im::Vector<Value>, value: Value) -> Result<(), Error> {
+        let timestamp = self.timestamp(&value)?;
+        // Points usually arrive in order, so check the end before searching.
+        let is_newest = match data.last() {
+            Some(last) => self.timestamp(last)? <= timestamp,
+            None => true,
+        };
+        if is_newest {
+            data.push_back(value);
+        } else {
+            let index = self.partition_point(data, |point| point <= timestamp);
+            data.insert(index, value);
+        }
+        if let Some(retention) = self.retention_millis() {
+            let newest = match data.last() {
+                Some(last) => self.timestamp(last)?,
+                None => return Ok(()),
+            };
+            let cutoff = newest.saturating_sub(retention);
+            let expired = self.partition_point(data, |point| point < cutoff);
+            if expired > 0 {
+                *data = data.skip(expired);
+            }
+        }
+        Ok(())
+    }
+
+    /// Sort points by their timestamp, such as after an update moved one or when a file
+    /// was edited by hand. Points with the same timestamp keep their order, and points
+    /// without a valid timestamp are treated as the oldest.
+    pub fn sort(&self, data: &mut im::Vector<Value>) {
+        let timestamp = |point: &Value| self.timestamp(point).unwrap_or(i64::MIN);
+        let is_sorted = data
+            .iter()
+            .zip(data.iter().skip(1))
+            .all(|(a, b)| timestamp(a) <= timestamp(b));
+        if is_sorted {
+            return;
+        }
+        let mut points = data.iter().cloned().collect::<Vec<_>>();
+        points.sort_by_cached_key(timestamp);
+        *data = points.into_iter().collect();
+    }
+
+    /// Get the points with a timestamp in `from..to`. Either bound may be left open.
+    pub fn range(
+        &self,
+        data: &im::Vector<Value>,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> im::Vector<Value> {
+        let start = from.map_or(0, |from| self.partition_point(data, |point| point < from));
+        let end = to.map_or(data.len(), |to| {
+            self.partition_point(data, |point| point < to)
+        });
+        if start >= end {
+            return im::Vector::new();
+        }
+        data.clone().slice(start..end)
+    }
+
+    /// Roll points up into buckets of the configured granularity, aggregating a numeric
+    /// field. Each bucket is returned as `{"timestamp", "count", "value"}`, where the
+    /// timestamp is the start of the bucket.
+    pub fn downsample(
+        &self,
+        points: &im::Vector<Value>,
+        field: &str,
+        aggregation: Aggregation,
+    ) -> Result<Vec<Value>, Error> {
+        let granularity = self.granularity_millis();
+        let mut buckets: Vec<(i64, Vec<f64>)> = vec![];
+        for point in points.iter() {
+            let bucket = self.timestamp(point)?.div_euclid(granularity) * granularity;
+            let value = path::get(point, field).and_then(Value::as_f64);
+            match buckets.last_mut() {
+                Some((start, values)) if *start == bucket => values.extend(value),
+                _ => buckets.push((bucket, value.into_iter().collect())),
+            }
+        }
+        Ok(buckets
+            .into_iter()
+            .map(|(timestamp, values)| {
+                json!({
+                    "timestamp": timestamp,
+                    "count": values.len(),
+                    "value": aggregation.apply(&values),
+                })
+            })
+            .collect())
+    }
+
+    /// Find the first index where the predicate is false, assuming the points are sorted.
+    /// Points without a valid timestamp are treated as the oldest.
+    fn partition_point<F>(&self, data: &im::Vector<Value>, predicate: F) -> usize
+    where
+        F: Fn(i64) -> bool,
+    {
+        let (mut low, mut high) = (0, data.len());
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let timestamp = self.timestamp(&data[middle]).unwrap_or(i64::MIN);
+            if predicate(timestamp) {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        low
+    }
+}
+
+/// How the values in a downsampled bucket are combined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Aggregation {
+    Avg,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Aggregation {
+    pub(crate) fn apply(&self, values: &[f64]) -> Value {
+        if values.is_empty() && *self != Aggregation::Count {
+            return Value::Null;
+        }
+        match self {
+            Aggregation::Avg => json!(values.iter().sum::<f64>() / values.len() as f64),
+            Aggregation::Min => json!(values.iter().copied().fold(f64::INFINITY, f64::min)),
+            Aggregation::Max => json!(values.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+            Aggregation::Sum => json!(values.iter().sum::<f64>()),
+            Aggregation::Count => json!(values.len()),
+        }
+    }
+}
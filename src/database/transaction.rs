@@ -7,6 +7,12 @@ pub struct Transaction {
     pub operations: Vec<Operation>,
 }
 
+impl Default for Transaction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Transaction {
     pub fn new() -> Self {
         Self {
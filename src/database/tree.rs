@@ -0,0 +1,216 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+
+use super::entity::Entity;
+
+/// Configuration for a self referencing entity, where each document points at its parent
+/// by primary key, such as category trees or nested comments.
+///
+/// With a materialized path, every document also stores the ids of its ancestors from
+/// the root down, kept up to date on insert and when a parent changes. An `eq` query on
+/// the path then finds every descendant of a document.
+///
+/// ```
+/// use deeb::*;
+///
+/// let category = Entity::new("category")
+///     .primary_key("id")
+///     .tree(Tree::new("parent_id").materialized_path("path"));
+/// let subtree = Query::eq("path", 4);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Tree {
+    pub parent_field: String,
+    pub path_field: Option<String>,
+}
+
+impl Tree {
+    pub fn new(parent_field: &str) -> Self {
+        Self {
+            parent_field: parent_field.to_string(),
+            path_field: None,
+        }
+    }
+
+    /// Store the ids of a document's ancestors, root first, in a field.
+    pub fn materialized_path(mut self, path_field: &str) -> Self {
+        self.path_field = Some(path_field.to_string());
+        self
+    }
+
+    /// Get the tree configuration and primary key of an entity.
+    pub fn of(entity: &Entity) -> Result<(&Tree, &str), Error> {
+        let tree = entity
+            .tree
+            .as_ref()
+            .ok_or_else(|| Error::msg(format!("Entity `{}` is not a tree", entity.name)))?;
+        let primary_key = entity.primary_key.as_deref().ok_or_else(|| {
+            Error::msg(format!(
+                "Entity `{}` needs a primary key to be a tree",
+                entity.name
+            ))
+        })?;
+        Ok((tree, primary_key))
+    }
+
+    fn parent<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        value
+            .get(&self.parent_field)
+            .filter(|parent| !parent.is_null())
+    }
+
+    /// Get every document below the one with the id, breadth first.
+    pub fn descendants(
+        &self,
+        primary_key: &str,
+        data: &im::Vector<Value>,
+        id: &Value,
+    ) -> Vec<Value> {
+        let mut descendants = vec![];
+        // Tracking visited ids keeps a cycle from being followed forever.
+        let mut visited = HashSet::from([id.to_string()]);
+        let mut parents = HashSet::from([id.to_string()]);
+        while !parents.is_empty() {
+            let mut children = HashSet::new();
+            for value in data.iter() {
+                let Some(parent) = self.parent(value) else {
+                    continue;
+                };
+                let Some(child) = value.get(primary_key) else {
+                    continue;
+                };
+                if parents.contains(&parent.to_string()) && visited.insert(child.to_string()) {
+                    children.insert(child.to_string());
+                    descendants.push(value.clone());
+                }
+            }
+            parents = children;
+        }
+        descendants
+    }
+
+    /// Get the ancestors of the document with the id, parent first.
+    pub fn ancestors(
+        &self,
+        primary_key: &str,
+        data: &im::Vector<Value>,
+        id: &Value,
+    ) -> Result<Vec<Value>, Error> {
+        let by_id = index_by_id(primary_key, data);
+        let mut ancestors = vec![];
+        let mut current = by_id.get(&id.to_string()).copied();
+        while let Some(value) = current {
+            let Some(parent) = self.parent(value) else {
+                break;
+            };
+            if ancestors.len() > data.len() {
+                return Err(Error::msg(format!("Document {} is in a cycle", id)));
+            }
+            current = by_id.get(&parent.to_string()).copied();
+            if let Some(parent) = current {
+                ancestors.push(parent.clone());
+            }
+        }
+        Ok(ancestors)
+    }
+
+    /// Set the materialized path of a document from its parent in the data.
+    pub fn set_path(
+        &self,
+        primary_key: &str,
+        data: &im::Vector<Value>,
+        value: &mut Value,
+    ) -> Result<(), Error> {
+        let Some(path_field) = &self.path_field else {
+            return Ok(());
+        };
+        let path = match self.parent(value) {
+            Some(parent) => {
+                let parent_value = data
+                    .iter()
+                    .find(|value| value.get(primary_key) == Some(parent))
+                    .ok_or_else(|| Error::msg(format!("Parent {} not found", parent)))?;
+                let mut path = parent_value
+                    .get(path_field)
+                    .and_then(Value::as_array)
+                    .cloned()
+                    .unwrap_or_default();
+                path.push(parent.clone());
+                path
+            }
+            None => vec![],
+        };
+        if let Some(object) = value.as_object_mut() {
+            object.insert(path_field.clone(), Value::Array(path));
+        }
+        Ok(())
+    }
+
+    /// Recompute the materialized path of every document, after parents have changed.
+    pub fn rebuild_paths(
+        &self,
+        primary_key: &str,
+        data: &mut im::Vector<Value>,
+    ) -> Result<(), Error> {
+        let Some(path_field) = &self.path_field else {
+            return Ok(());
+        };
+        let by_id = index_by_id(primary_key, data);
+        let mut paths = vec![];
+        for value in data.iter() {
+            let mut path = vec![];
+            let mut current = value;
+            while let Some(parent) = self.parent(current) {
+                if path.len() > data.len() {
+                    return Err(Error::msg(format!(
+                        "Document {} is in a cycle",
+                        value.get(primary_key).unwrap_or(&Value::Null)
+                    )));
+                }
+                path.push(parent.clone());
+                match by_id.get(&parent.to_string()) {
+                    Some(parent) => current = parent,
+                    None => break,
+                }
+            }
+            path.reverse();
+            paths.push(path);
+        }
+        for (value, path) in data.iter_mut().zip(paths) {
+            if let Some(object) = value.as_object_mut() {
+                object.insert(path_field.clone(), Value::Array(path));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn index_by_id<'a>(primary_key: &str, data: &'a im::Vector<Value>) -> HashMap<String, &'a Value> {
+    data.iter()
+        .filter_map(|value| Some((value.get(primary_key)?.to_string(), value)))
+        .collect()
+}
+
+/// Check if an update moves documents of a tree with a materialized path.
+pub fn reparents(entity: &Entity, update_value: &Value) -> bool {
+    entity.tree.as_ref().is_some_and(|tree| {
+        tree.path_field.is_some() && update_value.get(&tree.parent_field).is_some()
+    })
+}
+
+/// Recompute the materialized paths after an update, restoring the original data if the
+/// update made a cycle.
+pub fn rebuild(
+    entity: &Entity,
+    data: &mut im::Vector<Value>,
+    original: im::Vector<Value>,
+) -> Result<(), Error> {
+    let (tree, primary_key) = Tree::of(entity)?;
+    if let Err(err) = tree.rebuild_paths(primary_key, data) {
+        *data = original;
+        return Err(err);
+    }
+    Ok(())
+}
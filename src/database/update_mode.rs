@@ -0,0 +1,53 @@
+use anyhow::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// How an update value is combined with a document.
+///
+/// ```
+/// use deeb::*;
+/// use serde_json::json;
+///
+/// let document = json!({"name": "Joey", "address": {"city": "Austin", "zip": "78701"}});
+/// let update = json!({"nickname": null, "address": {"zip": null}});
+///
+/// let merged = UpdateMode::Merge.apply(&document, &update).unwrap();
+/// assert_eq!(merged["address"], json!({"zip": null}));
+///
+/// let patched = UpdateMode::MergePatch.apply(&document, &update).unwrap();
+/// assert_eq!(patched, json!({"name": "Joey", "address": {"city": "Austin"}}));
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UpdateMode {
+    /// Replace each top level field of the update, including with `null`.
+    #[default]
+    Merge,
+    /// JSON Merge Patch (RFC 7386). Objects merge recursively and `null` removes a field.
+    MergePatch,
+}
+
+impl UpdateMode {
+    /// Combine an update with a document, both of which must be JSON objects.
+    pub fn apply(&self, value: &Value, update_value: &Value) -> Result<Value, Error> {
+        let Value::Object(value) = value else {
+            return Err(Error::msg("Value must be a JSON object"));
+        };
+        let Value::Object(update_value) = update_value else {
+            return Err(Error::msg("Update value must be a JSON object"));
+        };
+        let mut value = value.clone();
+        match self {
+            UpdateMode::Merge => {
+                for (update_key, update_value) in update_value {
+                    value.insert(update_key.clone(), update_value.clone());
+                }
+            }
+            UpdateMode::MergePatch => {
+                let mut merged = Value::Object(value);
+                json_patch::merge(&mut merged, &Value::Object(update_value.clone()));
+                return Ok(merged);
+            }
+        }
+        Ok(Value::Object(value))
+    }
+}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+/// How durable a write must be before the operation returns.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteConcern {
+    /// Apply the write in memory only. It is written with the next commit of the instance
+    /// or by [Deeb::flush](crate::Deeb::flush).
+    Memory,
+    /// Write the instance file before returning. This is the default.
+    #[default]
+    Flushed,
+    /// Write the instance file and wait for it to reach the disk before returning.
+    Fsynced,
+}
@@ -1,16 +1,55 @@
 use anyhow::Error;
 use log::*;
+use serde::de::DeserializeOwned;
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
+#[cfg(feature = "arrow")]
+use crate::database::arrow;
+#[cfg(feature = "sql")]
+use crate::database::sql::Select;
 use crate::database::{
-    entity::Entity, name::Name, query::Query, transaction::Transaction, Database, ExecutedValue,
-    Operation,
+    add_key::{AddKeyReport, AddKeyStrategy},
+    blob,
+    encryption::KeyProvider,
+    entity::{Entity, EntityName},
+    find_many_options::{FindManyOptions, FindManyOrder},
+    insert_options::InsertOptions,
+    instance_config::merge_instance_config,
+    integrity::IntegrityReport,
+    name::Name,
+    outbox::OutboxMessage,
+    page::{self, Page, PageRequest},
+    path,
+    query::Query,
+    redaction,
+    reserved_fields::ReservedFields,
+    schema_log::{SchemaChange, SCHEMA_LOG_ENTITY},
+    slow_query::SlowQueryLog,
+    stats::EntityStats,
+    time_series::Aggregation,
+    transaction::Transaction,
+    update_mode::UpdateMode,
+    write_concern::WriteConcern,
+    Database, ExecutedValue, Operation,
 };
+use crate::kv::Kv;
+use crate::lock::DocumentLock;
+use crate::write_batch::WriteBatch;
 
 pub struct Deeb {
     db: Arc<RwLock<Database>>,
+    redact: bool,
+    write_concern: WriteConcern,
+}
+
+impl Default for Deeb {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Deeb {
@@ -32,9 +71,100 @@ impl Deeb {
         let database = Database::new();
         Self {
             db: Arc::new(RwLock::new(database)),
+            redact: true,
+            write_concern: WriteConcern::default(),
+        }
+    }
+
+    /// Get a view of the same database that does not hide fields declared with
+    /// [Entity::redacted_fields](crate::Entity::redacted_fields). Use this view when the
+    /// reader is allowed to see sensitive fields, such as the owner of a document or an admin.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user").redacted_fields(vec!["password"]);
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "password": "secret"}), None).await?;
+    /// let user_with_password = db
+    ///     .unredacted()
+    ///     .find_one(&user, Query::eq("name", "Joey"), None)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn unredacted(&self) -> Self {
+        Self {
+            db: self.db.clone(),
+            redact: false,
+            write_concern: self.write_concern,
+        }
+    }
+
+    /// Get a view of the same database where inserts, updates, and deletes wait for the
+    /// given durability before returning. Use [WriteConcern::Memory] for ephemeral data and
+    /// [WriteConcern::Fsynced] for critical records.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let payment = Entity::new("payment");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./payment.json", vec![payment.clone()]).await?;
+    /// db.with_write_concern(WriteConcern::Fsynced)
+    ///     .insert(&payment, json!({"id": 1, "amount": 100}), None)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_write_concern(&self, write_concern: WriteConcern) -> Self {
+        Self {
+            db: self.db.clone(),
+            redact: self.redact,
+            write_concern,
         }
     }
 
+    /// Set the key provider used to encrypt and decrypt fields declared with
+    /// [Entity::encrypted_fields](crate::Entity::encrypted_fields). Set the key provider
+    /// before adding instances with encrypted entities so their data can be decrypted on load.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # struct StaticKeyProvider;
+    /// # impl KeyProvider for StaticKeyProvider {
+    /// #     fn key(&self, _entity: &EntityName) -> Result<[u8; 32], Error> {
+    /// #         Ok([7; 32])
+    /// #     }
+    /// # }
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// let user = Entity::new("user").encrypted_fields(vec!["email"]);
+    /// let db = Deeb::new();
+    /// db.set_key_provider(StaticKeyProvider).await;
+    /// db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn set_key_provider<K>(&self, key_provider: K) -> &Self
+    where
+        K: KeyProvider + 'static,
+    {
+        debug!("Setting key provider");
+        let mut db = self.db.write().await;
+        db.set_key_provider(Arc::new(key_provider));
+        self
+    }
+
     /// Add an instance to the database. An instance is a segment of the database. This
     /// is a JSON file that may have one or more entities. You can add multiple instances
     /// to the database allowing you to segment your data between different files.
@@ -120,9 +250,10 @@ impl Deeb {
         }
 
         let mut db = self.db.write().await;
-        let value = db.insert(entity, value)?;
+        let mut value = db.insert(entity, value)?;
         let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        self.redact_returned(entity, [&mut value]);
         Ok(value)
     }
 
@@ -149,21 +280,57 @@ impl Deeb {
         entity: &Entity,
         values: Vec<Value>,
         transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        self.insert_many_with_options(entity, values, InsertOptions::default(), transaction)
+            .await
+    }
+
+    /// Insert multiple values with [InsertOptions](crate::InsertOptions). With
+    /// `return_document` turned off, an empty list is returned instead of the inserted
+    /// documents.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let options = InsertOptions::new().return_document(false);
+    /// let values = vec![json!({"id": 1, "name": "Joey"}), json!({"id": 2, "name": "Steve"})];
+    /// db.insert_many_with_options(&user, values, options, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn insert_many_with_options(
+        &self,
+        entity: &Entity,
+        values: Vec<Value>,
+        options: InsertOptions,
+        transaction: Option<&mut Transaction>,
     ) -> Result<Vec<Value>, Error> {
         debug!("Inserting many");
         if let Some(transaction) = transaction {
+            let returned = match options.return_document {
+                true => values.clone(),
+                false => vec![],
+            };
             let operation = Operation::InsertMany {
                 entity: entity.clone(),
-                values: values.clone(),
+                values,
             };
             transaction.add_operation(operation);
-            return Ok(values);
+            return Ok(returned);
         }
 
         let mut db = self.db.write().await;
-        let values = db.insert_many(entity, values)?;
+        let mut values = db.insert_many(entity, values, options)?;
         let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        self.redact_returned(entity, &mut values);
         Ok(values)
     }
 
@@ -202,9 +369,19 @@ impl Deeb {
             return Ok(Value::Null);
         }
 
-        let db = self.db.read().await;
-        let value = db.find_one(entity, query)?;
+        let started = Instant::now();
+        // Readers scan a snapshot so the lock is only held long enough to take it.
+        let db = self.db.read().await.snapshot();
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let (mut value, scanned) = db.find_one_scanned(entity, query)?;
+        if self.redact {
+            redaction::redact(entity, &[], &mut value);
+        }
         trace!("Found value: {:?}", value);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(&db, entity, "find_one", &query, scanned, started, lock_wait);
+        }
         Ok(value)
     }
 
@@ -233,23 +410,12 @@ impl Deeb {
         query: Query,
         transaction: Option<&mut Transaction>,
     ) -> Result<Vec<Value>, Error> {
-        debug!("Finding many");
-        if let Some(transaction) = transaction {
-            let operation = Operation::FindMany {
-                entity: entity.clone(),
-                query: query.clone(),
-            };
-            transaction.add_operation(operation);
-            return Ok(vec![]);
-        }
-
-        let db = self.db.read().await;
-        let values = db.find_many(entity, query)?;
-        trace!("Found values: {:?}", values);
-        Ok(values)
+        self.find_many_with_options(entity, query, FindManyOptions::default(), transaction)
+            .await
     }
 
-    /// Delete a single value from the database.
+    /// Find multiple values with [FindManyOptions](crate::FindManyOptions) to sort, skip,
+    /// and limit the results. Options are ignored when the find is queued in a transaction.
     /// Passing a transaction will queue the operation to be executed later and
     /// requires you to commit the transaction.
     ///
@@ -263,80 +429,149 @@ impl Deeb {
     /// # let db = Deeb::new();
     /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
     /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
-    /// db.delete_one(&user, Query::eq("name", "Joey"), None).await?;
+    /// let options = FindManyOptions::builder()
+    ///     .limit(10)
+    ///     .order(FindManyOrder::new("age", OrderDirection::Descending).nulls_first())
+    ///     .sort("name", OrderDirection::Ascending)
+    ///     .build();
+    /// db.find_many_with_options(&user, Query::All, options, None).await?;
     /// # Ok(())
     /// # }
     /// ```
     #[allow(dead_code)]
-    pub async fn delete_one(
+    pub async fn find_many_with_options(
         &self,
         entity: &Entity,
         query: Query,
+        options: FindManyOptions,
         transaction: Option<&mut Transaction>,
-    ) -> Result<Value, Error> {
-        debug!("Deleting one");
+    ) -> Result<Vec<Value>, Error> {
+        debug!("Finding many");
         if let Some(transaction) = transaction {
-            let operation = Operation::DeleteOne {
+            let operation = Operation::FindMany {
                 entity: entity.clone(),
                 query: query.clone(),
             };
             transaction.add_operation(operation);
-            return Ok(Value::Null);
+            return Ok(vec![]);
         }
 
-        let mut db = self.db.write().await;
-        let value = db.delete_one(entity, query)?;
-        let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
-        trace!("Deleted value: {:?}", value);
-        Ok(value)
+        let started = Instant::now();
+        // Readers scan a snapshot so the lock is only held long enough to take it.
+        let db = self.db.read().await.snapshot();
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let associated_entities = query.associated_entities();
+        let scanned = db.count_documents(entity);
+        let mut values = db.find_many(entity, query, Some(options))?;
+        if self.redact {
+            for value in values.iter_mut() {
+                redaction::redact(entity, &associated_entities, value);
+            }
+        }
+        trace!("Found values: {:?}", values);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "find_many",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(values)
     }
 
-    /// Delete multiple values from the database.
-    /// Passing a transaction will queue the operation to be executed later and
-    /// requires you to commit the transaction.
+    /// Find one page of the documents matching a query, with the total number of matches.
+    /// The page and the total are read from the same snapshot, so they always agree.
+    /// Pages are sorted by the requested order and then the primary key; paging an entity
+    /// without a primary key needs an order.
     ///
     /// ```
     /// # use deeb::*;
     /// # use anyhow::Error;
-    /// # use serde_json::json;
+    /// # use serde_json::{json, Value};
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
-    /// # let user = Entity::new("user");
+    /// # let user = Entity::new("user").primary_key("id");
     /// # let db = Deeb::new();
     /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
-    /// db.delete_many(&user, Query::eq("age", 10), None).await?;
+    /// let page = db.find_page::<Value>(&user, Query::All, PageRequest::new(10)).await?;
+    /// if let Some(cursor) = page.next_cursor {
+    ///     let request = PageRequest::new(10).cursor(&cursor);
+    ///     db.find_page::<Value>(&user, Query::All, request).await?;
+    /// }
     /// # Ok(())
     /// # }
     /// ```
     #[allow(dead_code)]
-    pub async fn delete_many(
+    pub async fn find_page<T>(
         &self,
         entity: &Entity,
         query: Query,
-        transaction: Option<&mut Transaction>,
-    ) -> Result<Vec<Value>, Error> {
-        debug!("Deleting many");
-        if let Some(transaction) = transaction {
-            let operation = Operation::DeleteMany {
-                entity: entity.clone(),
-                query: query.clone(),
-            };
-            transaction.add_operation(operation);
-            return Ok(vec![]);
+        request: PageRequest,
+    ) -> Result<Page<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        debug!("Finding page");
+        if request.limit == 0 {
+            return Err(Error::msg("Page limit must be greater than 0"));
         }
-
-        let mut db = self.db.write().await;
-        let values = db.delete_many(entity, query)?;
-        let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
-        trace!("Deleted values: {:?}", values);
-        Ok(values)
+        let order = page::page_order(entity, request.order)?;
+        let started = Instant::now();
+        let db = self.db.read().await.snapshot();
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let associated_entities = query.associated_entities();
+        let options = FindManyOptions {
+            order: Some(order.clone()),
+            ..Default::default()
+        };
+        let scanned = db.count_documents(entity);
+        let values = db.find_many(entity, query, Some(options))?;
+        let total = values.len();
+        let start = match &request.cursor {
+            Some(cursor) => page::cursor_position(cursor, &values, &order)?,
+            None => 0,
+        };
+        let end = start.saturating_add(request.limit).min(total);
+        let next_cursor = (end < total).then(|| page::encode_cursor(&values[end - 1], &order));
+        let items = values
+            .into_iter()
+            .skip(start)
+            .take(request.limit)
+            .map(|mut value| {
+                if self.redact {
+                    redaction::redact(entity, &associated_entities, &mut value);
+                }
+                serde_json::from_value(value).map_err(Error::from)
+            })
+            .collect::<Result<Vec<T>, Error>>()?;
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "find_page",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(Page {
+            items,
+            total,
+            next_cursor,
+        })
     }
 
-    /// Update a single value in the database.
-    /// Passing a transaction will queue the operation to be executed later and
-    /// requires you to commit the transaction.
+    /// Export the documents matching a query as an Arrow record batch, with a column for
+    /// each field declared with [Entity::field_type](crate::Entity::field_type), so they
+    /// can be handed to Polars or DataFusion. Redacted fields are null unless the handle is
+    /// [unredacted](Deeb::unredacted). Requires the `arrow` feature.
     ///
     /// ```
     /// # use deeb::*;
@@ -344,44 +579,37 @@ impl Deeb {
     /// # use serde_json::json;
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
-    /// # let user = Entity::new("user");
+    /// let user = Entity::new("user")
+    ///     .field_type("name", FieldType::String)
+    ///     .field_type("age", FieldType::Int);
     /// # let db = Deeb::new();
     /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
-    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
-    /// db.update_one(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
+    /// let batch = db.to_arrow(&user, Query::gte("age", 18)).await?;
+    /// println!("{} adults", batch.num_rows());
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg(feature = "arrow")]
     #[allow(dead_code)]
-    pub async fn update_one(
+    pub async fn to_arrow(
         &self,
         entity: &Entity,
         query: Query,
-        update_value: Value,
-        transaction: Option<&mut Transaction>,
-    ) -> Result<Value, Error> {
-        debug!("Updating one");
-        if let Some(transaction) = transaction {
-            let operation = Operation::UpdateOne {
-                entity: entity.clone(),
-                query: query.clone(),
-                value: update_value.clone(),
-            };
-            transaction.add_operation(operation);
-            return Ok(update_value);
+    ) -> Result<arrow_array::RecordBatch, Error> {
+        debug!("Exporting to Arrow");
+        let db = self.db.read().await.snapshot();
+        let associated_entities = query.associated_entities();
+        let mut values = db.find_many(entity, query, None)?;
+        if self.redact {
+            for value in values.iter_mut() {
+                redaction::redact(entity, &associated_entities, value);
+            }
         }
-
-        let mut db = self.db.write().await;
-        let value = db.update_one(entity, query, update_value)?;
-        let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
-        trace!("Updated value: {:?}", value);
-        Ok(value)
+        arrow::to_record_batch(entity, &values)
     }
 
-    /// Update multiple values in the database.
-    /// Passing a transaction will queue the operation to be executed later and
-    /// requires you to commit the transaction.
+    /// Run a read only SQL `SELECT` statement. Requires the `sql` feature. See
+    /// [Select](crate::Select) for the supported subset of SQL.
     ///
     /// ```
     /// # use deeb::*;
@@ -392,40 +620,110 @@ impl Deeb {
     /// # let user = Entity::new("user");
     /// # let db = Deeb::new();
     /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
-    /// # db.update_many(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
-    /// db.update_many(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
+    /// let adults = db
+    ///     .sql("SELECT name FROM user WHERE age >= 18 ORDER BY name LIMIT 10")
+    ///     .await?;
     /// # Ok(())
     /// # }
     /// ```
+    #[cfg(feature = "sql")]
     #[allow(dead_code)]
-    pub async fn update_many(
+    pub async fn sql(&self, sql: &str) -> Result<Vec<Value>, Error> {
+        debug!("Running SQL");
+        let select = Select::parse(sql)?;
+        let db = self.db.read().await.snapshot();
+        let values = select.run(&db, self.redact)?;
+        trace!("Found values: {:?}", values);
+        Ok(values)
+    }
+
+    /// Follow associations from the documents matching a query, such as
+    /// user → comment → post, instead of running a `find_many` per step. Each entity of the
+    /// path must be associated with the one before it. Pass a depth to stop early.
+    ///
+    /// Returns the documents reached at each step, starting with the matched documents.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// let mut comment = Entity::new("comment").primary_key("id");
+    /// let user = Entity::new("user")
+    ///     .primary_key("id")
+    ///     .associate(&mut comment, "user_id", None::<&str>)
+    ///     .map_err(Error::msg)?;
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.add_instance("test2", "./comment.json", vec![comment.clone()]).await?;
+    /// let levels = db
+    ///     .traverse(&user, Query::eq("id", 1), vec![comment.clone()], None)
+    ///     .await?;
+    /// let comments = &levels[1];
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn traverse(
         &self,
         entity: &Entity,
         query: Query,
-        update_value: Value,
-        transaction: Option<&mut Transaction>,
-    ) -> Result<Vec<Value>, Error> {
-        debug!("Updating many");
-        if let Some(transaction) = transaction {
-            let operation = Operation::UpdateMany {
-                entity: entity.clone(),
-                query: query.clone(),
-                value: update_value.clone(),
-            };
-            transaction.add_operation(operation);
-            return Ok(vec![]);
+        path: Vec<Entity>,
+        depth: Option<usize>,
+    ) -> Result<Vec<Vec<Value>>, Error> {
+        debug!("Traversing");
+        let db = self.db.read().await.snapshot();
+        let mut levels = db.traverse(entity, query, &path, depth)?;
+        if self.redact {
+            for (entity, level) in std::iter::once(entity)
+                .chain(path.iter())
+                .zip(levels.iter_mut())
+            {
+                for value in level.iter_mut() {
+                    redaction::redact(entity, &[], value);
+                }
+            }
         }
+        trace!("Traversed levels: {:?}", levels);
+        Ok(levels)
+    }
 
-        let mut db = self.db.write().await;
-        let values = db.update_many(entity, query, update_value)?;
-        let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
-        trace!("Updated values: {:?}", values);
+    /// Find every document below the one with the id in a [tree](crate::Tree) entity,
+    /// breadth first.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let category = Entity::new("category").primary_key("id").tree(Tree::new("parent_id"));
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./category.json", vec![category.clone()]).await?;
+    /// let subcategories = db.find_descendants(&category, 1).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn find_descendants<I>(&self, entity: &Entity, id: I) -> Result<Vec<Value>, Error>
+    where
+        I: Into<Value>,
+    {
+        debug!("Finding descendants");
+        let db = self.db.read().await.snapshot();
+        let mut values = db.find_descendants(entity, &id.into())?;
+        if self.redact {
+            for value in values.iter_mut() {
+                redaction::redact(entity, &[], value);
+            }
+        }
+        trace!("Found values: {:?}", values);
         Ok(values)
     }
 
-    // Handle Transaction
-    /// Begin a new transaction.
+    /// Find the ancestors of the document with the id in a [tree](crate::Tree) entity,
+    /// starting with its parent.
     ///
     /// ```
     /// # use deeb::*;
@@ -433,80 +731,1294 @@ impl Deeb {
     /// # use serde_json::json;
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
+    /// # let category = Entity::new("category").primary_key("id").tree(Tree::new("parent_id"));
     /// # let db = Deeb::new();
-    /// let mut transaction = db.begin_transaction().await;
+    /// # db.add_instance("test", "./category.json", vec![category.clone()]).await?;
+    /// let breadcrumbs = db.find_ancestors(&category, 4).await?;
     /// # Ok(())
     /// # }
     /// ```
     #[allow(dead_code)]
-    pub async fn begin_transaction(&self) -> Transaction {
-        debug!("Beginning transaction");
-        Transaction::new()
+    pub async fn find_ancestors<I>(&self, entity: &Entity, id: I) -> Result<Vec<Value>, Error>
+    where
+        I: Into<Value>,
+    {
+        debug!("Finding ancestors");
+        let db = self.db.read().await.snapshot();
+        let mut values = db.find_ancestors(entity, &id.into())?;
+        if self.redact {
+            for value in values.iter_mut() {
+                redaction::redact(entity, &[], value);
+            }
+        }
+        trace!("Found values: {:?}", values);
+        Ok(values)
     }
 
-    /// Commit a transaction. Once a transaction is committed, all operations will be executed and
-    /// the JSON file will be updated.
+    /// Wait for a document matching the query to be inserted or updated, long poll style.
+    /// Documents that already match are ignored until they change. Resolves with the
+    /// changed document, or `None` once the timeout passes.
     ///
     /// ```
     /// # use deeb::*;
     /// # use anyhow::Error;
     /// # use serde_json::json;
+    /// # use std::time::Duration;
     /// # #[tokio::main]
     /// # async fn main() -> Result<(), Error> {
-    /// # let user = Entity::new("user");
+    /// # let task = Entity::new("task");
     /// # let db = Deeb::new();
-    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
-    /// let mut transaction = db.begin_transaction().await;
-    /// db.insert(&user, json!({"id": 1, "name": "Steve", "age": 3}), Some(&mut transaction)).await?;
-    /// db.insert(&user, json!({"id": 2, "name": "Johnny", "age": 3}), Some(&mut transaction)).await?;
-    /// db.commit(&mut transaction).await?;
+    /// # db.add_instance("test", "./task.json", vec![task.clone()]).await?;
+    /// # db.insert(&task, json!({"id": 1, "status": "running"}), None).await?;
+    /// let query = Query::and(vec![Query::eq("id", 1), Query::eq("status", "done")]);
+    /// match db.wait_for_change(&task, query, Duration::from_millis(100)).await? {
+    ///     Some(task) => println!("Done: {}", task),
+    ///     None => println!("Still running"),
+    /// }
     /// # Ok(())
     /// # }
     /// ```
     #[allow(dead_code)]
-    pub async fn commit(&self, transaction: &mut Transaction) -> Result<(), Error> {
-        debug!("Committing transaction");
-        let mut db = self.db.write().await;
-        let mut executed = vec![];
-        for operation in transaction.operations.iter() {
-            let result = match operation {
-                Operation::InsertOne { entity, value } => db
-                    .insert(&entity, value.clone())
-                    .map(|value| (operation.clone(), ExecutedValue::InsertedOne(value))),
-                Operation::InsertMany { entity, values } => db
-                    .insert_many(&entity, values.clone())
-                    .map(|values| (operation.clone(), ExecutedValue::InsertedMany(values))),
-                Operation::FindOne { entity, query } => db
-                    .find_one(&entity, query.clone())
-                    .map(|_value| (operation.clone(), ExecutedValue::FoundOne)),
-                Operation::FindMany { entity, query } => db
-                    .find_many(&entity, query.clone())
-                    .map(|_values| (operation.clone(), ExecutedValue::FoundMany)),
-                Operation::DeleteOne { entity, query } => db
-                    .delete_one(&entity, query.clone())
-                    .map(|value| (operation.clone(), ExecutedValue::DeletedOne(value))),
-                Operation::DeleteMany { entity, query } => db
-                    .delete_many(&entity, query.clone())
+    pub async fn wait_for_change(
+        &self,
+        entity: &Entity,
+        query: Query,
+        timeout: Duration,
+    ) -> Result<Option<Value>, Error> {
+        debug!("Waiting for change");
+        let deadline = tokio::time::Instant::now() + timeout;
+        let changes = self.db.read().await.get_changes();
+        let mut seen = None;
+        loop {
+            // Register before reading so a write between the read and the wait is not missed.
+            let notified = changes.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            let db = self.db.read().await.snapshot();
+            let values = db.find_many(entity, query.clone(), None)?;
+            match &seen {
+                None => {
+                    seen = Some(values.iter().map(Value::to_string).collect::<HashSet<_>>());
+                }
+                Some(seen) => {
+                    if let Some(mut value) = values
+                        .into_iter()
+                        .find(|value| !seen.contains(&value.to_string()))
+                    {
+                        if self.redact {
+                            redaction::redact(entity, &query.associated_entities(), &mut value);
+                        }
+                        trace!("Changed value: {:?}", value);
+                        return Ok(Some(value));
+                    }
+                }
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Find the points of a [time series](crate::TimeSeries) entity with a timestamp in
+    /// `from..to`, in milliseconds since the Unix epoch. Either bound may be left open.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let reading = Entity::new("reading").time_series(TimeSeries::new("at", Duration::from_secs(60)));
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./reading.json", vec![reading.clone()]).await?;
+    /// db.insert(&reading, json!({"at": 1_000, "celsius": 21.5}), None).await?;
+    /// let last_hour = db.find_range(&reading, Some(0), None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn find_range(
+        &self,
+        entity: &Entity,
+        from: Option<i64>,
+        to: Option<i64>,
+    ) -> Result<Vec<Value>, Error> {
+        debug!("Finding range");
+        let db = self.db.read().await.snapshot();
+        let mut values = db.find_range(entity, from, to)?;
+        if self.redact {
+            for value in values.iter_mut() {
+                redaction::redact(entity, &[], value);
+            }
+        }
+        trace!("Found values: {:?}", values);
+        Ok(values)
+    }
+
+    /// Roll the points of a [time series](crate::TimeSeries) entity in `from..to` up into buckets
+    /// of its granularity, aggregating a numeric field. Each bucket is returned as
+    /// `{"timestamp", "count", "value"}`.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let reading = Entity::new("reading").time_series(TimeSeries::new("at", Duration::from_secs(60)));
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./reading.json", vec![reading.clone()]).await?;
+    /// let per_minute = db
+    ///     .downsample(&reading, None, None, "celsius", Aggregation::Avg)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn downsample(
+        &self,
+        entity: &Entity,
+        from: Option<i64>,
+        to: Option<i64>,
+        field: &str,
+        aggregation: Aggregation,
+    ) -> Result<Vec<Value>, Error> {
+        debug!("Downsampling");
+        let db = self.db.read().await.snapshot();
+        let values = db.downsample(entity, from, to, field, aggregation)?;
+        trace!("Downsampled values: {:?}", values);
+        Ok(values)
+    }
+
+    /// Delete a single value from the database.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
+    /// db.delete_one(&user, Query::eq("name", "Joey"), None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn delete_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        debug!("Deleting one");
+        if let Some(transaction) = transaction {
+            let operation = Operation::DeleteOne {
+                entity: entity.clone(),
+                query: query.clone(),
+            };
+            transaction.add_operation(operation);
+            return Ok(Value::Null);
+        }
+
+        let started = Instant::now();
+        let mut db = self.db.write().await;
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let (mut value, scanned) = db.delete_one_scanned(entity, query)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, [&value]);
+        trace!("Deleted value: {:?}", value);
+        self.redact_returned(entity, [&mut value]);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "delete_one",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(value)
+    }
+
+    /// Copy the documents matching a query into another entity, passing each through
+    /// `transform` first, and return how many were written. Use it for denormalizing and
+    /// backfilling. The documents are read and written under one write lock, and nothing is
+    /// written if any insert fails.
+    ///
+    /// `transform` sees the same fields a read would. Redacted fields of the source are
+    /// removed unless the handle is [unredacted](Deeb::unredacted), and encrypted fields of
+    /// the source are removed unless the destination encrypts the same field, so protected
+    /// values are never written to the destination in the clear.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let profile = Entity::new("profile");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone(), profile.clone()]).await?;
+    /// db.transform_into(&user, &profile, Query::All, |user| {
+    ///     json!({"user_id": user["id"], "display_name": user["name"]})
+    /// })
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn transform_into<F>(
+        &self,
+        source: &Entity,
+        destination: &Entity,
+        query: Query,
+        transform: F,
+    ) -> Result<usize, Error>
+    where
+        F: FnMut(Value) -> Value,
+    {
+        debug!("Transforming into");
+        let mut db = self.db.write().await;
+        let before = db.snapshot();
+        let values = db
+            .find_many(source, query, None)?
+            .into_iter()
+            .map(|mut value| {
+                if self.redact {
+                    redaction::redact(source, &[], &mut value);
+                }
+                for field in source.encrypted_fields.iter() {
+                    if !destination.encrypted_fields.contains(field) {
+                        path::remove(&mut value, &path::parse(field));
+                    }
+                }
+                value
+            })
+            .map(transform)
+            .collect::<Vec<_>>();
+        let count = values.len();
+        let options = InsertOptions::new().return_document(false);
+        if let Err(err) = db.insert_many(destination, values, options) {
+            *db = before;
+            return Err(err);
+        }
+        let name = db.get_instance_name_by_entity(destination)?;
+        db.commit_entity(destination, name, self.write_concern)?;
+        trace!("Transformed {} values", count);
+        Ok(count)
+    }
+
+    /// Delete multiple values from the database.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.delete_many(&user, Query::eq("age", 10), None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn delete_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        debug!("Deleting many");
+        if let Some(transaction) = transaction {
+            let operation = Operation::DeleteMany {
+                entity: entity.clone(),
+                query: query.clone(),
+            };
+            transaction.add_operation(operation);
+            return Ok(vec![]);
+        }
+
+        let started = Instant::now();
+        let mut db = self.db.write().await;
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let scanned = db.count_documents(entity);
+        let mut values = db.delete_many(entity, query)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, &values);
+        trace!("Deleted values: {:?}", values);
+        self.redact_returned(entity, &mut values);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "delete_many",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(values)
+    }
+
+    /// Delete every document of an entity at once and return how many were removed. The
+    /// entity's data is replaced rather than matched document by document, and the
+    /// instance is committed once. Truncating is not part of a transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.truncate(&user).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn truncate(&self, entity: &Entity) -> Result<usize, Error> {
+        debug!("Truncating");
+        let mut db = self.db.write().await;
+        let values = db.truncate(entity)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, &values);
+        trace!("Truncated {} values", values.len());
+        Ok(values.len())
+    }
+
+    /// Atomically remove and return the first matching document, or `None` if nothing
+    /// matches. Pass an order to choose which document comes first, otherwise documents
+    /// are taken in insertion order. Use this to consume a collection as a work queue.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let job = Entity::new("job");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./job.json", vec![job.clone()]).await?;
+    /// # db.insert(&job, json!({"id": 1, "priority": 2}), None).await?;
+    /// let next = db
+    ///     .pop_first(
+    ///         &job,
+    ///         Query::All,
+    ///         Some(vec![FindManyOrder::new("priority", OrderDirection::Descending)]),
+    ///     )
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn pop_first(
+        &self,
+        entity: &Entity,
+        query: Query,
+        order: Option<Vec<FindManyOrder>>,
+    ) -> Result<Option<Value>, Error> {
+        debug!("Popping first");
+        self.pop(entity, query, order, false).await
+    }
+
+    /// Atomically remove and return the last matching document, or `None` if nothing
+    /// matches. Pass an order to choose which document comes last, otherwise documents
+    /// are taken in insertion order.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let job = Entity::new("job");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./job.json", vec![job.clone()]).await?;
+    /// # db.insert(&job, json!({"id": 1, "priority": 2}), None).await?;
+    /// let newest = db.pop_last(&job, Query::All, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn pop_last(
+        &self,
+        entity: &Entity,
+        query: Query,
+        order: Option<Vec<FindManyOrder>>,
+    ) -> Result<Option<Value>, Error> {
+        debug!("Popping last");
+        self.pop(entity, query, order, true).await
+    }
+
+    async fn pop(
+        &self,
+        entity: &Entity,
+        query: Query,
+        order: Option<Vec<FindManyOrder>>,
+        last: bool,
+    ) -> Result<Option<Value>, Error> {
+        let mut db = self.db.write().await;
+        let mut value = db.pop(entity, query, order, last)?;
+        if value.is_some() {
+            let name = db.get_instance_name_by_entity(entity)?;
+            db.commit_entity(entity, name, self.write_concern)?;
+            db.remove_blobs(entity, &value);
+        }
+        trace!("Popped value: {:?}", value);
+        self.redact_returned(entity, &mut value);
+        Ok(value)
+    }
+
+    /// Update a single value in the database.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
+    /// db.update_one(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn update_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        self.update_one_with_mode(entity, query, update_value, UpdateMode::Merge, transaction)
+            .await
+    }
+
+    /// Update a single value in the database, choosing how the update is combined with
+    /// the document. Use [UpdateMode::MergePatch] for JSON Merge Patch (RFC 7386), where
+    /// objects merge recursively and `null` removes a field.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
+    /// db.update_one_with_mode(
+    ///     &user,
+    ///     Query::eq("age", 10),
+    ///     json!({"address": {"city": "Austin"}, "nickname": null}),
+    ///     UpdateMode::MergePatch,
+    ///     None,
+    /// )
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn update_one_with_mode(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        mode: UpdateMode,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        debug!("Updating one");
+        if let Some(transaction) = transaction {
+            let operation = Operation::UpdateOne {
+                entity: entity.clone(),
+                query: query.clone(),
+                value: update_value.clone(),
+                mode,
+            };
+            transaction.add_operation(operation);
+            return Ok(update_value);
+        }
+
+        let started = Instant::now();
+        let mut db = self.db.write().await;
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let before = db.get_blob_documents(entity);
+        let (mut value, scanned) = db.update_one_scanned(entity, query, update_value, mode)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, before.iter().flatten());
+        trace!("Updated value: {:?}", value);
+        self.redact_returned(entity, [&mut value]);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "update_one",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(value)
+    }
+
+    /// Apply a JSON Patch (RFC 6902) to a single value in the database. Supports the
+    /// `add`, `remove`, `replace`, `move`, `copy`, and `test` operations, and leaves the
+    /// value unchanged if any of them fails.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let profile = Entity::new("profile");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./profile.json", vec![profile.clone()]).await?;
+    /// # db.insert(&profile, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
+    /// let patch = json!([
+    ///     {"op": "replace", "path": "/age", "value": 11},
+    ///     {"op": "add", "path": "/tags", "value": ["birthday"]},
+    /// ]);
+    /// db.patch_one(&profile, Query::eq("id", 1), patch, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn patch_one(
+        &self,
+        entity: &Entity,
+        query: Query,
+        patch: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Value, Error> {
+        debug!("Patching one");
+        if let Some(transaction) = transaction {
+            let operation = Operation::PatchOne {
+                entity: entity.clone(),
+                query: query.clone(),
+                patch,
+            };
+            transaction.add_operation(operation);
+            return Ok(Value::Null);
+        }
+
+        let started = Instant::now();
+        let mut db = self.db.write().await;
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let before = db.get_blob_documents(entity);
+        let (mut value, scanned) = db.patch_one_scanned(entity, query, &patch)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, before.iter().flatten());
+        trace!("Patched value: {:?}", value);
+        self.redact_returned(entity, [&mut value]);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "patch_one",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(value)
+    }
+
+    /// Update multiple values in the database.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.update_many(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
+    /// db.update_many(&user, Query::eq("age", 10), json!({"age": 3}), None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn update_many(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        self.update_many_with_mode(entity, query, update_value, UpdateMode::Merge, transaction)
+            .await
+    }
+
+    /// Update multiple values in the database, choosing how the update is combined with
+    /// each document. Use [UpdateMode::MergePatch] for JSON Merge Patch (RFC 7386), where
+    /// objects merge recursively and `null` removes a field.
+    /// Passing a transaction will queue the operation to be executed later and
+    /// requires you to commit the transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// # db.insert(&user, json!({"id": 1, "name": "Joey", "age": 10}), None).await?;
+    /// db.update_many_with_mode(
+    ///     &user,
+    ///     Query::eq("age", 10),
+    ///     json!({"address": {"city": "Austin"}, "nickname": null}),
+    ///     UpdateMode::MergePatch,
+    ///     None,
+    /// )
+    /// .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn update_many_with_mode(
+        &self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+        mode: UpdateMode,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<Vec<Value>, Error> {
+        debug!("Updating many");
+        if let Some(transaction) = transaction {
+            let operation = Operation::UpdateMany {
+                entity: entity.clone(),
+                query: query.clone(),
+                value: update_value.clone(),
+                mode,
+            };
+            transaction.add_operation(operation);
+            return Ok(vec![]);
+        }
+
+        let started = Instant::now();
+        let mut db = self.db.write().await;
+        let lock_wait = started.elapsed();
+        let slow_query = db.get_slow_query_log(entity).map(|_| query.clone());
+        let before = db.get_blob_documents(entity);
+        let scanned = db.count_documents(entity);
+        let mut values = db.update_many(entity, query, update_value, mode)?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        db.remove_blobs(entity, before.iter().flatten());
+        trace!("Updated values: {:?}", values);
+        self.redact_returned(entity, &mut values);
+        if let Some(query) = slow_query {
+            Self::record_slow_query(
+                &db,
+                entity,
+                "update_many",
+                &query,
+                scanned,
+                started,
+                lock_wait,
+            );
+        }
+        Ok(values)
+    }
+
+    /// Log queries on an instance that take longer than the threshold. Pass `None` to
+    /// disable the slow query log.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.set_slow_query_log("test", Some(SlowQueryLog::new(Duration::from_millis(50))))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn set_slow_query_log<N>(
+        &self,
+        name: N,
+        slow_query_log: Option<SlowQueryLog>,
+    ) -> Result<&Self, Error>
+    where
+        N: Into<Name>,
+    {
+        debug!("Setting slow query log");
+        let mut db = self.db.write().await;
+        db.set_slow_query_log(&name.into(), slow_query_log)?;
+        Ok(self)
+    }
+
+    /// Check every document against its entity: primary keys are present and unique, and
+    /// association fields point at existing documents.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let report = db.verify_integrity().await;
+    /// for issue in report.issues.iter() {
+    ///     println!("{}", issue);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn verify_integrity(&self) -> IntegrityReport {
+        debug!("Verifying integrity");
+        let db = self.db.read().await.snapshot();
+        let report = db.verify_integrity();
+        trace!("Integrity report: {:?}", report);
+        report
+    }
+
+    /// Reserve field names on an instance so documents can not set them, leaving them to
+    /// metadata such as ids and timestamps. Entity defaults may still fill them. Pass
+    /// `None` to allow every field again.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.set_reserved_fields("test", Some(ReservedFields::new().prefix("_")))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn set_reserved_fields<N>(
+        &self,
+        name: N,
+        reserved_fields: Option<ReservedFields>,
+    ) -> Result<&Self, Error>
+    where
+        N: Into<Name>,
+    {
+        debug!("Setting reserved fields");
+        let mut db = self.db.write().await;
+        db.set_reserved_fields(&name.into(), reserved_fields)?;
+        Ok(self)
+    }
+
+    /// Keep writes to a high churn entity in memory and commit them at most once per
+    /// interval. Reads always see the latest in memory state. Writes made since the last
+    /// commit are lost if the process exits without calling [Deeb::flush]. Pass `None` to
+    /// commit on every write again. Setting the interval an entity already has does nothing,
+    /// and each entity has at most one task committing it.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let presence = Entity::new("presence");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./presence.json", vec![presence.clone()]).await?;
+    /// db.set_write_behind(&presence, Some(Duration::from_secs(5))).await;
+    /// db.insert(&presence, json!({"user": 1, "online": true}), None).await?;
+    /// // Before shutting down
+    /// db.flush().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn set_write_behind(&self, entity: &Entity, interval: Option<Duration>) -> &Self {
+        debug!("Setting write behind for {} to {:?}", entity.name, interval);
+        let mut db = self.db.write().await;
+        if interval.is_some() && db.get_write_behind(entity) == interval {
+            return self;
+        }
+        if interval.is_none() {
+            // Write anything held back before returning to commit on every write.
+            if let Err(err) = db.flush() {
+                error!("Failed to flush write behind entity: {:?}", err);
+            }
+        }
+        db.set_write_behind(entity, interval);
+        let generation = db.get_write_behind_generation(entity);
+        drop(db);
+
+        if let (Some(interval), Some(generation)) = (interval, generation) {
+            let db = Arc::downgrade(&self.db);
+            let entity = entity.clone();
+            tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    // Stop once the Deeb instance is dropped or the interval is set again.
+                    let Some(db) = db.upgrade() else {
+                        break;
+                    };
+                    let mut db = db.write().await;
+                    if db.get_write_behind_generation(&entity) != Some(generation) {
+                        break;
+                    }
+                    if let Err(err) = db.flush() {
+                        error!("Failed to flush write behind entity: {:?}", err);
+                    }
+                }
+            });
+        }
+        self
+    }
+
+    /// Commit every change held back by write behind entities. Call this before shutting
+    /// down so no writes are lost.
+    #[allow(dead_code)]
+    pub async fn flush(&self) -> Result<(), Error> {
+        debug!("Flushing");
+        let mut db = self.db.write().await;
+        db.flush()
+    }
+
+    /// Get the read, write, and scan counters of every entity used since the database
+    /// was created.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.find_many(&user, Query::All, None).await?;
+    /// let stats = db.stats().await;
+    /// assert!(stats[&user.name].reads >= 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn stats(&self) -> HashMap<EntityName, EntityStats> {
+        self.db.read().await.get_stats()
+    }
+
+    /// Store binary data as a blob file next to the entity's instance and get a reference
+    /// to put in a document. The file is removed once a write leaves no document in the
+    /// instance referencing it, so references can be shared and replaced.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let attachment = Entity::new("attachment");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./attachment.json", vec![attachment.clone()]).await?;
+    /// let file = db.put_blob(&attachment, b"%PDF-1.7").await?;
+    /// let inserted = db
+    ///     .insert(&attachment, json!({"name": "report.pdf", "file": file}), None)
+    ///     .await?;
+    /// let bytes = db.get_blob(&attachment, &inserted["file"]).await?;
+    /// assert_eq!(bytes, b"%PDF-1.7");
+    /// # db.delete_many(&attachment, Query::All, None).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn put_blob(&self, entity: &Entity, bytes: &[u8]) -> Result<Value, Error> {
+        debug!("Putting blob");
+        let dir = self.db.read().await.get_blob_dir(entity)?;
+        blob::write_blob(&dir, bytes)
+    }
+
+    /// Read the blob a reference made by [Deeb::put_blob] points to.
+    #[allow(dead_code)]
+    pub async fn get_blob(&self, entity: &Entity, reference: &Value) -> Result<Vec<u8>, Error> {
+        debug!("Getting blob");
+        let dir = self.db.read().await.get_blob_dir(entity)?;
+        blob::read_blob(&dir, reference)
+    }
+
+    /// Hide the redacted fields of documents returned by a write, unless the handle is
+    /// unredacted.
+    fn redact_returned<'a, I>(&self, entity: &Entity, documents: I)
+    where
+        I: IntoIterator<Item = &'a mut Value>,
+    {
+        if self.redact {
+            for document in documents {
+                redaction::redact(entity, &[], document);
+            }
+        }
+    }
+
+    fn record_slow_query(
+        db: &Database,
+        entity: &Entity,
+        operation: &str,
+        query: &Query,
+        scanned: usize,
+        started: Instant,
+        lock_wait: Duration,
+    ) {
+        let duration = started.elapsed();
+        if let Err(err) =
+            db.record_slow_query(entity, operation, query, scanned, duration, lock_wait)
+        {
+            error!("Failed to record slow query: {:?}", err);
+        }
+    }
+
+    /// Get a key value store kept in the `_kv` entity of an instance.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let db = Deeb::new();
+    /// db.add_instance("settings", "./settings.json", vec![]).await?;
+    /// let visits = db.kv("settings").incr("visits", 1).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn kv<N>(&self, name: N) -> Kv
+    where
+        N: Into<Name>,
+    {
+        Kv::new(self.db.clone(), name.into())
+    }
+
+    /// Take an advisory lock on the document with the given id, or get `None` if another
+    /// holder has an unexpired lock on it. The lock is kept in the `_locks` entity of the
+    /// document's instance and expires after `ttl`.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let document = Entity::new("document");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./document.json", vec![document.clone()]).await?;
+    /// if let Some(lock) = db.lock_document(&document, 1, Duration::from_secs(30)).await? {
+    ///     // Edit the document.
+    ///     lock.release().await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn lock_document<I>(
+        &self,
+        entity: &Entity,
+        id: I,
+        ttl: Duration,
+    ) -> Result<Option<DocumentLock>, Error>
+    where
+        I: Into<Value>,
+    {
+        DocumentLock::acquire(self.db.clone(), entity, id.into(), ttl).await
+    }
+
+    /// Publish a message to the outbox of the entity's instance. Pass the transaction of
+    /// the change the message describes, so the message is written only if the change is.
+    /// Consumers read the outbox with [Deeb::poll_outbox].
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let payment = Entity::new("payment");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./outbox.json", vec![payment.clone()]).await?;
+    /// let mut transaction = db.begin_transaction().await;
+    /// let value = json!({"id": 1, "amount": 100});
+    /// db.insert(&payment, value.clone(), Some(&mut transaction)).await?;
+    /// db.publish_outbox(&payment, "payment.created", value, Some(&mut transaction)).await?;
+    /// db.commit(&mut transaction).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn publish_outbox(
+        &self,
+        entity: &Entity,
+        topic: &str,
+        payload: Value,
+        transaction: Option<&mut Transaction>,
+    ) -> Result<OutboxMessage, Error> {
+        debug!("Publishing to outbox");
+        let message = OutboxMessage::new(&entity.name, topic, payload);
+        if let Some(transaction) = transaction {
+            let operation = Operation::PublishOutbox {
+                entity: entity.clone(),
+                message: message.clone(),
+            };
+            transaction.add_operation(operation);
+            return Ok(message);
+        }
+
+        let mut db = self.db.write().await;
+        db.publish_outbox(entity, message.clone())?;
+        let name = db.get_instance_name_by_entity(entity)?;
+        db.commit_entity(entity, name, self.write_concern)?;
+        Ok(message)
+    }
+
+    /// Claim up to `limit` outbox messages of an instance, oldest first. A claimed message
+    /// is not returned by another poll until `visibility` passes, and is delivered again
+    /// then unless it was acknowledged with [Deeb::ack_outbox].
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use std::time::Duration;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let payment = Entity::new("payment");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./outbox.json", vec![payment.clone()]).await?;
+    /// for message in db.poll_outbox("test", 10, Duration::from_secs(30)).await? {
+    ///     // Send the message to the queue.
+    ///     db.ack_outbox("test", &[message.id]).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn poll_outbox<N>(
+        &self,
+        name: N,
+        limit: usize,
+        visibility: Duration,
+    ) -> Result<Vec<OutboxMessage>, Error>
+    where
+        N: Into<Name>,
+    {
+        debug!("Polling outbox");
+        let name = name.into();
+        let mut db = self.db.write().await;
+        let messages = db.claim_outbox(&name, limit, visibility)?;
+        if !messages.is_empty() {
+            db.commit(vec![name])?;
+        }
+        trace!("Claimed messages: {:?}", messages);
+        Ok(messages)
+    }
+
+    /// Acknowledge delivered outbox messages, removing them from the outbox. Returns how
+    /// many messages were removed.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let payment = Entity::new("payment");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./outbox.json", vec![payment.clone()]).await?;
+    /// let acked = db.ack_outbox("test", &["9b2f0c1e".to_string()]).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn ack_outbox<N>(&self, name: N, ids: &[String]) -> Result<usize, Error>
+    where
+        N: Into<Name>,
+    {
+        debug!("Acknowledging outbox messages");
+        let name = name.into();
+        let mut db = self.db.write().await;
+        let acked = db.ack_outbox(&name, ids)?;
+        if acked > 0 {
+            db.commit(vec![name])?;
+        }
+        Ok(acked)
+    }
+
+    /// Start a batch of writes that are applied in memory immediately and acknowledged
+    /// once a group commit has fsynced them. Use this to trade a short durability window
+    /// for throughput.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let mut batch = db.write_batch();
+    /// let ack = batch.insert(&user, json!({"id": 1, "name": "Joey"})).await?;
+    /// batch.flush().await?;
+    /// ack.acknowledged().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub fn write_batch(&self) -> WriteBatch {
+        WriteBatch::new(self.db.clone())
+    }
+
+    // Handle Transaction
+    /// Begin a new transaction.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let db = Deeb::new();
+    /// let mut transaction = db.begin_transaction().await;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn begin_transaction(&self) -> Transaction {
+        debug!("Beginning transaction");
+        Transaction::new()
+    }
+
+    /// Commit a transaction. Once a transaction is committed, all operations will be executed and
+    /// the JSON file will be updated. If an operation fails, the database is restored to its
+    /// state before the commit and the error is returned.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let mut transaction = db.begin_transaction().await;
+    /// db.insert(&user, json!({"id": 1, "name": "Steve", "age": 3}), Some(&mut transaction)).await?;
+    /// db.insert(&user, json!({"id": 2, "name": "Johnny", "age": 3}), Some(&mut transaction)).await?;
+    /// db.commit(&mut transaction).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn commit(&self, transaction: &mut Transaction) -> Result<(), Error> {
+        debug!("Committing transaction");
+        let mut db = self.db.write().await;
+        let before = db.snapshot();
+        let mut executed = vec![];
+        for operation in transaction.operations.iter() {
+            let result = match operation {
+                Operation::InsertOne { entity, value } => db
+                    .insert(entity, value.clone())
+                    .map(|value| (operation.clone(), ExecutedValue::InsertedOne(value))),
+                Operation::InsertMany { entity, values } => db
+                    .insert_many(entity, values.clone(), InsertOptions::default())
+                    .map(|values| (operation.clone(), ExecutedValue::InsertedMany(values))),
+                Operation::FindOne { entity, query } => db
+                    .find_one(entity, query.clone())
+                    .map(|_value| (operation.clone(), ExecutedValue::FoundOne)),
+                Operation::FindMany { entity, query } => db
+                    .find_many(entity, query.clone(), None)
+                    .map(|_values| (operation.clone(), ExecutedValue::FoundMany)),
+                Operation::DeleteOne { entity, query } => db
+                    .delete_one(entity, query.clone())
+                    .map(|value| (operation.clone(), ExecutedValue::DeletedOne(value))),
+                Operation::DeleteMany { entity, query } => db
+                    .delete_many(entity, query.clone())
                     .map(|values| (operation.clone(), ExecutedValue::DeletedMany(values))),
                 Operation::UpdateOne {
                     entity,
                     query,
                     value,
+                    mode,
                 } => db
-                    .update_one(&entity, query.clone(), value.clone())
+                    .update_one(entity, query.clone(), value.clone(), *mode)
                     .map(|value| (operation.clone(), ExecutedValue::UpdatedOne(value))),
                 Operation::UpdateMany {
                     entity,
                     query,
                     value,
+                    mode,
                 } => db
-                    .update_many(&entity, query.clone(), value.clone())
+                    .update_many(entity, query.clone(), value.clone(), *mode)
                     .map(|values| (operation.clone(), ExecutedValue::UpdatedMany(values))),
+                Operation::PatchOne {
+                    entity,
+                    query,
+                    patch,
+                } => db
+                    .patch_one(entity, query.clone(), patch)
+                    .map(|value| (operation.clone(), ExecutedValue::PatchedOne(value))),
+                Operation::PublishOutbox { entity, message } => {
+                    db.publish_outbox(entity, message.clone()).map(|_| {
+                        let executed_value = ExecutedValue::PublishedOutbox(message.clone());
+                        (operation.clone(), executed_value)
+                    })
+                }
                 Operation::DropKey { entity, key } => db
-                    .drop_key(&entity, &key)
+                    .drop_key(entity, key)
                     .map(|_value| (operation.clone(), ExecutedValue::DroppedKey)),
                 Operation::AddKey { entity, key, value } => db
-                    .add_key(&entity, &key, value.clone())
+                    .add_key(entity, key, value.clone(), AddKeyStrategy::Overwrite)
                     .map(|_value| (operation.clone(), ExecutedValue::AddedKey)),
             };
             trace!("Executed operation: {:?}", operation);
@@ -515,8 +2027,8 @@ impl Deeb {
                 Ok(executed_value) => executed.push(executed_value),
                 Err(err) => {
                     trace!("Error occurred: {:?}", err);
-                    drop(db);
-                    self.rollback(&mut executed).await?;
+                    // Undo every executed operation before other handles can see them.
+                    *db = before;
                     return Err(err);
                 }
             }
@@ -527,77 +2039,45 @@ impl Deeb {
             trace!("Getting names");
             let entity = match operation {
                 Operation::InsertOne { entity, .. } => entity,
+                Operation::InsertMany { entity, .. } => entity,
                 Operation::DeleteOne { entity, .. } => entity,
                 Operation::DeleteMany { entity, .. } => entity,
+                Operation::UpdateOne { entity, .. } => entity,
+                Operation::UpdateMany { entity, .. } => entity,
+                Operation::PatchOne { entity, .. } => entity,
+                Operation::PublishOutbox { entity, .. } => entity,
+                Operation::DropKey { entity, .. } => entity,
+                Operation::AddKey { entity, .. } => entity,
                 _ => continue,
             };
             let name = db.get_instance_name_by_entity(entity).unwrap();
-            names.push(name);
+            if !names.contains(&name) {
+                names.push(name);
+            }
         }
         trace!("Names: {:?}", names);
 
         db.commit(names)?;
         trace!("Executed operations: {:?}", executed);
-        Ok(())
-    }
-
-    async fn rollback(&self, executed: &mut Vec<(Operation, ExecutedValue)>) -> Result<(), Error> {
-        debug!("Rolling back transaction");
-        let mut db = self.db.write().await;
-        for (operation, executed_value) in executed.iter().rev() {
-            match operation {
-                Operation::InsertOne { entity, .. } => match executed_value {
-                    ExecutedValue::InsertedOne(value) => {
-                        let query = Query::and(
-                            value
-                                .as_object()
-                                .unwrap()
-                                .iter()
-                                .map(|(key, value)| {
-                                    Query::Eq(key.clone().as_str().into(), value.clone())
-                                })
-                                .collect::<Vec<_>>(),
-                        );
-                        db.delete_one(&entity, query)?;
-                    }
-                    _ => {}
-                },
-                Operation::InsertMany { entity, .. } => match executed_value {
-                    ExecutedValue::InsertedMany(values) => {
-                        for value in values.iter() {
-                            let query = Query::and(
-                                value
-                                    .as_object()
-                                    .unwrap()
-                                    .iter()
-                                    .map(|(key, value)| {
-                                        Query::Eq(key.clone().as_str().into(), value.clone())
-                                    })
-                                    .collect::<Vec<_>>(),
-                            );
-                            db.delete_one(&entity, query)?;
-                        }
-                    }
-                    _ => {}
-                },
-                Operation::DeleteOne { entity, .. } => match executed_value {
-                    ExecutedValue::DeletedOne(value) => {
-                        db.insert(&entity, value.clone()).unwrap();
-                    }
-                    _ => {}
-                },
-                Operation::DeleteMany { entity, .. } => match executed_value {
-                    ExecutedValue::DeletedMany(values) => {
-                        for value in values.iter() {
-                            db.insert(&entity, value.clone()).unwrap();
-                        }
-                    }
-                    _ => {}
-                },
+        for (operation, executed_value) in executed.iter() {
+            match (operation, executed_value) {
+                (Operation::DeleteOne { entity, .. }, ExecutedValue::DeletedOne(value)) => {
+                    db.remove_blobs(entity, [value]);
+                }
+                (Operation::DeleteMany { entity, .. }, ExecutedValue::DeletedMany(values)) => {
+                    db.remove_blobs(entity, values);
+                }
+                (Operation::UpdateOne { entity, .. }, _)
+                | (Operation::UpdateMany { entity, .. }, _)
+                | (Operation::PatchOne { entity, .. }, _)
+                | (Operation::DropKey { entity, .. }, _)
+                | (Operation::AddKey { entity, .. }, _) => {
+                    let replaced = before.get_blob_documents(entity);
+                    db.remove_blobs(entity, replaced.iter().flatten());
+                }
                 _ => {}
             }
         }
-        trace!("Rolled back operations");
         Ok(())
     }
 
@@ -637,9 +2117,12 @@ impl Deeb {
         // }
 
         let mut db = self.db.write().await;
-        db.drop_key(entity, key)?;
+        let before = db.get_blob_documents(entity);
+        let change = db.drop_key(entity, key)?;
+        db.record_schema_change(change)?;
         let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
+        db.commit(vec![name, Name::from("_meta")])?;
+        db.remove_blobs(entity, before.iter().flatten());
         Ok(())
     }
 
@@ -679,10 +2162,175 @@ impl Deeb {
         //     transaction.add_operation(operation);
         //     return Ok(());
         // }
+        self.add_key_with_strategy(entity, key, value, AddKeyStrategy::Overwrite)
+            .await?;
+        Ok(())
+    }
+
+    /// Add key to every entity in the database, choosing what happens to documents where
+    /// the key already exists or a value on the path is not an object.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let report = db
+    ///     .add_key_with_strategy(&user, "address.zip", 10001, AddKeyStrategy::Skip)
+    ///     .await?;
+    /// println!("Added to {} documents", report.touched(AddKeyStrategy::Skip));
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn add_key_with_strategy<V>(
+        &self,
+        entity: &Entity,
+        key: &str,
+        value: V,
+        strategy: AddKeyStrategy,
+    ) -> Result<AddKeyReport, Error>
+    where
+        V: Into<Value>,
+    {
+        debug!("Adding key with strategy {:?}", strategy);
+        let value = value.into();
         let mut db = self.db.write().await;
-        db.add_key(entity, key, value.into())?;
+        let before = db.get_blob_documents(entity);
+        let report = db.add_key(entity, key, value.clone(), strategy)?;
+        let change = SchemaChange::add_key(&entity.name, key, value, strategy, report);
+        db.record_schema_change(change)?;
         let name = db.get_instance_name_by_entity(entity)?;
-        db.commit(vec![name])?;
+        db.commit(vec![name, Name::from("_meta")])?;
+        db.remove_blobs(entity, before.iter().flatten());
+        Ok(report)
+    }
+
+    /// Report how many documents adding a key would touch, without changing them.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// let report = db.add_key_dry_run(&user, "address.zip").await?;
+    /// if report.conflicts == 0 {
+    ///     db.add_key_with_strategy(&user, "address.zip", 10001, AddKeyStrategy::Fail)
+    ///         .await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn add_key_dry_run(&self, entity: &Entity, key: &str) -> Result<AddKeyReport, Error> {
+        debug!("Reporting add key");
+        let db = self.db.read().await;
+        db.add_key_report(entity, key)
+    }
+
+    /// Get the `add_key` and `drop_key` operations recorded in the schema log, oldest
+    /// first.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let db = Deeb::new();
+    /// for change in db.schema_log().await? {
+    ///     println!("{:?} changed {} documents", change.operation, change.documents);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn schema_log(&self) -> Result<Vec<SchemaChange>, Error> {
+        debug!("Reading schema log");
+        let db = self.db.read().await.snapshot();
+        let Some(data) =
+            db.get_instance_data(&Name::from("_meta"), &EntityName::from(SCHEMA_LOG_ENTITY))?
+        else {
+            return Ok(vec![]);
+        };
+        data.iter()
+            .map(|value| serde_json::from_value(value.clone()).map_err(Error::from))
+            .collect()
+    }
+
+    /// Undo the last `add_key` or `drop_key` recorded in the schema log and remove it from
+    /// the log. Returns `None` when the log is empty, and an error when the last change
+    /// has no reverse. See [SchemaChange] for which changes can be undone.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # use serde_json::json;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user").primary_key("id");
+    /// # let db = Deeb::new();
+    /// # db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.drop_key(&user, "age").await?;
+    /// db.undo_last_schema_change().await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn undo_last_schema_change(&self) -> Result<Option<SchemaChange>, Error> {
+        debug!("Undoing last schema change");
+        let mut db = self.db.write().await;
+        let Some((change, name)) = db.undo_last_schema_change()? else {
+            return Ok(None);
+        };
+        db.commit(vec![name, Name::from("_meta")])?;
+        trace!("Undid schema change: {:?}", change);
+        Ok(Some(change))
+    }
+
+    /// Merge the instances and entities defined in code into an instance config file, so
+    /// the file can be generated instead of maintained by hand. Keys the entities do not
+    /// define, such as server settings, are kept. The file is created when it does not
+    /// exist. See [merge_instance_config](crate::merge_instance_config) for how entries
+    /// are matched.
+    ///
+    /// ```
+    /// # use deeb::*;
+    /// # use anyhow::Error;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Error> {
+    /// # let user = Entity::new("user");
+    /// # let db = Deeb::new();
+    /// db.add_instance("test", "./user.json", vec![user.clone()]).await?;
+    /// db.sync_instance_config_from_entities("./instances.json").await?;
+    /// # std::fs::remove_file("./instances.json")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[allow(dead_code)]
+    pub async fn sync_instance_config_from_entities(&self, path: &str) -> Result<(), Error> {
+        debug!("Syncing instance config");
+        let instances = self.db.read().await.get_instances();
+        let mut config = match std::fs::read_to_string(path) {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Value::Array(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        let instances = instances
+            .iter()
+            .map(|(name, file_path, entities)| {
+                (name.as_str(), file_path.as_str(), entities.clone())
+            })
+            .collect::<Vec<_>>();
+        merge_instance_config(&mut config, &instances)?;
+        std::fs::write(path, serde_json::to_string_pretty(&config)?)?;
         Ok(())
     }
 
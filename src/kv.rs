@@ -0,0 +1,111 @@
+use anyhow::Error;
+use log::*;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::database::{entity::EntityName, name::Name, Database};
+
+const KV_ENTITY: &str = "_kv";
+
+/// A key value store kept in the `_kv` entity of an instance.
+///
+/// Each key is stored as a `{"key": ..., "value": ...}` document, so the values live in
+/// the same JSON file as the rest of the instance. Every operation holds the write lock
+/// for its whole read and modify, making [Kv::incr] atomic.
+///
+/// ```
+/// # use deeb::*;
+/// # use anyhow::Error;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// # let db = Deeb::new();
+/// db.add_instance("settings", "./settings.json", vec![]).await?;
+/// let kv = db.kv("settings");
+/// kv.set("theme", &"dark").await?;
+/// let theme: Option<String> = kv.get("theme").await?;
+/// assert_eq!(theme.as_deref(), Some("dark"));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Kv {
+    db: Arc<RwLock<Database>>,
+    name: Name,
+}
+
+impl Kv {
+    pub(crate) fn new(db: Arc<RwLock<Database>>, name: Name) -> Self {
+        Self { db, name }
+    }
+
+    /// Get the value of a key, or `None` if it is not set.
+    pub async fn get<T>(&self, key: &str) -> Result<Option<T>, Error>
+    where
+        T: DeserializeOwned,
+    {
+        debug!("Getting kv `{}`", key);
+        let db = self.db.read().await;
+        let Some(data) = db.get_instance_data(&self.name, &EntityName::from(KV_ENTITY))? else {
+            return Ok(None);
+        };
+        data.iter()
+            .find(|document| document["key"] == key)
+            .map(|document| serde_json::from_value(document["value"].clone()))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    /// Set the value of a key, replacing any existing value.
+    pub async fn set<T>(&self, key: &str, value: &T) -> Result<(), Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        debug!("Setting kv `{}`", key);
+        let value = serde_json::to_value(value)?;
+        let mut db = self.db.write().await;
+        let data = db.get_instance_data_mut(&self.name, &EntityName::from(KV_ENTITY))?;
+        match data.iter_mut().find(|document| document["key"] == key) {
+            Some(document) => document["value"] = value,
+            None => data.push_back(json!({"key": key, "value": value})),
+        }
+        db.commit(vec![self.name.clone()])
+    }
+
+    /// Delete a key. Returns `true` if the key was set.
+    pub async fn delete(&self, key: &str) -> Result<bool, Error> {
+        debug!("Deleting kv `{}`", key);
+        let mut db = self.db.write().await;
+        let data = db.get_instance_data_mut(&self.name, &EntityName::from(KV_ENTITY))?;
+        let Some(index) = data.iter().position(|document| document["key"] == key) else {
+            return Ok(false);
+        };
+        data.remove(index);
+        db.commit(vec![self.name.clone()])?;
+        Ok(true)
+    }
+
+    /// Add to the integer value of a key and return the new value. A missing key starts
+    /// at zero.
+    pub async fn incr(&self, key: &str, by: i64) -> Result<i64, Error> {
+        debug!("Incrementing kv `{}` by {}", key, by);
+        let mut db = self.db.write().await;
+        let data = db.get_instance_data_mut(&self.name, &EntityName::from(KV_ENTITY))?;
+        let index = data.iter().position(|document| document["key"] == key);
+        let current = match index {
+            Some(index) => data[index]["value"]
+                .as_i64()
+                .ok_or_else(|| Error::msg(format!("Value of `{}` is not an integer", key)))?,
+            None => 0,
+        };
+        let value = current
+            .checked_add(by)
+            .ok_or_else(|| Error::msg(format!("Incrementing `{}` overflowed", key)))?;
+        match index {
+            Some(index) => data[index]["value"] = Value::from(value),
+            None => data.push_back(json!({"key": key, "value": value})),
+        }
+        db.commit(vec![self.name.clone()])?;
+        Ok(value)
+    }
+}
@@ -79,6 +79,12 @@
 //! - **Schemaless**: Deeb is schemaless
 //! - **Transactions**: Deeb supports transactions
 //! - **Querying**: Deeb supports querying, nested queries, and combination queries.
+//! - **Exact Numbers**: Numbers are compared exactly. Enable the `arbitrary_precision`
+//!   feature to keep numbers beyond `u64` and `f64` at full precision.
+//! - **SQL**: Enable the `sql` feature to run read only `SELECT` statements with
+//!   `Deeb::sql`.
+//! - **Arrow**: Enable the `arrow` feature to export typed fields as Arrow record batches
+//!   with `Deeb::to_arrow`, for Polars or DataFusion.
 //!
 //! ## Roadmap
 //!
@@ -101,17 +107,34 @@
 //! ### Operations
 //!
 //! - `insert`: [Insert](deeb::Deeb::insert) a new document into the database
+//! - `insert_many_with_options`: [Insert multiple](deeb::Deeb::insert_many_with_options) documents with [InsertOptions], skipping the returned copies for bulk ingestion
 //! - `find_one`: [Find](deeb::Deeb::find_one) a single document in the database
 //! - `find_many`: [Find multiple](deeb::Deeb::find_many) documents in the database
+//! - `find_many_with_options`: [Find multiple](deeb::Deeb::find_many_with_options) documents sorted and paged with [FindManyOptions::builder]
+//! - `find_page`: [Find a page](deeb::Deeb::find_page) of documents with the total number of matches and a cursor for the next page
 //! - `update_one`: [Update a single](deeb::Deeb::update_one) document in the database
 //! - `update_many`: [Update multiple](deeb::Deeb::update_many) documents in the database
+//! - `update_one_with_mode` / `update_many_with_mode`: [Update with JSON Merge Patch](deeb::Deeb::update_one_with_mode) semantics, where `null` removes a field
+//! - `patch_one`: [Apply a JSON Patch](deeb::Deeb::patch_one) to a single document in the database
 //! - `delete_one`: [Delete a single](deeb::Deeb::delete_one) document in the database
 //! - `delete_many`: [Delete multiple](deeb::Deeb::delete_many) documents in the database
+//! - `truncate`: [Delete every](deeb::Deeb::truncate) document of an entity at once
+//! - `transform_into`: [Copy matching documents](deeb::Deeb::transform_into) into another entity through a mapping closure
+//! - `pop_first` / `pop_last`: [Remove and return](deeb::Deeb::pop_first) one matching document atomically
+//! - `to_arrow`: Export matching documents as an Arrow record batch of their typed fields with `Deeb::to_arrow`. Requires the `arrow` feature.
+//! - `wait_for_change`: [Wait for](deeb::Deeb::wait_for_change) a matching document to be inserted or updated, with a timeout
 //!
 //! ### Queries
 //!
+//! Keys are dot paths into nested documents. A number selects an array element, as in
+//! `items.0.sku` or `items[0].sku`, and `*` or `[*]` matches every value of a map or
+//! element of an array. The same paths work for `add_key`, `drop_key`, sorting, and
+//! redacted and encrypted fields.
+//!
 //! - `eq`: [Equal](database::query::Query::eq) - Find documents based on exact match.
 //! - `like`: [Like](database::query::Query::like) - Find documents based on like match.
+//! - `like_with`: [Like With](database::query::Query::like_with) - Find documents based on like match with case, anchor, and wildcard options.
+//! - `has_key`: [Has Key](database::query::Query::has_key) - Find documents where a map has a key.
 //! - `ne`: [Not Equal](database::query::Query::ne) - Find documents based on not equal match.
 //! - `gt`: [Greater Than](database::query::Query::gt) - Find documents based on greater than match.
 //! - `lt`: [Less Than](database::query::Query::lt) - Find documents based on less than match.
@@ -121,21 +144,148 @@
 //! - `or`: [Or](database::query::Query::or) - Find documents based on multiple conditions.
 //! - `all`: [All](database::query::Query::all) - Return all documents.
 //! - `associated`: [Associated](database::query::Query::associated) - Find documents based on association.
+//! - `order_by_cost`: [Order by cost](database::query::Query::order_by_cost) - Reorder `and` and `or` to check their cheapest queries first. They otherwise check queries in the order written.
+//! - `PreparedQuery`: [Prepared](database::prepared_query::PreparedQuery) - Build a query once with [param] placeholders and bind values per execution.
+//! - `traverse`: [Traverse](deeb::Deeb::traverse) - Follow associations across several entities in one call.
+//! - `sql`: Run a read only SQL `SELECT` with `Deeb::sql`, translated into queries, find options, and aggregates. Requires the `sql` feature.
 //!
 //! ### Transactions
 //!
 //! - `begin_transaction`: [Begin](deeb::Deeb::begin_transaction) a new transaction
 //! - `commit`: [Commit](deeb::Deeb::commit) a transaction
 //!
+//! ### Binary Data
+//!
+//! - `base64_bytes`: [Store small binary fields](database::blob::base64_bytes) as base64 strings with `#[serde(with = "deeb::base64_bytes")]`.
+//! - `put_blob`: [Store larger binary data](deeb::Deeb::put_blob) in a blob file referenced from a document, removed when the document is deleted.
+//! - `get_blob`: [Read a blob](deeb::Deeb::get_blob) from its reference.
+//!
+//! ### Trees
+//!
+//! - `tree`: [Treat an entity as a tree](database::entity::Entity::tree) of documents pointing at their parent, optionally keeping a materialized path.
+//! - `find_descendants`: [Find every document below](deeb::Deeb::find_descendants) a document.
+//! - `find_ancestors`: [Find the ancestors](deeb::Deeb::find_ancestors) of a document.
+//!
+//! ### Time Series
+//!
+//! - `time_series`: [Store an entity as a time series](database::entity::Entity::time_series), kept sorted by timestamp with optional retention.
+//! - `find_range`: [Find points](deeb::Deeb::find_range) between two timestamps.
+//! - `downsample`: [Aggregate points](deeb::Deeb::downsample) into buckets of the series granularity.
+//!
+//! ### Key Value
+//!
+//! - `kv`: [Get and set values by key](deeb::Deeb::kv) in the `_kv` entity of an instance.
+//!
+//! ### Locks
+//!
+//! - `lock_document`: [Take an advisory lock](deeb::Deeb::lock_document) on a single document, kept in the `_locks` entity of its instance.
+//!
+//! ### Outbox
+//!
+//! - `publish_outbox`: [Publish a message](deeb::Deeb::publish_outbox) to the `_outbox` entity of an instance, in the same transaction as the change it describes.
+//! - `poll_outbox`: [Claim messages](deeb::Deeb::poll_outbox) for delivery to an external system, at least once.
+//! - `ack_outbox`: [Acknowledge delivered messages](deeb::Deeb::ack_outbox), removing them from the outbox.
+//!
 //! ### Data Management
 //!
 //! - `add_key` : [Add a new key](deeb::Deeb::add_key) to the database
+//! - `add_key_with_strategy` : [Add a new key](deeb::Deeb::add_key_with_strategy), skipping or failing on conflicting documents
+//! - `add_key_dry_run` : [Report](deeb::Deeb::add_key_dry_run) how many documents adding a key would touch
 //! - `drop_key` : [Drop a key](deeb::Deeb::drop_key) from the database
+//! - `schema_log`: [List the recorded](deeb::Deeb::schema_log) `add_key` and `drop_key` operations
+//! - `undo_last_schema_change`: [Undo](deeb::Deeb::undo_last_schema_change) the last recorded `add_key` or `drop_key` where possible
+//! - `verify_integrity`: [Check](deeb::Deeb::verify_integrity) that primary keys are present and unique, and association fields point at existing documents.
+//!
+//! ### Schema Import
+//!
+//! - `entities_from_json_schema`: [Create entities](database::json_schema::entities_from_json_schema) from a JSON Schema or OpenAPI document.
+//!
+//! ### Schema Diff
+//!
+//! - `SchemaDiff`: [Compare two versions](database::schema_diff::SchemaDiff) of an instance config, reporting entity, index, and association changes and planning the migration.
+//! - `entities_from_config`: [Read the entities](database::schema_diff::entities_from_config) of an instance config or `_meta.json`.
+//! - `sync_instance_config_from_entities`: [Write the entities](deeb::Deeb::sync_instance_config_from_entities) defined in code into an instance config file, keeping settings they do not define.
+//!
+//! ### Durability
+//!
+//! - `write_batch`: [Batch writes](deeb::Deeb::write_batch) and await their acknowledgment once fsynced
+//! - `set_write_behind`: [Commit high churn entities](deeb::Deeb::set_write_behind) at most once per interval.
+//! - `flush`: [Commit held back writes](deeb::Deeb::flush) before shutting down.
+//! - `with_write_concern`: [Choose per call](deeb::Deeb::with_write_concern) whether writes stay in memory, are flushed, or are fsynced.
+//!
+//! ### Logging
+//!
+//! - `set_slow_query_log`: [Log slow queries](deeb::Deeb::set_slow_query_log) on an instance.
+//! - `stats`: [Count reads, writes, and scans](deeb::Deeb::stats) per entity.
+//!
+//! ### Defaults
+//!
+//! - `default_value`: [Set a default](database::entity::Entity::default_value) for a field missing from an inserted document.
+//! - `set_reserved_fields`: [Reserve field names](deeb::Deeb::set_reserved_fields) on an instance, rejecting or stripping them from written documents.
+//! - `field_type`: [Require a type](database::entity::Entity::field_type) for a field on insert and update, optionally [coercing](database::entity::Entity::coerce_types) mismatched values.
+//!
+//! ### Encryption
+//!
+//! - `encrypted_fields`: [Encrypt fields](database::entity::Entity::encrypted_fields) of an entity before they are written to disk.
+//! - `set_key_provider`: [Set the key provider](deeb::Deeb::set_key_provider) used to encrypt and decrypt fields.
+//!
+//! ### Redaction
+//!
+//! - `redacted_fields`: [Hide fields](database::entity::Entity::redacted_fields) from documents returned by reads and writes. Queries can still filter on them.
+//! - `unredacted`: [Read redacted fields](deeb::Deeb::unredacted) when the reader is allowed to see them.
+//!
+//! ### Backends
+//!
+//! - `DeebBackend`: [Trait](backend::DeebBackend) implemented by the embedded `Deeb` instance,
+//!   allowing application code to switch between embedded and remote backends.
 
+mod backend;
 mod database;
 mod deeb;
+mod kv;
+mod lock;
+mod write_batch;
 
+#[cfg(feature = "arrow")]
+pub use crate::database::arrow::to_record_batch;
+#[cfg(feature = "sql")]
+pub use crate::database::sql::Select;
 pub use crate::{
-    database::{entity::Entity, query::Query},
+    backend::DeebBackend,
+    database::{
+        add_key::{AddKeyReport, AddKeyStrategy},
+        blob::base64_bytes,
+        encryption::KeyProvider,
+        entity::{Entity, EntityAssociation, EntityName, Index},
+        error::{DeebError, ErrorKind},
+        field_default::DefaultValue,
+        field_type::FieldType,
+        find_many_options::{
+            FindManyOptions, FindManyOptionsBuilder, FindManyOrder, NullsOrder, OrderDirection,
+        },
+        insert_options::InsertOptions,
+        instance_config::merge_instance_config,
+        integrity::{verify_integrity, IntegrityIssue, IntegrityReport},
+        json_schema::{
+            entities_from_json_schema, entity_from_json_schema, json_schema_definitions,
+        },
+        outbox::OutboxMessage,
+        page::{Page, PageRequest},
+        prepared_query::{param, PreparedQuery},
+        query::{LikeMode, LikeOptions, Query},
+        reserved_fields::{ReservedFieldCollision, ReservedFields},
+        schema_diff::{entities_from_config, EntityDiff, MigrationStep, SchemaDiff},
+        schema_log::{SchemaChange, SchemaOperation, SchemaReverse},
+        slow_query::{SlowQuery, SlowQueryLog},
+        stats::EntityStats,
+        time_series::{Aggregation, TimeSeries},
+        transaction::Transaction,
+        tree::Tree,
+        update_mode::UpdateMode,
+        write_concern::WriteConcern,
+    },
     deeb::Deeb,
+    kv::Kv,
+    lock::DocumentLock,
+    write_batch::{WriteAck, WriteBatch},
 };
@@ -0,0 +1,180 @@
+use anyhow::Error;
+use chrono::Utc;
+use log::*;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+use crate::database::{
+    entity::{Entity, EntityName},
+    name::Name,
+    Database,
+};
+
+const LOCKS_ENTITY: &str = "_locks";
+
+/// An advisory lock on a single document, kept in the `_locks` entity of the document's
+/// instance.
+///
+/// Locks coordinate the users of one [Deeb](crate::Deeb) handle, such as the request
+/// handlers of an HTTP server sharing it. They are written with the rest of the instance,
+/// but other processes do not reload the file and will not see them. A lock expires once
+/// its ttl passes and can then be taken by another holder. Dropping the guard releases
+/// the lock in the background; call [DocumentLock::release] to wait for it instead.
+///
+/// ```
+/// # use deeb::*;
+/// # use anyhow::Error;
+/// # use std::time::Duration;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Error> {
+/// # let document = Entity::new("document");
+/// # let db = Deeb::new();
+/// # db.add_instance("test", "./document.json", vec![document.clone()]).await?;
+/// let lock = db
+///     .lock_document(&document, 1, Duration::from_secs(30))
+///     .await?
+///     .expect("document is not locked");
+/// assert!(db.lock_document(&document, 1, Duration::from_secs(30)).await?.is_none());
+/// lock.release().await?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct DocumentLock {
+    db: Arc<RwLock<Database>>,
+    name: Name,
+    entity: EntityName,
+    id: Value,
+    token: String,
+    expires_at: i64,
+    released: bool,
+}
+
+impl DocumentLock {
+    /// Take the lock on a document, or return `None` if another holder has an unexpired
+    /// lock on it. Expired locks in the instance are removed.
+    pub(crate) async fn acquire(
+        db: Arc<RwLock<Database>>,
+        entity: &Entity,
+        id: Value,
+        ttl: Duration,
+    ) -> Result<Option<Self>, Error> {
+        debug!("Locking `{}` document {}", entity.name, id);
+        let mut guard = db.write().await;
+        let name = guard.get_instance_name_by_entity(entity)?;
+        let now = Utc::now().timestamp_millis();
+        let data = guard.get_instance_data_mut(&name, &EntityName::from(LOCKS_ENTITY))?;
+        let before = data.len();
+        data.retain(|lock| !is_expired(lock, now));
+        let expired = before - data.len();
+        if data.iter().any(|lock| is_lock_on(lock, &entity.name, &id)) {
+            if expired > 0 {
+                guard.commit(vec![name])?;
+            }
+            trace!("Document {} is already locked", id);
+            return Ok(None);
+        }
+        let token = uuid::Uuid::new_v4().to_string();
+        let expires_at = now.saturating_add(ttl_millis(ttl));
+        data.push_back(json!({
+            "entity": entity.name.0,
+            "id": id,
+            "token": token,
+            "expires_at": expires_at,
+        }));
+        guard.commit(vec![name.clone()])?;
+        drop(guard);
+        Ok(Some(Self {
+            db,
+            name,
+            entity: entity.name.clone(),
+            id,
+            token,
+            expires_at,
+            released: false,
+        }))
+    }
+
+    /// The token identifying this holder of the lock.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// When the lock expires, in milliseconds since the Unix epoch.
+    pub fn expires_at(&self) -> i64 {
+        self.expires_at
+    }
+
+    /// Extend the lock to expire `ttl` from now. Returns `false` if the lock was lost
+    /// because it expired and another holder took it.
+    pub async fn refresh(&mut self, ttl: Duration) -> Result<bool, Error> {
+        debug!("Refreshing lock on `{}` document {}", self.entity, self.id);
+        let mut db = self.db.write().await;
+        let data = db.get_instance_data_mut(&self.name, &EntityName::from(LOCKS_ENTITY))?;
+        let Some(lock) = data.iter_mut().find(|lock| lock["token"] == self.token) else {
+            return Ok(false);
+        };
+        let expires_at = Utc::now()
+            .timestamp_millis()
+            .saturating_add(ttl_millis(ttl));
+        lock["expires_at"] = Value::from(expires_at);
+        db.commit(vec![self.name.clone()])?;
+        self.expires_at = expires_at;
+        Ok(true)
+    }
+
+    /// Release the lock. Returns `false` if the lock was already lost.
+    pub async fn release(mut self) -> Result<bool, Error> {
+        self.released = true;
+        remove_lock(&self.db, &self.name, &self.token).await
+    }
+}
+
+impl Drop for DocumentLock {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            warn!(
+                "Lock on `{}` document {} was not released",
+                self.entity, self.id
+            );
+            return;
+        };
+        let db = self.db.clone();
+        let name = self.name.clone();
+        let token = self.token.clone();
+        handle.spawn(async move {
+            if let Err(err) = remove_lock(&db, &name, &token).await {
+                error!("Failed to release lock: {:?}", err);
+            }
+        });
+    }
+}
+
+async fn remove_lock(db: &RwLock<Database>, name: &Name, token: &str) -> Result<bool, Error> {
+    let mut db = db.write().await;
+    let data = db.get_instance_data_mut(name, &EntityName::from(LOCKS_ENTITY))?;
+    let Some(index) = data.iter().position(|lock| lock["token"] == token) else {
+        return Ok(false);
+    };
+    data.remove(index);
+    db.commit(vec![name.clone()])?;
+    Ok(true)
+}
+
+fn is_expired(lock: &Value, now: i64) -> bool {
+    lock["expires_at"]
+        .as_i64()
+        .is_none_or(|expires_at| expires_at <= now)
+}
+
+fn is_lock_on(lock: &Value, entity: &EntityName, id: &Value) -> bool {
+    lock["entity"] == entity.0.as_str() && lock["id"] == *id
+}
+
+fn ttl_millis(ttl: Duration) -> i64 {
+    i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX)
+}
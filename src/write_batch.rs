@@ -0,0 +1,177 @@
+use anyhow::Error;
+use log::*;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::{oneshot, RwLock};
+
+use crate::database::{
+    entity::Entity, insert_options::InsertOptions, name::Name, query::Query,
+    update_mode::UpdateMode, Database,
+};
+
+/// A group of writes that are applied in memory immediately and made durable together.
+///
+/// Each write returns a [WriteAck] holding the written value. Readers see the write right
+/// away, but the ack only resolves once [WriteBatch::flush] has written and fsynced every
+/// instance touched by the batch. Dropping the batch without flushing fails its pending acks.
+pub struct WriteBatch {
+    db: Arc<RwLock<Database>>,
+    names: Vec<Name>,
+    replaced: Vec<(Entity, im::Vector<Value>)>,
+    pending: Vec<oneshot::Sender<Result<(), String>>>,
+}
+
+/// The result of a write in a [WriteBatch], acknowledged once the batch is durable.
+#[derive(Debug)]
+pub struct WriteAck<T> {
+    value: T,
+    receiver: oneshot::Receiver<Result<(), String>>,
+}
+
+impl<T> WriteAck<T> {
+    /// The value as it was applied in memory.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Wait for the group commit that makes this write durable.
+    pub async fn acknowledged(self) -> Result<T, Error> {
+        match self.receiver.await {
+            Ok(Ok(())) => Ok(self.value),
+            Ok(Err(err)) => Err(Error::msg(err)),
+            Err(_) => Err(Error::msg("Write batch dropped before it was flushed")),
+        }
+    }
+}
+
+impl WriteBatch {
+    pub(crate) fn new(db: Arc<RwLock<Database>>) -> Self {
+        Self {
+            db,
+            names: vec![],
+            replaced: vec![],
+            pending: vec![],
+        }
+    }
+
+    /// Number of writes waiting for the next flush.
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub async fn insert(
+        &mut self,
+        entity: &Entity,
+        value: Value,
+    ) -> Result<WriteAck<Value>, Error> {
+        debug!("Batching insert");
+        self.apply(entity, |db| db.insert(entity, value)).await
+    }
+
+    pub async fn insert_many(
+        &mut self,
+        entity: &Entity,
+        values: Vec<Value>,
+    ) -> Result<WriteAck<Vec<Value>>, Error> {
+        debug!("Batching insert many");
+        self.apply(entity, |db| {
+            db.insert_many(entity, values, InsertOptions::default())
+        })
+        .await
+    }
+
+    pub async fn delete_one(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+    ) -> Result<WriteAck<Value>, Error> {
+        debug!("Batching delete one");
+        self.apply(entity, |db| db.delete_one(entity, query)).await
+    }
+
+    pub async fn delete_many(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+    ) -> Result<WriteAck<Vec<Value>>, Error> {
+        debug!("Batching delete many");
+        self.apply(entity, |db| db.delete_many(entity, query)).await
+    }
+
+    pub async fn update_one(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+    ) -> Result<WriteAck<Value>, Error> {
+        debug!("Batching update one");
+        self.apply(entity, |db| {
+            db.update_one(entity, query, update_value, UpdateMode::Merge)
+        })
+        .await
+    }
+
+    pub async fn update_many(
+        &mut self,
+        entity: &Entity,
+        query: Query,
+        update_value: Value,
+    ) -> Result<WriteAck<Vec<Value>>, Error> {
+        debug!("Batching update many");
+        self.apply(entity, |db| {
+            db.update_many(entity, query, update_value, UpdateMode::Merge)
+        })
+        .await
+    }
+
+    /// Write and fsync every instance touched since the last flush, then resolve the
+    /// pending acks. If the commit fails, the pending acks fail with the same error.
+    /// Blobs that the batch stopped referencing are removed once the commit succeeds.
+    pub async fn flush(&mut self) -> Result<(), Error> {
+        debug!("Flushing write batch");
+        let names = std::mem::take(&mut self.names);
+        let pending = std::mem::take(&mut self.pending);
+        let replaced = std::mem::take(&mut self.replaced);
+        let result = {
+            let mut db = self.db.write().await;
+            let result = db.commit_durable(names);
+            if result.is_ok() {
+                for (entity, documents) in replaced.iter() {
+                    db.remove_blobs(entity, documents);
+                }
+            }
+            result
+        };
+        trace!("Flushed {} writes", pending.len());
+        let ack = result.as_ref().map(|_| ()).map_err(|err| err.to_string());
+        for sender in pending {
+            let _ = sender.send(ack.clone());
+        }
+        result
+    }
+
+    async fn apply<T, F>(&mut self, entity: &Entity, operation: F) -> Result<WriteAck<T>, Error>
+    where
+        F: FnOnce(&mut Database) -> Result<T, Error>,
+    {
+        let (value, name) = {
+            let mut db = self.db.write().await;
+            let before = db.get_blob_documents(entity);
+            let value = operation(&mut db)?;
+            if let Some(documents) = before {
+                self.replaced.push((entity.clone(), documents));
+            }
+            (value, db.get_instance_name_by_entity(entity)?)
+        };
+        if !self.names.contains(&name) {
+            self.names.push(name);
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.pending.push(sender);
+        Ok(WriteAck { value, receiver })
+    }
+}
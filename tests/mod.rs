@@ -1,6 +1,6 @@
 use anyhow::Error;
 use deeb::*;
-use serde_json::json;
+use serde_json::{json, Value};
 
 async fn spawn_deeb() -> Result<(Deeb, Entity, Entity), Error> {
     let db = Deeb::new();
@@ -20,8 +20,8 @@ async fn spawn_deeb() -> Result<(Deeb, Entity, Entity), Error> {
     )
     .await?;
 
-    db.delete_many(&user, Query::All, None).await?;
-    db.delete_many(&comment, Query::All, None).await?;
+    db.truncate(&user).await?;
+    db.truncate(&comment).await?;
 
     // Populate initial data
     db.insert(&user, json!({"id": 1, "name": "oliver", "age": 0.5}), None)
@@ -74,6 +74,39 @@ async fn insert_many() -> Result<(), Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn insert_many_with_options() -> Result<(), Error> {
+    let db = Deeb::new();
+    let event = Entity::new("event").default_value("status", json!("new"));
+    db.add_instance(
+        "insert_options",
+        "./tests/insert_options.json",
+        vec![event.clone()],
+    )
+    .await?;
+    db.delete_many(&event, Query::All, None).await?;
+
+    let values = vec![json!({"id": 1}), json!({"id": 2})];
+    let options = InsertOptions::new().return_document(false);
+    let result = db
+        .insert_many_with_options(&event, values.clone(), options, None)
+        .await?;
+    assert!(result.is_empty());
+    let found = db.find_many(&event, Query::All, None).await?;
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0]["status"], json!("new"));
+
+    let mut transaction = db.begin_transaction().await;
+    let result = db
+        .insert_many_with_options(&event, values, options, Some(&mut transaction))
+        .await?;
+    assert!(result.is_empty());
+    db.commit(&mut transaction).await?;
+    let found = db.find_many(&event, Query::All, None).await?;
+    assert_eq!(found.len(), 4);
+    Ok(())
+}
+
 #[tokio::test]
 async fn find_one() -> Result<(), Error> {
     let (db, user, _comment) = spawn_deeb().await?;
@@ -96,6 +129,558 @@ async fn find_many() -> Result<(), Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn truncate() -> Result<(), Error> {
+    let db = Deeb::new();
+    let log = Entity::new("log");
+    let other = Entity::new("other");
+    db.add_instance(
+        "truncate",
+        "./tests/truncate.json",
+        vec![log.clone(), other.clone()],
+    )
+    .await?;
+    db.truncate(&log).await?;
+    db.truncate(&other).await?;
+    db.insert_many(&log, vec![json!({"id": 1}), json!({"id": 2})], None)
+        .await?;
+    db.insert(&other, json!({"id": 1}), None).await?;
+
+    assert_eq!(db.truncate(&log).await?, 2);
+    assert!(db.find_many(&log, Query::All, None).await?.is_empty());
+    assert_eq!(db.find_many(&other, Query::All, None).await?.len(), 1);
+
+    // The truncate is committed to disk.
+    let db = Deeb::new();
+    db.add_instance(
+        "truncate",
+        "./tests/truncate.json",
+        vec![log.clone(), other.clone()],
+    )
+    .await?;
+    assert!(db.find_many(&log, Query::All, None).await?.is_empty());
+    assert_eq!(db.truncate(&log).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transform_into() -> Result<(), Error> {
+    let db = Deeb::new();
+    let order = Entity::new("order");
+    let summary = Entity::new("summary").primary_key("id");
+    db.add_instance(
+        "transform_into",
+        "./tests/transform_into.json",
+        vec![order.clone(), summary.clone()],
+    )
+    .await?;
+    db.truncate(&order).await?;
+    db.truncate(&summary).await?;
+    db.insert_many(
+        &order,
+        vec![
+            json!({"id": 1, "total": 10, "status": "paid"}),
+            json!({"id": 2, "total": 20, "status": "open"}),
+            json!({"id": 3, "total": 30, "status": "paid"}),
+        ],
+        None,
+    )
+    .await?;
+
+    let count = db
+        .transform_into(
+            &order,
+            &summary,
+            Query::eq("status", "paid"),
+            |order| json!({"id": order["id"], "cents": order["total"].as_i64().unwrap() * 100}),
+        )
+        .await?;
+    assert_eq!(count, 2);
+    let summaries = db.find_many(&summary, Query::All, None).await?;
+    assert_eq!(
+        summaries,
+        vec![
+            json!({"id": 1, "cents": 1000}),
+            json!({"id": 3, "cents": 3000})
+        ]
+    );
+
+    // A failed insert writes nothing.
+    let result = db
+        .transform_into(&order, &summary, Query::All, |order| {
+            match order["id"] == 3 {
+                true => json!("not an object"),
+                false => json!({"id": order["id"]}),
+            }
+        })
+        .await;
+    assert!(result.is_err());
+    assert_eq!(db.find_many(&summary, Query::All, None).await?.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn transform_into_protected_fields() -> Result<(), Error> {
+    let account = Entity::new("account")
+        .encrypted_fields(vec!["ssn"])
+        .redacted_fields(vec!["pin"]);
+    let copy = Entity::new("account_copy");
+    let secure_copy = Entity::new("account_secure_copy").encrypted_fields(vec!["ssn"]);
+    let db = Deeb::new();
+    db.set_key_provider(StaticKeyProvider).await;
+    db.add_instance(
+        "transform_into_protected_fields",
+        "./tests/transform_into_protected_fields.json",
+        vec![account.clone(), copy.clone(), secure_copy.clone()],
+    )
+    .await?;
+    for entity in [&account, &copy, &secure_copy] {
+        db.truncate(entity).await?;
+    }
+    db.insert(
+        &account,
+        json!({"id": 1, "ssn": "123-45-6789", "pin": "8642"}),
+        None,
+    )
+    .await?;
+
+    // Protected fields are not copied to an entity that does not protect them.
+    db.transform_into(&account, &copy, Query::All, |account| account)
+        .await?;
+    let copied = db.unredacted().find_one(&copy, Query::All, None).await?;
+    assert_eq!(copied, json!({"id": 1}));
+
+    // Encrypted fields are copied when the destination encrypts them too, and redacted
+    // fields when the handle may read them.
+    db.unredacted()
+        .transform_into(&account, &secure_copy, Query::All, |account| account)
+        .await?;
+    let copied = db
+        .unredacted()
+        .find_one(&secure_copy, Query::All, None)
+        .await?;
+    assert_eq!(
+        copied,
+        json!({"id": 1, "ssn": "123-45-6789", "pin": "8642"})
+    );
+    let raw = std::fs::read_to_string("./tests/transform_into_protected_fields.json")?;
+    assert!(!raw.contains("123-45-6789"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn reserved_fields() -> Result<(), Error> {
+    let db = Deeb::new();
+    let note = Entity::new("note").default_value("_version", json!(1));
+    db.add_instance(
+        "reserved_fields",
+        "./tests/reserved_fields.json",
+        vec![note.clone()],
+    )
+    .await?;
+    db.truncate(&note).await?;
+    db.set_reserved_fields("reserved_fields", Some(ReservedFields::new()))
+        .await?;
+
+    // Defaults may fill reserved fields, documents may not.
+    let inserted = db.insert(&note, json!({"id": 1}), None).await?;
+    assert_eq!(inserted, json!({"id": 1, "_version": 1}));
+    assert!(db
+        .insert(&note, json!({"id": 2, "_id": "abc"}), None)
+        .await
+        .is_err());
+    assert!(db
+        .update_many(&note, Query::All, json!({"_version": 2}), None)
+        .await
+        .is_err());
+    let patch = json!([{"op": "remove", "path": "/_version"}]);
+    assert!(db
+        .patch_one(&note, Query::eq("id", 1), patch, None)
+        .await
+        .is_err());
+
+    db.set_reserved_fields(
+        "reserved_fields",
+        Some(
+            ReservedFields::new()
+                .prefix("$")
+                .collision(ReservedFieldCollision::Strip),
+        ),
+    )
+    .await?;
+    let inserted = db
+        .insert(&note, json!({"id": 2, "_id": "abc", "$meta": true}), None)
+        .await?;
+    assert_eq!(inserted, json!({"id": 2, "_version": 1}));
+    let updated = db
+        .update_one(
+            &note,
+            Query::eq("id", 1),
+            json!({"_version": 5, "title": "Hello"}),
+            None,
+        )
+        .await?;
+    assert_eq!(updated, json!({"id": 1, "_version": 1, "title": "Hello"}));
+
+    db.set_reserved_fields("reserved_fields", None).await?;
+    db.insert(&note, json!({"id": 3, "_id": "abc"}), None)
+        .await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn reserved_fields_rollback() -> Result<(), Error> {
+    let db = Deeb::new();
+    let note = Entity::new("note")
+        .default_value("_id", json!("uuid()"))
+        .default_value("_created_at", json!("now()"));
+    db.add_instance(
+        "reserved_fields_rollback",
+        "./tests/reserved_fields_rollback.json",
+        vec![note.clone()],
+    )
+    .await?;
+    db.truncate(&note).await?;
+    db.set_reserved_fields("reserved_fields_rollback", Some(ReservedFields::new()))
+        .await?;
+    let created = db.insert(&note, json!({"id": 1}), None).await?;
+
+    // A failed transaction puts deleted documents back as they were stored, even though
+    // their reserved fields could not be inserted again.
+    let mut transaction = db.begin_transaction().await;
+    db.delete_one(&note, Query::eq("id", 1), Some(&mut transaction))
+        .await?;
+    db.find_one(&note, Query::eq("id", 2), Some(&mut transaction))
+        .await?;
+    assert!(db.commit(&mut transaction).await.is_err());
+    assert_eq!(db.find_many(&note, Query::All, None).await?, vec![created]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn verify_integrity() -> Result<(), Error> {
+    let db = Deeb::new();
+    let mut post = Entity::new("post").primary_key("id");
+    let author = Entity::new("author")
+        .primary_key("id")
+        .associate(&mut post, "author_id", None::<&str>)
+        .map_err(Error::msg)?;
+    db.add_instance(
+        "verify_integrity",
+        "./tests/verify_integrity.json",
+        vec![author.clone(), post.clone()],
+    )
+    .await?;
+    db.truncate(&author).await?;
+    db.truncate(&post).await?;
+    db.insert(&author, json!({"id": 1}), None).await?;
+    db.insert_many(
+        &post,
+        vec![json!({"id": 1, "author_id": 1}), json!({"id": 2})],
+        None,
+    )
+    .await?;
+    assert!(db.verify_integrity().await.is_ok());
+
+    db.insert_many(
+        &post,
+        vec![json!({"id": 2, "author_id": 1}), json!({"author_id": 7})],
+        None,
+    )
+    .await?;
+    let report = db.verify_integrity().await;
+    assert_eq!(report.documents, 5);
+    assert_eq!(
+        report.issues,
+        vec![
+            IntegrityIssue::MissingPrimaryKey {
+                entity: EntityName::from("post"),
+                position: 3,
+            },
+            IntegrityIssue::DuplicatePrimaryKey {
+                entity: EntityName::from("post"),
+                key: json!(2),
+                count: 2,
+            },
+            IntegrityIssue::MissingReference {
+                entity: EntityName::from("post"),
+                field: "author_id".to_string(),
+                value: json!(7),
+                target: EntityName::from("author"),
+            },
+        ]
+    );
+
+    // Documents of an entity with the same name in another instance are checked on their own.
+    let archived_post = Entity::new("post");
+    db.add_instance(
+        "verify_integrity_archive",
+        "./tests/verify_integrity_archive.json",
+        vec![archived_post.clone()],
+    )
+    .await?;
+    db.truncate(&archived_post).await?;
+    db.insert(&archived_post, json!({"author_id": 9}), None)
+        .await?;
+    let archived = db.verify_integrity().await;
+    assert_eq!(archived.documents, 6);
+    assert_eq!(archived.issues, report.issues);
+    Ok(())
+}
+
+#[tokio::test]
+async fn find_page() -> Result<(), Error> {
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Player {
+        name: String,
+    }
+
+    let db = Deeb::new();
+    let player = Entity::new("player");
+    db.add_instance("find_page", "./tests/find_page.json", vec![player.clone()])
+        .await?;
+    db.truncate(&player).await?;
+    let names = ["e", "b", "d", "a", "c"];
+    db.insert_many(
+        &player,
+        names.iter().map(|name| json!({"name": name})).collect(),
+        None,
+    )
+    .await?;
+
+    let request = PageRequest::new(2).order(FindManyOrder::new("name", OrderDirection::Ascending));
+    let mut names = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let request = match &cursor {
+            Some(cursor) => request.clone().cursor(cursor),
+            None => request.clone(),
+        };
+        let page = db.find_page::<Player>(&player, Query::All, request).await?;
+        assert_eq!(page.total, 5);
+        names.extend(page.items.into_iter().map(|player| player.name));
+        cursor = page.next_cursor;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(names, vec!["a", "b", "c", "d", "e"]);
+
+    // Deleting from an earlier page does not skip documents on the next one.
+    let first = db
+        .find_page::<Player>(&player, Query::All, request.clone())
+        .await?;
+    db.delete_one(&player, Query::eq("name", "a"), None).await?;
+    let cursor = first.next_cursor.expect("next cursor");
+    let second = db
+        .find_page::<Player>(&player, Query::All, request.clone().cursor(&cursor))
+        .await?;
+    let names = second.items.into_iter().map(|player| player.name);
+    assert_eq!(names.collect::<Vec<_>>(), vec!["c", "d"]);
+
+    let page = db
+        .find_page::<Value>(&player, Query::eq("name", "z"), request.clone())
+        .await?;
+    assert_eq!(
+        (page.items.len(), page.total, page.next_cursor),
+        (0, 0, None)
+    );
+    assert!(db
+        .find_page::<Value>(&player, Query::All, request.clone().cursor("x"))
+        .await
+        .is_err());
+    assert!(db
+        .find_page::<Value>(&player, Query::All, PageRequest::new(2))
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn field_types() -> Result<(), Error> {
+    let db = Deeb::new();
+    let strict = Entity::new("strict")
+        .field_type("count", FieldType::Int)
+        .field_type("meta.seen_at", FieldType::DateTime);
+    let loose = Entity::new("loose")
+        .field_type("count", FieldType::Int)
+        .field_type("label", FieldType::String)
+        .field_type("active", FieldType::Bool)
+        .field_type("seen_at", FieldType::DateTime)
+        .coerce_types();
+    db.add_instance(
+        "field_types",
+        "./tests/field_types.json",
+        vec![strict.clone(), loose.clone()],
+    )
+    .await?;
+    db.truncate(&strict).await?;
+    db.truncate(&loose).await?;
+
+    db.insert(
+        &strict,
+        json!({"count": 1, "meta": {"seen_at": "2024-01-01T00:00:00Z"}}),
+        None,
+    )
+    .await?;
+    db.insert(&strict, json!({"count": null}), None).await?;
+    assert!(db
+        .insert(&strict, json!({"count": "42"}), None)
+        .await
+        .is_err());
+    assert!(db
+        .insert(&strict, json!({"meta": {"seen_at": "yesterday"}}), None)
+        .await
+        .is_err());
+    assert!(db
+        .update_many(&strict, Query::All, json!({"count": 1.5}), None)
+        .await
+        .is_err());
+    assert_eq!(
+        db.find_many(&strict, Query::eq("count", 1), None)
+            .await?
+            .len(),
+        1
+    );
+
+    let inserted = db
+        .insert(
+            &loose,
+            json!({"count": "42", "label": 7, "active": "true", "seen_at": 0}),
+            None,
+        )
+        .await?;
+    assert_eq!(
+        inserted,
+        json!({"count": 42, "label": "7", "active": true, "seen_at": "1970-01-01T00:00:00.000Z"})
+    );
+    assert!(db
+        .insert(&loose, json!({"count": "many"}), None)
+        .await
+        .is_err());
+    let updated = db
+        .update_one(&loose, Query::eq("count", 42), json!({"count": "43"}), None)
+        .await?;
+    assert_eq!(updated["count"], json!(43));
+    Ok(())
+}
+
+#[tokio::test]
+async fn undo_schema_change() -> Result<(), Error> {
+    let db = Deeb::new();
+    let account = Entity::new("account").primary_key("id");
+    db.add_instance(
+        "undo_schema_change",
+        "./tests/undo_schema_change.json",
+        vec![account.clone()],
+    )
+    .await?;
+    db.truncate(&account).await?;
+    db.insert_many(
+        &account,
+        vec![
+            json!({"id": 1, "plan": {"tier": "pro"}}),
+            json!({"id": 2, "plan": {"tier": "free"}}),
+            json!({"id": 3}),
+        ],
+        None,
+    )
+    .await?;
+    let original = db.find_many(&account, Query::All, None).await?;
+
+    db.add_key(&account, "active", true).await?;
+    db.drop_key(&account, "plan.tier").await?;
+    let log = db.schema_log().await?;
+    let change = log.last().unwrap();
+    assert_eq!(change.entity, EntityName::from("account"));
+    assert_eq!(
+        change.operation,
+        SchemaOperation::DropKey {
+            key: "plan.tier".to_string()
+        }
+    );
+    assert_eq!(change.documents, 2);
+
+    let undone = db.undo_last_schema_change().await?.unwrap();
+    assert_eq!(undone.id, change.id);
+    let undone = db.undo_last_schema_change().await?.unwrap();
+    assert!(matches!(undone.operation, SchemaOperation::AddKey { .. }));
+    assert_eq!(db.find_many(&account, Query::All, None).await?, original);
+
+    // A key added over existing values can not be dropped to undo it.
+    db.add_key(&account, "id", 0).await?;
+    assert!(db.undo_last_schema_change().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn drop_protected_key() -> Result<(), Error> {
+    let secret = Entity::new("secret")
+        .primary_key("id")
+        .encrypted_fields(vec!["ssn"])
+        .redacted_fields(vec!["profile.pin"]);
+    let db = Deeb::new();
+    db.set_key_provider(StaticKeyProvider).await;
+    db.add_instance(
+        "drop_protected_key",
+        "./tests/drop_protected_key.json",
+        vec![secret.clone()],
+    )
+    .await?;
+    db.truncate(&secret).await?;
+    db.insert(
+        &secret,
+        json!({"id": 1, "ssn": "123-45-6789", "profile": {"pin": "8642"}}),
+        None,
+    )
+    .await?;
+
+    db.drop_key(&secret, "ssn").await?;
+    db.drop_key(&secret, "profile").await?;
+    let log = db.schema_log().await?;
+    for change in log.iter().rev().take(2) {
+        assert_eq!(change.entity, EntityName::from("secret"));
+        assert_eq!(change.reverse, None);
+    }
+    let raw = std::fs::read_to_string("./_meta.json")?;
+    assert!(!raw.contains("123-45-6789"));
+    assert!(!raw.contains("8642"));
+    assert!(db.undo_last_schema_change().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn sync_instance_config() -> Result<(), Error> {
+    let db = Deeb::new();
+    let mut order = Entity::new("order").primary_key("id");
+    let customer = Entity::new("customer")
+        .primary_key("id")
+        .associate(&mut order, "customer_id", None::<&str>)
+        .map_err(Error::msg)?;
+    db.add_instance(
+        "sync_instance_config",
+        "./tests/sync_instance_config.json",
+        vec![customer.clone(), order.clone()],
+    )
+    .await?;
+
+    let path = "./tests/sync_instance_config.instances.json";
+    let existing = json!([{
+        "name": "sync_instance_config",
+        "rate_limit": 10,
+        "entities": [{"name": "customer", "primary_key": "uuid", "cache": "60s"}],
+    }]);
+    std::fs::write(path, existing.to_string())?;
+    db.sync_instance_config_from_entities(path).await?;
+
+    let config: Value = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    assert_eq!(config[0]["rate_limit"], json!(10));
+    assert_eq!(config[0]["entities"][0]["cache"], json!("60s"));
+    assert_eq!(config[0]["entities"][0]["primary_key"], json!("id"));
+    assert_eq!(entities_from_config(&config)?, vec![customer, order]);
+    Ok(())
+}
+
 #[tokio::test]
 async fn delete_one() -> Result<(), Error> {
     let (db, user, _comment) = spawn_deeb().await?;
@@ -155,6 +740,69 @@ async fn transaction() -> Result<(), Error> {
     Ok(())
 }
 
+#[tokio::test]
+async fn failed_transaction() -> Result<(), Error> {
+    let db = Deeb::new();
+    let account = Entity::new("account").primary_key("id");
+    db.add_instance(
+        "failed_transaction",
+        "./tests/failed_transaction.json",
+        vec![account.clone()],
+    )
+    .await?;
+    db.delete_many(&account, Query::All, None).await?;
+    db.insert_many(
+        &account,
+        vec![
+            json!({"id": 1, "balance": 10}),
+            json!({"id": 2, "balance": 5}),
+        ],
+        None,
+    )
+    .await?;
+
+    // Every executed operation is undone when a later one fails.
+    let mut transaction = db.begin_transaction().await;
+    db.update_one(
+        &account,
+        Query::eq("id", 1),
+        json!({"balance": 0}),
+        Some(&mut transaction),
+    )
+    .await?;
+    db.patch_one(
+        &account,
+        Query::eq("id", 2),
+        json!([{"op": "add", "path": "/frozen", "value": true}]),
+        Some(&mut transaction),
+    )
+    .await?;
+    db.insert(
+        &account,
+        json!({"id": 3, "balance": 1}),
+        Some(&mut transaction),
+    )
+    .await?;
+    db.find_one(&account, Query::eq("id", 4), Some(&mut transaction))
+        .await?;
+    assert!(db.commit(&mut transaction).await.is_err());
+
+    let expected = vec![
+        json!({"id": 1, "balance": 10}),
+        json!({"id": 2, "balance": 5}),
+    ];
+    assert_eq!(db.find_many(&account, Query::All, None).await?, expected);
+    // The next write does not flush any part of the failed transaction.
+    db.insert(&account, json!({"id": 5, "balance": 2}), None)
+        .await?;
+    let file = std::fs::read_to_string("./tests/failed_transaction.json")?;
+    let on_disk = serde_json::from_str::<Value>(&file)?["account"].clone();
+    assert_eq!(on_disk[0], expected[0]);
+    assert_eq!(on_disk[1], expected[1]);
+    assert_eq!(on_disk.as_array().unwrap().len(), 3);
+    Ok(())
+}
+
 #[tokio::test]
 async fn update_one() -> Result<(), Error> {
     let (db, user, _comment) = spawn_deeb().await?;
@@ -399,6 +1047,39 @@ async fn test_all() {
     assert!(query.matches(&value).unwrap());
 }
 
+#[tokio::test]
+async fn query_cost() {
+    assert!(Query::eq("name", "nick").cost() < Query::lt("age", 35).cost());
+    assert!(Query::lt("age", 35).cost() < Query::like("name", "ni").cost());
+    assert!(Query::eq("name", "nick").cost() < Query::eq("tags.*", "rust").cost());
+
+    // Branches keep their written order unless reordered by cost.
+    let query = Query::Or(vec![
+        Query::like("name", "zz"),
+        Query::And(vec![Query::lt("age", 35), Query::eq("name", "nick")]),
+        Query::eq("name", "oliver"),
+    ]);
+    let ordered = query.clone().order_by_cost();
+    assert_eq!(
+        ordered,
+        Query::Or(vec![
+            Query::eq("name", "oliver"),
+            Query::like("name", "zz"),
+            Query::And(vec![Query::eq("name", "nick"), Query::lt("age", 35)]),
+        ])
+    );
+    for value in [
+        json!({"name": "nick", "age": 34}),
+        json!({"name": "oliver", "age": 40}),
+        json!({"name": "nick", "age": 36}),
+    ] {
+        assert_eq!(
+            query.matches(&value).unwrap(),
+            ordered.matches(&value).unwrap()
+        );
+    }
+}
+
 #[tokio::test]
 async fn drop_key() -> Result<(), Error> {
     let (db, user, _comment) = spawn_deeb().await?;
@@ -493,19 +1174,20 @@ async fn add_key_nested() -> Result<(), Error> {
 async fn load_meta() -> Result<(), Error> {
     let (db, ..) = spawn_deeb().await?;
     let _meta = db.get_meta()?;
-    let meta = db.find_many(&_meta, Query::All, None).await?;
+    // The meta instance is shared by every test, so look entities up by name.
+    let user = db.find_one(&_meta, Query::eq("name", "user"), None).await?;
+    let comment = db
+        .find_one(&_meta, Query::eq("name", "comment"), None)
+        .await?;
 
-    assert_eq!(meta.len(), 2);
-    assert_eq!(meta[0]["name"], "user");
-    assert_eq!(meta[1]["name"], "comment");
     // primary key
-    assert_eq!(meta[0]["primary_key"], "id");
-    assert_eq!(meta[1]["primary_key"], "id");
+    assert_eq!(user["primary_key"], "id");
+    assert_eq!(comment["primary_key"], "id");
     // associations
-    assert_eq!(meta[0]["associations"][0]["from"], "id");
-    assert_eq!(meta[0]["associations"][0]["to"], "user_id");
-    assert_eq!(meta[1]["associations"][0]["from"], "user_id");
-    assert_eq!(meta[1]["associations"][0]["to"], "id");
+    assert_eq!(user["associations"][0]["from"], "id");
+    assert_eq!(user["associations"][0]["to"], "user_id");
+    assert_eq!(comment["associations"][0]["from"], "user_id");
+    assert_eq!(comment["associations"][0]["to"], "id");
 
     Ok(())
 }
@@ -522,3 +1204,1841 @@ async fn find_by_association() -> Result<(), Error> {
     assert_eq!(first_comment, "Hello");
     Ok(())
 }
+
+async fn find_with_backend<B: DeebBackend>(
+    backend: &B,
+    entity: &Entity,
+    query: Query,
+) -> Result<Value, Error> {
+    backend.find_one(entity, query, None).await
+}
+
+#[tokio::test]
+async fn backend_trait() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+    let result = find_with_backend(&db, &user, Query::eq("name", "oliver")).await?;
+    assert_eq!(result, json!({"id": 1, "name": "oliver", "age": 0.5}));
+    Ok(())
+}
+
+struct StaticKeyProvider;
+
+impl KeyProvider for StaticKeyProvider {
+    fn key(&self, _entity: &EntityName) -> Result<[u8; 32], Error> {
+        Ok([7; 32])
+    }
+}
+
+#[tokio::test]
+async fn encrypted_fields() -> Result<(), Error> {
+    let secret = Entity::new("secret").encrypted_fields(vec!["email", "address.street"]);
+    let db = Deeb::new();
+    db.set_key_provider(StaticKeyProvider).await;
+    db.add_instance("secret", "./tests/encrypted.json", vec![secret.clone()])
+        .await?;
+    db.delete_many(&secret, Query::All, None).await?;
+    db.insert(
+        &secret,
+        json!({"name": "oliver", "email": "oliver@example.com", "address": {"street": "1 Main St"}}),
+        None,
+    )
+    .await?;
+
+    let raw = std::fs::read_to_string("./tests/encrypted.json")?;
+    assert!(raw.contains("oliver"));
+    assert!(!raw.contains("oliver@example.com"));
+    assert!(!raw.contains("1 Main St"));
+
+    let reopened = Deeb::new();
+    reopened.set_key_provider(StaticKeyProvider).await;
+    reopened
+        .add_instance("secret", "./tests/encrypted.json", vec![secret.clone()])
+        .await?;
+    let result = reopened
+        .find_one(&secret, Query::eq("email", "oliver@example.com"), None)
+        .await?;
+    assert_eq!(result["address"]["street"], "1 Main St");
+    Ok(())
+}
+
+#[tokio::test]
+async fn redacted_fields() -> Result<(), Error> {
+    let account = Entity::new("account").redacted_fields(vec!["password", "profile.email"]);
+    let db = Deeb::new();
+    db.add_instance("account", "./tests/redaction.json", vec![account.clone()])
+        .await?;
+    db.delete_many(&account, Query::All, None).await?;
+    db.insert(
+        &account,
+        json!({"name": "oliver", "password": "hunter2", "profile": {"email": "oliver@example.com", "bio": "hi"}}),
+        None,
+    )
+    .await?;
+
+    let result = db
+        .find_one(&account, Query::eq("name", "oliver"), None)
+        .await?;
+    assert_eq!(result, json!({"name": "oliver", "profile": {"bio": "hi"}}));
+
+    let result = db.find_many(&account, Query::All, None).await?;
+    assert_eq!(
+        result,
+        vec![json!({"name": "oliver", "profile": {"bio": "hi"}})]
+    );
+
+    let result = db
+        .unredacted()
+        .find_one(&account, Query::eq("password", "hunter2"), None)
+        .await?;
+    assert_eq!(result["profile"]["email"], "oliver@example.com");
+
+    // Documents returned by writes are redacted too.
+    let result = db
+        .update_one(
+            &account,
+            Query::eq("name", "oliver"),
+            json!({"age": 30}),
+            None,
+        )
+        .await?;
+    assert_eq!(result.get("password"), None);
+    let result = db
+        .update_many(&account, Query::All, json!({"age": 31}), None)
+        .await?;
+    assert_eq!(result[0].get("password"), None);
+    let result = db
+        .patch_one(
+            &account,
+            Query::All,
+            json!([{"op": "replace", "path": "/age", "value": 32}]),
+            None,
+        )
+        .await?;
+    assert_eq!(result["profile"], json!({"bio": "hi"}));
+    let result = db.pop_first(&account, Query::All, None).await?.unwrap();
+    assert_eq!(result.get("password"), None);
+    db.unredacted().insert(&account, result, None).await?;
+    let result = db.delete_one(&account, Query::All, None).await?;
+    assert_eq!(result.get("password"), None);
+
+    // Redaction only hides values, filters on redacted fields still match.
+    db.insert(
+        &account,
+        json!({"name": "oliver", "password": "hunter2"}),
+        None,
+    )
+    .await?;
+    let result = db
+        .find_many(&account, Query::eq("password", "hunter2"), None)
+        .await?;
+    assert_eq!(result, vec![json!({"name": "oliver"})]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_query_log() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+    let _ = std::fs::remove_file("./tests/slow_query.log");
+    db.set_slow_query_log(
+        "user",
+        Some(SlowQueryLog::new(std::time::Duration::ZERO).file_path("./tests/slow_query.log")),
+    )
+    .await?;
+    db.find_many(&user, Query::eq("name", "oliver"), None)
+        .await?;
+    db.find_one(&user, Query::eq("name", "oliver"), None)
+        .await?;
+
+    let log = std::fs::read_to_string("./tests/slow_query.log")?;
+    let mut lines = log.lines();
+    let entry: Value = serde_json::from_str(lines.next().unwrap())?;
+    assert_eq!(entry["entity"], "user");
+    assert_eq!(entry["operation"], "find_many");
+    assert_eq!(entry["shape"], json!({"Eq": ["name", "?"]}));
+    assert_eq!(entry["scanned"], 3);
+    // Finding one stops at the first match.
+    let entry: Value = serde_json::from_str(lines.next().unwrap())?;
+    assert_eq!(entry["operation"], "find_one");
+    assert_eq!(entry["scanned"], 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_batch() -> Result<(), Error> {
+    let db = Deeb::new();
+    let event = Entity::new("event");
+    db.add_instance("batch", "./tests/write_batch.json", vec![event.clone()])
+        .await?;
+    db.delete_many(&event, Query::All, None).await?;
+
+    let mut batch = db.write_batch();
+    let first = batch
+        .insert(&event, json!({"id": 1, "kind": "open"}))
+        .await?;
+    let second = batch
+        .update_one(&event, Query::eq("id", 1), json!({"kind": "close"}))
+        .await?;
+    assert_eq!(batch.len(), 2);
+    assert_eq!(second.value(), &json!({"id": 1, "kind": "close"}));
+
+    // Writes are visible before they are flushed.
+    let found = db.find_one(&event, Query::eq("id", 1), None).await?;
+    assert_eq!(found["kind"], "close");
+
+    batch.flush().await?;
+    assert!(batch.is_empty());
+    first.acknowledged().await?;
+    second.acknowledged().await?;
+
+    let file: Value = serde_json::from_str(&std::fs::read_to_string("./tests/write_batch.json")?)?;
+    assert_eq!(file["event"], json!([{"id": 1, "kind": "close"}]));
+
+    let dropped = {
+        let mut batch = db.write_batch();
+        batch
+            .insert(&event, json!({"id": 2, "kind": "open"}))
+            .await?
+    };
+    assert!(dropped.acknowledged().await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn entities_from_schema() -> Result<(), Error> {
+    let document = json!({
+        "$defs": {
+            "Base": {
+                "type": "object",
+                "properties": {"uuid": {"type": "string"}},
+                "required": ["uuid"]
+            },
+            "UserProfile": {
+                "x-deeb-primary-key": "uuid",
+                "allOf": [
+                    {"$ref": "#/$defs/Base"},
+                    {
+                        "properties": {
+                            "password": {"type": "string", "writeOnly": true},
+                            "address": {
+                                "type": "object",
+                                "properties": {
+                                    "street": {"type": "string", "x-deeb-encrypted": true}
+                                }
+                            }
+                        }
+                    }
+                ]
+            },
+            "Status": {"type": "string", "enum": ["active", "disabled"]}
+        }
+    });
+
+    let entities = entities_from_json_schema(&document)?;
+    assert_eq!(entities.len(), 2);
+    let profile = entities
+        .iter()
+        .find(|entity| entity.name.to_string() == "user_profile")
+        .unwrap();
+    assert_eq!(profile.primary_key, Some("uuid".to_string()));
+    assert_eq!(profile.redacted_fields, vec!["password"]);
+    assert_eq!(profile.encrypted_fields, vec!["address.street"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn error_context() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+
+    let err = db
+        .find_one(&user, Query::eq("name", "nobody"), None)
+        .await
+        .unwrap_err();
+    let context = err.downcast_ref::<DeebError>().unwrap();
+    assert_eq!(context.kind, ErrorKind::ValueNotFound);
+    assert_eq!(context.entity, Some(EntityName::from("user")));
+    assert_eq!(context.instance.as_deref(), Some("user"));
+    assert_eq!(context.file_path.as_deref(), Some("./tests/test.json"));
+    assert_eq!(
+        err.to_string(),
+        "Value not found (entity `user`, instance `user`, file `./tests/test.json`)"
+    );
+
+    let unknown = Entity::new("unknown");
+    let err = db.find_many(&unknown, Query::All, None).await.unwrap_err();
+    let context = err.downcast_ref::<DeebError>().unwrap();
+    assert_eq!(context.kind, ErrorKind::EntityNotFound);
+    assert_eq!(context.instance, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn find_many_like_with() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+
+    let options = LikeOptions::new().case_insensitive(true);
+    let result = db
+        .find_many(&user, Query::like_with("name", "OLI", options), None)
+        .await?;
+    assert_eq!(result.len(), 2);
+
+    let options = LikeOptions::new().mode(LikeMode::StartsWith);
+    let result = db
+        .find_many(&user, Query::like_with("name", "ol", options), None)
+        .await?;
+    assert_eq!(result.len(), 2);
+
+    let options = LikeOptions::new().mode(LikeMode::EndsWith);
+    let result = db
+        .find_many(&user, Query::like_with("name", "ia", options), None)
+        .await?;
+    assert_eq!(result.len(), 1);
+
+    let options = LikeOptions::new().mode(LikeMode::Wildcard);
+    let result = db
+        .find_many(&user, Query::like_with("name", "ol%i%", options), None)
+        .await?;
+    assert_eq!(result.len(), 2);
+    let result = db
+        .find_many(&user, Query::like_with("name", "ol_i%", options), None)
+        .await?;
+    assert_eq!(result.len(), 1);
+    let result = db
+        .find_many(&user, Query::like_with("name", "oli", options), None)
+        .await?;
+    assert_eq!(result.len(), 0);
+
+    // The plain like query keeps its substring behavior.
+    let result = db.find_many(&user, Query::like("name", "li"), None).await?;
+    assert_eq!(result.len(), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn find_many_order() -> Result<(), Error> {
+    let db = Deeb::new();
+    let player = Entity::new("player");
+    db.add_instance("order", "./tests/order.json", vec![player.clone()])
+        .await?;
+    db.delete_many(&player, Query::All, None).await?;
+    db.insert_many(
+        &player,
+        vec![
+            json!({"name": "d", "team": "red", "score": 2}),
+            json!({"name": "a", "team": "blue", "score": 5}),
+            json!({"name": "e", "team": "red"}),
+            json!({"name": "b", "team": "blue", "score": 1}),
+            json!({"name": "c", "team": "red", "score": 2, "rank": {"value": 1}}),
+            json!({"name": "f", "team": "blue", "score": null}),
+        ],
+        None,
+    )
+    .await?;
+
+    let names = |values: Vec<Value>| {
+        values
+            .iter()
+            .map(|value| value["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let options = FindManyOptions {
+        skip: None,
+        limit: None,
+        order: Some(vec![
+            FindManyOrder::new("team", OrderDirection::Ascending),
+            FindManyOrder::new("score", OrderDirection::Descending).nulls_first(),
+        ]),
+    };
+    let result = db
+        .find_many_with_options(&player, Query::All, options, None)
+        .await?;
+    assert_eq!(names(result), vec!["f", "a", "b", "e", "d", "c"]);
+
+    // Equal documents keep their insertion order.
+    let options = FindManyOptions {
+        skip: Some(1),
+        limit: Some(3),
+        order: Some(vec![FindManyOrder::new("score", OrderDirection::Ascending)]),
+    };
+    let result = db
+        .find_many_with_options(&player, Query::All, options, None)
+        .await?;
+    assert_eq!(names(result), vec!["d", "c", "a"]);
+
+    let options = FindManyOptions {
+        skip: None,
+        limit: Some(1),
+        order: Some(vec![FindManyOrder::new(
+            "rank.value",
+            OrderDirection::Ascending,
+        )
+        .nulls_last()]),
+    };
+    let result = db
+        .find_many_with_options(&player, Query::All, options, None)
+        .await?;
+    assert_eq!(names(result), vec!["c"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn field_defaults() -> Result<(), Error> {
+    let db = Deeb::new();
+    let post = Entity::new("post")
+        .default_value("id", DefaultValue::Uuid)
+        .default_value("meta.created_at", DefaultValue::Now)
+        .default_value("status", json!("draft"))
+        .default_value("views", json!(0));
+    db.add_instance("defaults", "./tests/defaults.json", vec![post.clone()])
+        .await?;
+    db.delete_many(&post, Query::All, None).await?;
+
+    let inserted = db
+        .insert(
+            &post,
+            json!({"title": "Hello", "status": "published"}),
+            None,
+        )
+        .await?;
+    assert_eq!(inserted["status"], "published");
+    assert_eq!(inserted["views"], 0);
+    assert_eq!(inserted["id"].as_str().unwrap().len(), 36);
+    assert!(inserted["meta"]["created_at"]
+        .as_str()
+        .unwrap()
+        .ends_with('Z'));
+
+    let inserted = db
+        .insert_many(&post, vec![json!({"title": "Draft", "views": 3})], None)
+        .await?;
+    assert_eq!(inserted[0]["status"], "draft");
+    assert_eq!(inserted[0]["views"], 3);
+
+    let found = db
+        .find_one(&post, Query::eq("title", "Draft"), None)
+        .await?;
+    assert_eq!(found, inserted[0]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn field_default_paths() -> Result<(), Error> {
+    let db = Deeb::new();
+    let cart = Entity::new("cart").default_value("items[0].quantity", json!(1));
+    db.add_instance(
+        "field_default_paths",
+        "./tests/field_default_paths.json",
+        vec![cart.clone()],
+    )
+    .await?;
+    db.truncate(&cart).await?;
+
+    // Defaults use the same paths as queries.
+    let inserted = db
+        .insert(
+            &cart,
+            json!({"items": [{"sku": "a"}, {"sku": "b", "gift": true}]}),
+            None,
+        )
+        .await?;
+    assert_eq!(
+        inserted["items"],
+        json!([{"sku": "a", "quantity": 1}, {"sku": "b", "gift": true}])
+    );
+    assert!(db
+        .insert(&cart, json!({"items": "none"}), None)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn add_key_strategy() -> Result<(), Error> {
+    let db = Deeb::new();
+    let place = Entity::new("place");
+    db.add_instance("add_key", "./tests/add_key.json", vec![place.clone()])
+        .await?;
+    db.delete_many(&place, Query::All, None).await?;
+    db.insert_many(
+        &place,
+        vec![
+            json!({"name": "a", "address": {"city": "lagos"}}),
+            json!({"name": "b", "address": "unknown"}),
+            json!({"name": "c", "address": {"zip": 1}}),
+            json!({"name": "d"}),
+        ],
+        None,
+    )
+    .await?;
+
+    let report = db.add_key_dry_run(&place, "address.zip").await?;
+    assert_eq!(
+        report,
+        AddKeyReport {
+            documents: 4,
+            conflicts: 2
+        }
+    );
+    assert_eq!(report.touched(AddKeyStrategy::Skip), 2);
+
+    let result = db
+        .add_key_with_strategy(&place, "address.zip", 10001, AddKeyStrategy::Fail)
+        .await;
+    assert!(result.is_err());
+    let unchanged = db.find_one(&place, Query::eq("name", "a"), None).await?;
+    assert_eq!(unchanged["address"], json!({"city": "lagos"}));
+
+    db.add_key_with_strategy(&place, "address.zip", 10001, AddKeyStrategy::Skip)
+        .await?;
+    let places = db.find_many(&place, Query::All, None).await?;
+    assert_eq!(places[0]["address"], json!({"city": "lagos", "zip": 10001}));
+    assert_eq!(places[1]["address"], "unknown");
+    assert_eq!(places[2]["address"], json!({"zip": 1}));
+    assert_eq!(places[3]["address"], json!({"zip": 10001}));
+
+    db.add_key_with_strategy(&place, "address.zip", 2, AddKeyStrategy::Overwrite)
+        .await?;
+    let places = db.find_many(&place, Query::All, None).await?;
+    assert!(places.iter().all(|place| place["address"]["zip"] == 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn add_key_array() -> Result<(), Error> {
+    let db = Deeb::new();
+    let order = Entity::new("order");
+    db.add_instance(
+        "add_key_array",
+        "./tests/add_key_array.json",
+        vec![order.clone()],
+    )
+    .await?;
+    db.truncate(&order).await?;
+    db.insert_many(
+        &order,
+        vec![
+            json!({"id": 1, "items": [{"name": "a"}, {"name": "b"}]}),
+            json!({"id": 2, "items": [{"name": "c", "sku": 7}]}),
+        ],
+        None,
+    )
+    .await?;
+
+    // A key on an array is set on each element, as queries and drop_key read it.
+    let report = db.add_key_dry_run(&order, "items.sku").await?;
+    assert_eq!(
+        report,
+        AddKeyReport {
+            documents: 2,
+            conflicts: 1
+        }
+    );
+    db.add_key(&order, "items.sku", 0).await?;
+    let orders = db.find_many(&order, Query::All, None).await?;
+    assert_eq!(
+        orders[0]["items"],
+        json!([{"name": "a", "sku": 0}, {"name": "b", "sku": 0}])
+    );
+    assert_eq!(orders[1]["items"], json!([{"name": "c", "sku": 0}]));
+    let found = db
+        .find_many(&order, Query::eq("items.sku", 0), None)
+        .await?;
+    assert_eq!(found.len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn kv() -> Result<(), Error> {
+    let db = Deeb::new();
+    db.add_instance("kv", "./tests/kv.json", vec![]).await?;
+    let kv = db.kv("kv");
+    kv.delete("flags").await?;
+    kv.delete("visits").await?;
+
+    assert_eq!(kv.get::<Value>("flags").await?, None);
+    kv.set("flags", &json!({"beta": true})).await?;
+    kv.set("flags", &json!({"beta": false})).await?;
+    assert_eq!(
+        kv.get::<Value>("flags").await?,
+        Some(json!({"beta": false}))
+    );
+
+    assert_eq!(kv.incr("visits", 2).await?, 2);
+    assert_eq!(kv.incr("visits", -1).await?, 1);
+    assert!(kv.incr("flags", 1).await.is_err());
+
+    // Values are persisted to the instance file.
+    let reloaded = Deeb::new();
+    reloaded
+        .add_instance("kv", "./tests/kv.json", vec![])
+        .await?;
+    assert_eq!(reloaded.kv("kv").get::<i64>("visits").await?, Some(1));
+
+    assert!(kv.delete("visits").await?);
+    assert!(!kv.delete("visits").await?);
+    assert!(db.kv("missing").get::<Value>("key").await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn pop() -> Result<(), Error> {
+    let db = Deeb::new();
+    let job = Entity::new("job");
+    db.add_instance("pop", "./tests/pop.json", vec![job.clone()])
+        .await?;
+    db.delete_many(&job, Query::All, None).await?;
+    db.insert_many(
+        &job,
+        vec![
+            json!({"id": 1, "priority": 1, "queue": "email"}),
+            json!({"id": 2, "priority": 3, "queue": "email"}),
+            json!({"id": 3, "priority": 3, "queue": "sms"}),
+            json!({"id": 4, "priority": 2, "queue": "email"}),
+        ],
+        None,
+    )
+    .await?;
+
+    let first = db
+        .pop_first(&job, Query::eq("queue", "email"), None)
+        .await?;
+    assert_eq!(first.unwrap()["id"], 1);
+    let last = db.pop_last(&job, Query::All, None).await?;
+    assert_eq!(last.unwrap()["id"], 4);
+
+    let order = vec![FindManyOrder::new("priority", OrderDirection::Descending)];
+    let highest = db.pop_first(&job, Query::All, Some(order.clone())).await?;
+    assert_eq!(highest.unwrap()["id"], 2);
+    let lowest = db.pop_last(&job, Query::All, Some(order)).await?;
+    assert_eq!(lowest.unwrap()["id"], 3);
+
+    assert_eq!(db.pop_first(&job, Query::All, None).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_behind() -> Result<(), Error> {
+    let db = Deeb::new();
+    let presence = Entity::new("presence");
+    db.add_instance(
+        "write_behind",
+        "./tests/write_behind.json",
+        vec![presence.clone()],
+    )
+    .await?;
+    db.delete_many(&presence, Query::All, None).await?;
+    let on_disk = || -> Result<Value, Error> {
+        let file = std::fs::read_to_string("./tests/write_behind.json")?;
+        Ok(serde_json::from_str::<Value>(&file)?["presence"].clone())
+    };
+
+    db.set_write_behind(&presence, Some(std::time::Duration::from_secs(60)))
+        .await;
+    db.insert(&presence, json!({"user": 1, "online": true}), None)
+        .await?;
+    db.update_one(
+        &presence,
+        Query::eq("user", 1),
+        json!({"online": false}),
+        None,
+    )
+    .await?;
+    let found = db.find_one(&presence, Query::eq("user", 1), None).await?;
+    assert_eq!(found["online"], false);
+    assert_eq!(on_disk()?, json!([]));
+
+    db.flush().await?;
+    assert_eq!(on_disk()?, json!([{"user": 1, "online": false}]));
+
+    // Held back writes are committed by the background task.
+    db.set_write_behind(&presence, Some(std::time::Duration::from_millis(20)))
+        .await;
+    db.insert(&presence, json!({"user": 2, "online": true}), None)
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(on_disk()?.as_array().unwrap().len(), 2);
+
+    // Setting a new interval stops the task of the old one, even if it comes back.
+    db.set_write_behind(&presence, Some(std::time::Duration::from_secs(60)))
+        .await;
+    db.set_write_behind(&presence, Some(std::time::Duration::from_millis(20)))
+        .await;
+    db.set_write_behind(&presence, Some(std::time::Duration::from_millis(20)))
+        .await;
+    db.set_write_behind(&presence, Some(std::time::Duration::from_secs(60)))
+        .await;
+    db.insert(&presence, json!({"user": 3, "online": true}), None)
+        .await?;
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+    assert_eq!(on_disk()?.as_array().unwrap().len(), 2);
+
+    db.set_write_behind(&presence, None).await;
+    db.delete_many(&presence, Query::All, None).await?;
+    assert_eq!(on_disk()?, json!([]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn write_concern() -> Result<(), Error> {
+    let db = Deeb::new();
+    let session = Entity::new("session");
+    db.add_instance(
+        "write_concern",
+        "./tests/write_concern.json",
+        vec![session.clone()],
+    )
+    .await?;
+    db.delete_many(&session, Query::All, None).await?;
+    let on_disk = || -> Result<Value, Error> {
+        let file = std::fs::read_to_string("./tests/write_concern.json")?;
+        Ok(serde_json::from_str::<Value>(&file)?["session"].clone())
+    };
+
+    let memory = db.with_write_concern(WriteConcern::Memory);
+    memory
+        .insert(&session, json!({"id": 1, "token": "abc"}), None)
+        .await?;
+    let found = db.find_one(&session, Query::eq("id", 1), None).await?;
+    assert_eq!(found["token"], "abc");
+    assert_eq!(on_disk()?, json!([]));
+
+    db.flush().await?;
+    assert_eq!(on_disk()?, json!([{"id": 1, "token": "abc"}]));
+
+    db.with_write_concern(WriteConcern::Fsynced)
+        .insert(&session, json!({"id": 2, "token": "def"}), None)
+        .await?;
+    assert_eq!(on_disk()?.as_array().unwrap().len(), 2);
+
+    memory.delete_many(&session, Query::All, None).await?;
+    assert_eq!(on_disk()?.as_array().unwrap().len(), 2);
+    db.delete_many(&session, Query::All, None).await?;
+    assert_eq!(on_disk()?, json!([]));
+    Ok(())
+}
+
+#[tokio::test]
+async fn lock_document() -> Result<(), Error> {
+    let db = Deeb::new();
+    let draft = Entity::new("draft");
+    db.add_instance("lock", "./tests/lock.json", vec![draft.clone()])
+        .await?;
+    let ttl = std::time::Duration::from_secs(30);
+
+    let lock = db.lock_document(&draft, 1, ttl).await?.unwrap();
+    assert!(db.lock_document(&draft, 1, ttl).await?.is_none());
+    // Locks are per document.
+    let other = db.lock_document(&draft, 2, ttl).await?.unwrap();
+    assert!(other.release().await?);
+
+    let file = std::fs::read_to_string("./tests/lock.json")?;
+    let locks = serde_json::from_str::<Value>(&file)?["_locks"].clone();
+    assert_eq!(locks.as_array().unwrap().len(), 1);
+    assert_eq!(locks[0]["token"], lock.token());
+
+    assert!(lock.release().await?);
+    let lock = db.lock_document(&draft, 1, ttl).await?.unwrap();
+
+    // Dropping the guard releases the lock.
+    drop(lock);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let mut lock = db
+        .lock_document(&draft, 1, std::time::Duration::ZERO)
+        .await?
+        .unwrap();
+
+    // An expired lock can be taken by another holder, and the old holder loses it.
+    let taken = db.lock_document(&draft, 1, ttl).await?.unwrap();
+    assert!(!lock.refresh(ttl).await?);
+    assert!(!lock.release().await?);
+    assert!(taken.release().await?);
+    Ok(())
+}
+
+#[tokio::test]
+async fn stats() -> Result<(), Error> {
+    let db = Deeb::new();
+    let metric = Entity::new("metric");
+    db.add_instance("stats", "./tests/stats.json", vec![metric.clone()])
+        .await?;
+    db.delete_many(&metric, Query::All, None).await?;
+    // Clearing scans whatever an earlier run left behind.
+    let cleared = db.stats().await[&metric.name].scanned;
+    db.insert_many(&metric, vec![json!({"id": 1}), json!({"id": 2})], None)
+        .await?;
+    db.find_one(&metric, Query::eq("id", 2), None).await?;
+    db.find_many(&metric, Query::All, None).await?;
+    db.update_one(&metric, Query::eq("id", 1), json!({"seen": true}), None)
+        .await?;
+
+    let stats = db.stats().await[&metric.name];
+    assert_eq!(
+        stats,
+        EntityStats {
+            reads: 2,
+            writes: 3,
+            scans: 4,
+            scanned: cleared + 5,
+        }
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn number_precision() -> Result<(), Error> {
+    let db = Deeb::new();
+    let account = Entity::new("account");
+    db.add_instance("number", "./tests/number.json", vec![account.clone()])
+        .await?;
+    db.delete_many(&account, Query::All, None).await?;
+    // Equal as `f64`, so only an exact comparison tells them apart.
+    db.insert_many(
+        &account,
+        vec![
+            json!({"id": 9007199254740993u64, "balance": -2.5}),
+            json!({"id": 9007199254740992u64, "balance": 1e3}),
+            json!({"id": 18446744073709551615u64, "balance": -9223372036854775808i64}),
+        ],
+        None,
+    )
+    .await?;
+
+    let found = db
+        .find_many(&account, Query::gt("id", 9007199254740992u64), None)
+        .await?;
+    assert_eq!(found.len(), 2);
+    let found = db
+        .find_many(&account, Query::lte("id", 9007199254740992u64), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+
+    let options = FindManyOptions {
+        order: Some(vec![FindManyOrder::new("id", OrderDirection::Ascending)]),
+        ..Default::default()
+    };
+    let ids = db
+        .find_many_with_options(&account, Query::All, options, None)
+        .await?
+        .iter()
+        .map(|account| account["id"].as_u64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        ids,
+        vec![9007199254740992, 9007199254740993, 18446744073709551615]
+    );
+
+    let options = FindManyOptions {
+        order: Some(vec![FindManyOrder::new(
+            "balance",
+            OrderDirection::Ascending,
+        )]),
+        ..Default::default()
+    };
+    let balances = db
+        .find_many_with_options(&account, Query::All, options, None)
+        .await?
+        .iter()
+        .map(|account| account["balance"].clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        balances,
+        vec![json!(-9223372036854775808i64), json!(-2.5), json!(1e3)]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn blobs() -> Result<(), Error> {
+    let db = Deeb::new();
+    let avatar = Entity::new("avatar");
+    db.add_instance("blob", "./tests/blob.json", vec![avatar.clone()])
+        .await?;
+    db.delete_many(&avatar, Query::All, None).await?;
+
+    let image = db.put_blob(&avatar, &[0, 159, 146, 150]).await?;
+    let thumbnail = db.put_blob(&avatar, &[1, 2, 3]).await?;
+    let id = image["$blob"].as_str().unwrap().to_string();
+    let path = format!("./tests/blob.json.blobs/{}", id);
+    db.insert(
+        &avatar,
+        json!({"user": 1, "image": image, "sizes": [{"small": thumbnail}]}),
+        None,
+    )
+    .await?;
+    let found = db.find_one(&avatar, Query::eq("user", 1), None).await?;
+    assert_eq!(
+        db.get_blob(&avatar, &found["image"]).await?,
+        vec![0, 159, 146, 150]
+    );
+    assert_eq!(
+        db.get_blob(&avatar, &found["sizes"][0]["small"]).await?,
+        vec![1, 2, 3]
+    );
+    assert!(db
+        .get_blob(&avatar, &json!({"$blob": "../blob.json"}))
+        .await
+        .is_err());
+
+    // Blobs of a document deleted in a transaction are kept until the commit.
+    let mut transaction = db.begin_transaction().await;
+    db.delete_one(&avatar, Query::eq("user", 1), Some(&mut transaction))
+        .await?;
+    assert!(std::path::Path::new(&path).exists());
+    db.commit(&mut transaction).await?;
+    assert!(!std::path::Path::new(&path).exists());
+    assert_eq!(std::fs::read_dir("./tests/blob.json.blobs")?.count(), 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn replaced_blobs() -> Result<(), Error> {
+    let db = Deeb::new();
+    let avatar = Entity::new("avatar");
+    db.add_instance(
+        "replaced_blob",
+        "./tests/replaced_blob.json",
+        vec![avatar.clone()],
+    )
+    .await?;
+    db.delete_many(&avatar, Query::All, None).await?;
+    let exists = |reference: &Value| {
+        let id = reference["$blob"].as_str().unwrap();
+        std::path::Path::new(&format!("./tests/replaced_blob.json.blobs/{}", id)).exists()
+    };
+
+    // Replacing or dropping a reference removes the blob it pointed to.
+    let first = db.put_blob(&avatar, &[1]).await?;
+    let second = db.put_blob(&avatar, &[2]).await?;
+    let third = db.put_blob(&avatar, &[3]).await?;
+    db.insert(&avatar, json!({"user": 1, "image": first}), None)
+        .await?;
+    db.update_one(
+        &avatar,
+        Query::eq("user", 1),
+        json!({"image": second}),
+        None,
+    )
+    .await?;
+    assert!(!exists(&first));
+    assert!(exists(&second));
+    db.patch_one(
+        &avatar,
+        Query::eq("user", 1),
+        json!([{"op": "replace", "path": "/image", "value": third}]),
+        None,
+    )
+    .await?;
+    assert!(!exists(&second));
+    assert!(exists(&third));
+    db.drop_key(&avatar, "image").await?;
+    assert!(!exists(&third));
+
+    // A shared reference is kept until the last document holding it is deleted.
+    let shared = db.put_blob(&avatar, &[4]).await?;
+    db.insert_many(
+        &avatar,
+        vec![
+            json!({"user": 2, "image": shared}),
+            json!({"user": 3, "image": shared}),
+        ],
+        None,
+    )
+    .await?;
+    db.delete_one(&avatar, Query::eq("user", 2), None).await?;
+    assert!(exists(&shared));
+    db.delete_one(&avatar, Query::eq("user", 3), None).await?;
+    assert!(!exists(&shared));
+
+    // Blobs are kept until the instance no longer referencing them is written.
+    let deferred = db.put_blob(&avatar, &[5]).await?;
+    db.insert(&avatar, json!({"user": 4, "image": deferred}), None)
+        .await?;
+    db.with_write_concern(WriteConcern::Memory)
+        .delete_one(&avatar, Query::eq("user", 4), None)
+        .await?;
+    assert!(exists(&deferred));
+    db.flush().await?;
+    assert!(!exists(&deferred));
+    Ok(())
+}
+
+#[tokio::test]
+async fn time_series() -> Result<(), Error> {
+    let db = Deeb::new();
+    let reading = Entity::new("reading").time_series(
+        TimeSeries::new("at", std::time::Duration::from_secs(60))
+            .retention(std::time::Duration::from_secs(600)),
+    );
+    db.add_instance(
+        "time_series",
+        "./tests/time_series.json",
+        vec![reading.clone()],
+    )
+    .await?;
+    db.delete_many(&reading, Query::All, None).await?;
+
+    // Out of order points are inserted in timestamp order.
+    db.insert_many(
+        &reading,
+        vec![
+            json!({"at": 0, "celsius": 20.0}),
+            json!({"at": 120_000, "celsius": 23.0}),
+            json!({"at": 30_000, "celsius": 22.0}),
+            json!({"at": "1970-01-01T00:01:30Z", "celsius": 25.0}),
+        ],
+        None,
+    )
+    .await?;
+    let at = db
+        .find_many(&reading, Query::All, None)
+        .await?
+        .iter()
+        .map(|point| point["celsius"].as_f64().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(at, vec![20.0, 22.0, 25.0, 23.0]);
+
+    let range = db.find_range(&reading, Some(30_000), Some(120_000)).await?;
+    assert_eq!(range.len(), 2);
+    assert!(db
+        .find_range(&reading, Some(200_000), None)
+        .await?
+        .is_empty());
+
+    let buckets = db
+        .downsample(&reading, None, None, "celsius", Aggregation::Avg)
+        .await?;
+    assert_eq!(
+        buckets,
+        vec![
+            json!({"timestamp": 0, "count": 2, "value": 21.0}),
+            json!({"timestamp": 60_000, "count": 1, "value": 25.0}),
+            json!({"timestamp": 120_000, "count": 1, "value": 23.0}),
+        ]
+    );
+
+    // Points older than the retention, measured from the newest point, are dropped.
+    db.insert(&reading, json!({"at": 700_000, "celsius": 19.0}), None)
+        .await?;
+    let remaining = db.find_range(&reading, None, None).await?;
+    assert_eq!(remaining.len(), 2);
+    assert_eq!(remaining[0]["at"], 120_000);
+
+    let user = Entity::new("not_series");
+    assert!(db.find_range(&user, None, None).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn time_series_order() -> Result<(), Error> {
+    let reading = Entity::new("reading")
+        .primary_key("id")
+        .time_series(TimeSeries::new("at", std::time::Duration::from_secs(60)));
+    std::fs::write(
+        "./tests/time_series_order.json",
+        r#"{"reading": [{"id": 1, "at": 30}, {"id": 2, "at": 10}, {"id": 3, "at": 20}]}"#,
+    )?;
+    let db = Deeb::new();
+    db.add_instance(
+        "time_series_order",
+        "./tests/time_series_order.json",
+        vec![reading.clone()],
+    )
+    .await?;
+    let ids = |points: Vec<Value>| {
+        points
+            .iter()
+            .map(|point| point["id"].clone())
+            .collect::<Vec<_>>()
+    };
+
+    // Points loaded out of order are sorted.
+    let range = db.find_range(&reading, Some(15), Some(35)).await?;
+    assert_eq!(ids(range), vec![json!(3), json!(1)]);
+
+    // A failed point inserts none of the batch.
+    let result = db
+        .insert_many(
+            &reading,
+            vec![json!({"id": 4, "at": 40}), json!({"id": 5})],
+            None,
+        )
+        .await;
+    assert!(result.is_err());
+    assert_eq!(db.find_range(&reading, None, None).await?.len(), 3);
+
+    // Updates that move a point keep the points sorted.
+    db.update_one(&reading, Query::eq("id", 2), json!({"at": 100}), None)
+        .await?;
+    assert_eq!(
+        ids(db.find_range(&reading, Some(15), Some(25)).await?),
+        vec![json!(3)]
+    );
+    assert_eq!(
+        ids(db.find_range(&reading, Some(90), Some(110)).await?),
+        vec![json!(2)]
+    );
+    db.patch_one(
+        &reading,
+        Query::eq("id", 1),
+        json!([{"op": "replace", "path": "/at", "value": 5}]),
+        None,
+    )
+    .await?;
+    db.update_many(&reading, Query::eq("id", 3), json!({"at": 200}), None)
+        .await?;
+    assert_eq!(
+        ids(db.find_range(&reading, None, None).await?),
+        vec![json!(1), json!(2), json!(3)]
+    );
+
+    // Points must keep a valid timestamp.
+    let result = db
+        .update_one(&reading, Query::eq("id", 1), json!({"at": "soon"}), None)
+        .await;
+    assert!(result.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn traverse() -> Result<(), Error> {
+    let db = Deeb::new();
+    let mut member = Entity::new("member").primary_key("id");
+    let mut reply = Entity::new("reply").primary_key("id");
+    let mut thread = Entity::new("thread").primary_key("id");
+    member
+        .associate(&mut reply, "member_id", None::<&str>)
+        .map_err(Error::msg)?;
+    thread
+        .associate(&mut reply, "thread_id", None::<&str>)
+        .map_err(Error::msg)?;
+    member
+        .associate(&mut thread, "author_id", None::<&str>)
+        .map_err(Error::msg)?;
+    db.add_instance(
+        "traverse",
+        "./tests/traverse.json",
+        vec![member.clone(), reply.clone(), thread.clone()],
+    )
+    .await?;
+    for entity in [&member, &reply, &thread] {
+        db.delete_many(entity, Query::All, None).await?;
+    }
+    db.insert_many(
+        &member,
+        vec![
+            json!({"id": 1, "name": "ada"}),
+            json!({"id": 2, "name": "grace"}),
+            json!({"id": 3, "name": "linus"}),
+        ],
+        None,
+    )
+    .await?;
+    db.insert_many(
+        &thread,
+        vec![
+            json!({"id": 10, "author_id": 2}),
+            json!({"id": 11, "author_id": 3}),
+        ],
+        None,
+    )
+    .await?;
+    db.insert_many(
+        &reply,
+        vec![
+            json!({"id": 100, "member_id": 1, "thread_id": 10}),
+            json!({"id": 101, "member_id": 1, "thread_id": 10}),
+            json!({"id": 102, "member_id": 2, "thread_id": 11}),
+        ],
+        None,
+    )
+    .await?;
+
+    // Who wrote the threads ada replied to?
+    let path = vec![reply.clone(), thread.clone(), member.clone()];
+    let levels = db
+        .traverse(&member, Query::eq("name", "ada"), path.clone(), None)
+        .await?;
+    assert_eq!(levels.len(), 4);
+    assert_eq!(levels[1].len(), 2);
+    assert_eq!(levels[2], vec![json!({"id": 10, "author_id": 2})]);
+    assert_eq!(levels[3], vec![json!({"id": 2, "name": "grace"})]);
+
+    let levels = db
+        .traverse(&member, Query::eq("name", "ada"), path, Some(1))
+        .await?;
+    assert_eq!(levels.len(), 2);
+
+    // Members are not associated with each other.
+    assert!(db
+        .traverse(&member, Query::All, vec![member.clone()], None)
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn tree() -> Result<(), Error> {
+    let db = Deeb::new();
+    let category = Entity::new("category")
+        .primary_key("id")
+        .tree(Tree::new("parent_id").materialized_path("path"));
+    db.add_instance("tree", "./tests/tree.json", vec![category.clone()])
+        .await?;
+    db.delete_many(&category, Query::All, None).await?;
+    db.insert_many(
+        &category,
+        vec![
+            json!({"id": 1, "name": "shop"}),
+            json!({"id": 2, "name": "books", "parent_id": 1}),
+            json!({"id": 3, "name": "music", "parent_id": 1}),
+            json!({"id": 4, "name": "poetry", "parent_id": 2}),
+        ],
+        None,
+    )
+    .await?;
+    let poetry = db.find_one(&category, Query::eq("id", 4), None).await?;
+    assert_eq!(poetry["path"], json!([1, 2]));
+
+    let names = |values: Vec<Value>| {
+        values
+            .iter()
+            .map(|value| value["name"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(db.find_descendants(&category, 1).await?),
+        vec!["books", "music", "poetry"]
+    );
+    assert_eq!(
+        names(db.find_ancestors(&category, 4).await?),
+        vec!["books", "shop"]
+    );
+    let subtree = db.find_many(&category, Query::eq("path", 2), None).await?;
+    assert_eq!(names(subtree), vec!["poetry"]);
+
+    // Moving a document updates the paths below it.
+    let moved = db
+        .update_one(&category, Query::eq("id", 2), json!({"parent_id": 3}), None)
+        .await?;
+    assert_eq!(moved["path"], json!([1, 3]));
+    let poetry = db.find_one(&category, Query::eq("id", 4), None).await?;
+    assert_eq!(poetry["path"], json!([1, 3, 2]));
+
+    // A cycle is rejected and leaves the tree unchanged.
+    assert!(db
+        .update_one(&category, Query::eq("id", 1), json!({"parent_id": 4}), None)
+        .await
+        .is_err());
+    let shop = db.find_one(&category, Query::eq("id", 1), None).await?;
+    assert_eq!(shop.get("parent_id"), None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn prepared_query() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+    let prepared = PreparedQuery::new(Query::or(vec![
+        Query::eq("name", param("name")),
+        Query::and(vec![
+            Query::gt("id", param("id")),
+            Query::ne("name", param("name")),
+        ]),
+    ]));
+    assert_eq!(prepared.params(), ["name", "id"]);
+
+    let query = prepared.bind([("name", json!("oliver")), ("id", json!(2))])?;
+    let found = db.find_many(&user, query, None).await?;
+    let names = found
+        .iter()
+        .map(|user| user["name"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["oliver", "olliard"]);
+
+    assert!(prepared.bind([("name", json!("oliver"))]).is_err());
+    assert!(prepared
+        .bind([
+            ("name", json!("oliver")),
+            ("id", json!(2)),
+            ("age", json!(1))
+        ])
+        .is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn patch_one() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+    let patch = json!([
+        {"op": "add", "path": "/address", "value": {"city": "Austin"}},
+        {"op": "copy", "from": "/name", "path": "/nickname"},
+        {"op": "move", "from": "/age", "path": "/years"},
+        {"op": "replace", "path": "/address/city", "value": "Denver"},
+        {"op": "remove", "path": "/nickname"},
+        {"op": "test", "path": "/years", "value": 0.5},
+    ]);
+    let patched = db.patch_one(&user, Query::eq("id", 1), patch, None).await?;
+    assert_eq!(
+        patched,
+        json!({"id": 1, "name": "oliver", "years": 0.5, "address": {"city": "Denver"}})
+    );
+
+    // A failing operation leaves the document unchanged.
+    let patch = json!([
+        {"op": "replace", "path": "/name", "value": "oscar"},
+        {"op": "test", "path": "/years", "value": 2},
+    ]);
+    assert!(db
+        .patch_one(&user, Query::eq("id", 1), patch, None)
+        .await
+        .is_err());
+    let found = db.find_one(&user, Query::eq("id", 1), None).await?;
+    assert_eq!(found["name"], "oliver");
+
+    let invalid = json!([{"op": "rename", "path": "/name"}]);
+    assert!(db
+        .patch_one(&user, Query::eq("id", 1), invalid, None)
+        .await
+        .is_err());
+
+    let mut transaction = db.begin_transaction().await;
+    let patch = json!([{"op": "replace", "path": "/name", "value": "oscar"}]);
+    db.patch_one(&user, Query::eq("id", 1), patch, Some(&mut transaction))
+        .await?;
+    db.commit(&mut transaction).await?;
+    let found = db.find_one(&user, Query::eq("id", 1), None).await?;
+    assert_eq!(found["name"], "oscar");
+    Ok(())
+}
+
+#[tokio::test]
+async fn update_merge_patch() -> Result<(), Error> {
+    let (db, user, _comment) = spawn_deeb().await?;
+    db.update_one(
+        &user,
+        Query::eq("id", 1),
+        json!({"settings": {"theme": "dark", "font": "mono"}}),
+        None,
+    )
+    .await?;
+    let updated = db
+        .update_one_with_mode(
+            &user,
+            Query::eq("id", 1),
+            json!({"age": null, "settings": {"font": null, "size": 12}}),
+            UpdateMode::MergePatch,
+            None,
+        )
+        .await?;
+    assert_eq!(
+        updated,
+        json!({"id": 1, "name": "oliver", "settings": {"theme": "dark", "size": 12}})
+    );
+
+    let mut transaction = db.begin_transaction().await;
+    db.update_many_with_mode(
+        &user,
+        Query::All,
+        json!({"age": null}),
+        UpdateMode::MergePatch,
+        Some(&mut transaction),
+    )
+    .await?;
+    db.commit(&mut transaction).await?;
+    let users = db.find_many(&user, Query::All, None).await?;
+    assert!(users.iter().all(|user| user.get("age").is_none()));
+
+    // The default merge keeps explicit nulls.
+    let updated = db
+        .update_one(&user, Query::eq("id", 2), json!({"age": null}), None)
+        .await?;
+    assert_eq!(updated["age"], Value::Null);
+    assert!(updated.get("age").is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn dynamic_keys() -> Result<(), Error> {
+    let db = Deeb::new();
+    let product = Entity::new("product");
+    db.add_instance(
+        "dynamic_keys",
+        "./tests/dynamic_keys.json",
+        vec![product.clone()],
+    )
+    .await?;
+    db.delete_many(&product, Query::All, None).await?;
+    db.insert_many(
+        &product,
+        vec![
+            json!({"id": 1, "attributes": {"color": {"value": "red"}, "size": {"value": "L"}}}),
+            json!({"id": 2, "attributes": {"size": {"value": "M"}}}),
+            json!({"id": 3, "variants": [{"sizes": {"eu": 40, "us": 7}}, {"sizes": {"eu": 44}}]}),
+        ],
+        None,
+    )
+    .await?;
+
+    let found = db
+        .find_many(&product, Query::has_key("attributes", "color"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], 1);
+
+    let found = db
+        .find_many(&product, Query::eq("attributes.*.value", "M"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], 2);
+
+    let found = db
+        .find_many(&product, Query::gte("variants.*.sizes.*", 44), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], 3);
+
+    let found = db
+        .find_many(&product, Query::has_key("variants.*.sizes", "us"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn path_segments() -> Result<(), Error> {
+    let db = Deeb::new();
+    let order = Entity::new("order");
+    db.add_instance(
+        "path_segments",
+        "./tests/path_segments.json",
+        vec![order.clone()],
+    )
+    .await?;
+    db.delete_many(&order, Query::All, None).await?;
+    db.insert_many(
+        &order,
+        vec![
+            json!({"id": 1, "items": [{"sku": "a1", "qty": 1}, {"sku": "b2", "qty": 5}]}),
+            json!({"id": 2, "items": [{"sku": "b2", "qty": 2}]}),
+        ],
+        None,
+    )
+    .await?;
+
+    let found = db
+        .find_many(&order, Query::eq("items.0.sku", "b2"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], 2);
+    let found = db
+        .find_many(&order, Query::eq("items[1].sku", "b2"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["id"], 1);
+    let found = db
+        .find_many(&order, Query::gt("items[*].qty", 4), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+
+    db.add_key(&order, "items[*].shipped", false).await?;
+    db.drop_key(&order, "items.0.qty").await?;
+    let found = db.find_one(&order, Query::eq("id", 1), None).await?;
+    assert_eq!(
+        found["items"],
+        json!([{"sku": "a1", "shipped": false}, {"sku": "b2", "qty": 5, "shipped": false}])
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn zero_padded_keys() -> Result<(), Error> {
+    let db = Deeb::new();
+    let code = Entity::new("code");
+    db.add_instance(
+        "zero_padded_keys",
+        "./tests/zero_padded_keys.json",
+        vec![code.clone()],
+    )
+    .await?;
+    db.truncate(&code).await?;
+    db.insert(
+        &code,
+        json!({"id": 1, "codes": {"007": "bond", "7": "seven"}, "list": ["a", "b"]}),
+        None,
+    )
+    .await?;
+
+    let found = db
+        .find_many(&code, Query::eq("codes.007", "bond"), None)
+        .await?;
+    assert_eq!(found.len(), 1);
+    let found = db.find_many(&code, Query::eq("list.01", "b"), None).await?;
+    assert_eq!(found.len(), 1);
+
+    db.add_key(&code, "codes.010", "ten").await?;
+    db.drop_key(&code, "codes.007").await?;
+    let found = db.find_one(&code, Query::eq("id", 1), None).await?;
+    assert_eq!(found["codes"], json!({"7": "seven", "010": "ten"}));
+    Ok(())
+}
+
+#[tokio::test]
+async fn wait_for_change() -> Result<(), Error> {
+    let db = Deeb::new();
+    let job = Entity::new("job");
+    db.add_instance(
+        "wait_for_change",
+        "./tests/wait_for_change.json",
+        vec![job.clone()],
+    )
+    .await?;
+    db.delete_many(&job, Query::All, None).await?;
+    db.insert(&job, json!({"id": 1, "status": "done"}), None)
+        .await?;
+
+    // Documents that already match do not resolve the wait.
+    let done = Query::eq("status", "done");
+    let found = db
+        .wait_for_change(&job, done.clone(), std::time::Duration::from_millis(50))
+        .await?;
+    assert!(found.is_none());
+
+    let worker = async {
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        db.insert(&job, json!({"id": 2, "status": "queued"}), None)
+            .await?;
+        db.update_one(&job, Query::eq("id", 2), json!({"status": "done"}), None)
+            .await
+    };
+    let (found, updated) = tokio::join!(
+        db.wait_for_change(&job, done, std::time::Duration::from_secs(5)),
+        worker
+    );
+    updated?;
+    assert_eq!(found?, Some(json!({"id": 2, "status": "done"})));
+    Ok(())
+}
+
+#[tokio::test]
+async fn outbox() -> Result<(), Error> {
+    let db = Deeb::new();
+    let payment = Entity::new("payment");
+    db.add_instance("outbox", "./tests/outbox.json", vec![payment.clone()])
+        .await?;
+    let stale = db
+        .poll_outbox("outbox", usize::MAX, std::time::Duration::ZERO)
+        .await?;
+    db.ack_outbox(
+        "outbox",
+        &stale.into_iter().map(|m| m.id).collect::<Vec<_>>(),
+    )
+    .await?;
+
+    let mut transaction = db.begin_transaction().await;
+    db.insert(
+        &payment,
+        json!({"id": 1, "amount": 100}),
+        Some(&mut transaction),
+    )
+    .await?;
+    let published = db
+        .publish_outbox(
+            &payment,
+            "payment.created",
+            json!({"id": 1}),
+            Some(&mut transaction),
+        )
+        .await?;
+    db.commit(&mut transaction).await?;
+
+    // A failed transaction publishes nothing.
+    let mut transaction = db.begin_transaction().await;
+    db.publish_outbox(
+        &payment,
+        "payment.deleted",
+        json!({"id": 2}),
+        Some(&mut transaction),
+    )
+    .await?;
+    db.delete_one(&payment, Query::eq("id", 2), Some(&mut transaction))
+        .await?;
+    assert!(db.commit(&mut transaction).await.is_err());
+
+    let visibility = std::time::Duration::from_millis(50);
+    let messages = db.poll_outbox("outbox", 10, visibility).await?;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id, published.id);
+    assert_eq!(messages[0].topic, "payment.created");
+    assert_eq!(messages[0].attempts, 1);
+    // Claimed messages are hidden until the visibility timeout passes.
+    assert!(db.poll_outbox("outbox", 10, visibility).await?.is_empty());
+    tokio::time::sleep(visibility).await;
+    let messages = db.poll_outbox("outbox", 10, visibility).await?;
+    assert_eq!(messages[0].attempts, 2);
+
+    assert_eq!(
+        db.ack_outbox("outbox", std::slice::from_ref(&published.id))
+            .await?,
+        1
+    );
+    tokio::time::sleep(visibility).await;
+    assert!(db.poll_outbox("outbox", 10, visibility).await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn schema_diff() -> Result<(), Error> {
+    let mut comment = Entity::new("comment").primary_key("id");
+    let user = Entity::new("user")
+        .primary_key("id")
+        .add_index("by_name", vec!["name"])
+        .clone();
+    let old = vec![
+        user.clone()
+            .associate(&mut comment, "user_id", None::<&str>)
+            .map_err(Error::msg)?,
+        comment.clone(),
+    ];
+    let config = json!([{
+        "name": "test",
+        "entities": [
+            {"name": "user", "primary_key": "id", "indexes": [{"name": "by_email", "columns": ["email"]}], "defaults": {"status": "active"}},
+            {"name": "post", "primary_key": "id"},
+        ],
+    }]);
+    let new = entities_from_config(&config)?;
+
+    let diff = SchemaDiff::new(&old, &new);
+    assert_eq!(diff.added_entities, vec![EntityName::from("post")]);
+    assert_eq!(diff.removed_entities, vec![EntityName::from("comment")]);
+    let user_diff = &diff.changed_entities[0];
+    assert_eq!(user_diff.name, EntityName::from("user"));
+    assert_eq!(user_diff.added_indexes[0].name, "by_email");
+    assert_eq!(user_diff.removed_indexes[0].name, "by_name");
+    assert_eq!(user_diff.removed_associations.len(), 1);
+    assert_eq!(
+        diff.migration_plan(),
+        vec![
+            MigrationStep::DropIndex {
+                entity: EntityName::from("user"),
+                name: "by_name".to_string(),
+            },
+            MigrationStep::AddIndex {
+                entity: EntityName::from("user"),
+                name: "by_email".to_string(),
+                columns: vec!["email".to_string()],
+            },
+            MigrationStep::AddKey {
+                entity: EntityName::from("user"),
+                key: "status".to_string(),
+                value: DefaultValue::from(json!("active")),
+            },
+        ]
+    );
+    assert!(SchemaDiff::new(&new, &new).is_empty());
+
+    // Encryption, redaction, time series, tree, and field type changes are diffed too.
+    let old = vec![Entity::new("reading")
+        .encrypted_fields(vec!["device"])
+        .field_type("celsius", FieldType::Int)];
+    let new = vec![Entity::new("reading")
+        .encrypted_fields(vec!["owner"])
+        .redacted_fields(vec!["owner"])
+        .time_series(TimeSeries::new("at", std::time::Duration::from_secs(60)))
+        .tree(Tree::new("parent_id"))
+        .field_type("celsius", FieldType::Float)];
+    let diff = SchemaDiff::new(&old, &new);
+    let reading_diff = &diff.changed_entities[0];
+    assert_eq!(reading_diff.added_encrypted_fields, vec!["owner"]);
+    assert_eq!(reading_diff.removed_encrypted_fields, vec!["device"]);
+    assert_eq!(reading_diff.added_redacted_fields, vec!["owner"]);
+    assert_eq!(
+        reading_diff.field_types["celsius"],
+        (Some(FieldType::Int), Some(FieldType::Float))
+    );
+    assert!(reading_diff.time_series.is_some());
+    assert!(reading_diff.tree.is_some());
+    let reading = EntityName::from("reading");
+    assert_eq!(
+        diff.migration_plan(),
+        vec![
+            MigrationStep::EncryptField {
+                entity: reading.clone(),
+                field: "owner".to_string(),
+            },
+            MigrationStep::DecryptField {
+                entity: reading.clone(),
+                field: "device".to_string(),
+            },
+            MigrationStep::CheckFieldType {
+                entity: reading.clone(),
+                field: "celsius".to_string(),
+                field_type: FieldType::Float,
+            },
+            MigrationStep::CheckTimestamps {
+                entity: reading.clone(),
+                field: "at".to_string(),
+            },
+            MigrationStep::CheckTree {
+                entity: reading,
+                parent_field: "parent_id".to_string(),
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "sql")]
+#[tokio::test]
+async fn sql() -> Result<(), Error> {
+    let db = Deeb::new();
+    let mut comment = Entity::new("comment").primary_key("id");
+    let user = Entity::new("user")
+        .primary_key("id")
+        .redacted_fields(vec!["password"])
+        .associate(&mut comment, "user_id", Some("comments"))
+        .map_err(Error::msg)?;
+    db.add_instance(
+        "sql",
+        "./tests/sql.json",
+        vec![user.clone(), comment.clone()],
+    )
+    .await?;
+    db.delete_many(&user, Query::All, None).await?;
+    db.delete_many(&comment, Query::All, None).await?;
+    db.insert_many(
+        &user,
+        vec![
+            json!({"id": 1, "name": "Joey", "age": 31, "password": "hunter2"}),
+            json!({"id": 2, "name": "O'Brien", "age": 17}),
+            json!({"id": 3, "name": "jane", "age": 45}),
+        ],
+        None,
+    )
+    .await?;
+    db.insert_many(
+        &comment,
+        vec![
+            json!({"id": 1, "user_id": 1, "text": "Hello"}),
+            json!({"id": 2, "user_id": 3, "text": "Hi"}),
+        ],
+        None,
+    )
+    .await?;
+
+    let rows = db
+        .sql("SELECT name, age FROM user WHERE age >= 18 ORDER BY age DESC LIMIT 1")
+        .await?;
+    assert_eq!(rows, vec![json!({"name": "jane", "age": 45})]);
+
+    let rows = db
+        .sql("select user.name from user where name ilike 'j%' or name = 'O''Brien' order by id offset 1")
+        .await?;
+    assert_eq!(
+        rows,
+        vec![
+            json!({"user.name": "O'Brien"}),
+            json!({"user.name": "jane"})
+        ]
+    );
+
+    let rows = db.sql("SELECT * FROM user WHERE id IN (1)").await?;
+    assert!(rows[0].get("password").is_none());
+
+    let rows = db
+        .sql("SELECT COUNT(*), AVG(age), MAX(age) FROM user WHERE age < 40")
+        .await?;
+    assert_eq!(
+        rows,
+        vec![json!({"count(*)": 2, "avg(age)": 24.0, "max(age)": 31.0})]
+    );
+    let rows = db
+        .sql("SELECT id FROM user WHERE password IS NOT NULL")
+        .await?;
+    assert_eq!(rows, vec![json!({"id": 1})]);
+
+    let rows = db
+        .sql(
+            "SELECT user.name, comment.text FROM user JOIN comment ON user.id = comment.user_id \
+             WHERE comment.text = 'Hi'",
+        )
+        .await?;
+    assert_eq!(
+        rows,
+        vec![json!({"user.name": "jane", "comment.text": ["Hi"]})]
+    );
+
+    assert!(db.sql("DELETE FROM user").await.is_err());
+    assert!(db.sql("SELECT name, COUNT(*) FROM user").await.is_err());
+    assert!(db
+        .sql("SELECT * FROM user JOIN comment ON user.name = comment.text")
+        .await
+        .is_err());
+    Ok(())
+}
+
+#[cfg(feature = "arrow")]
+#[tokio::test]
+async fn to_arrow() -> Result<(), Error> {
+    use arrow_array::{Array, BooleanArray, Int64Array, StringArray, TimestampMillisecondArray};
+
+    let db = Deeb::new();
+    let user = Entity::new("user")
+        .redacted_fields(vec!["email"])
+        .field_type("name", FieldType::String)
+        .field_type("email", FieldType::String)
+        .field_type("address.zip", FieldType::Int)
+        .field_type("active", FieldType::Bool)
+        .field_type("joined", FieldType::DateTime);
+    db.add_instance("to_arrow", "./tests/to_arrow.json", vec![user.clone()])
+        .await?;
+    db.truncate(&user).await?;
+    db.insert_many(
+        &user,
+        vec![
+            json!({"name": "Joey", "email": "joey@example.com", "address": {"zip": 10001}, "active": true, "joined": "1970-01-01T00:00:01Z"}),
+            json!({"name": "Jane", "tags": ["a"]}),
+        ],
+        None,
+    )
+    .await?;
+
+    let batch = db.to_arrow(&user, Query::All).await?;
+    assert_eq!(batch.num_rows(), 2);
+    let columns = batch
+        .schema()
+        .fields()
+        .iter()
+        .map(|field| field.name().clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        columns,
+        vec!["active", "address.zip", "email", "joined", "name"]
+    );
+    let column = |name: &str| batch.column_by_name(name).unwrap().clone();
+    let name = column("name");
+    let name = name.as_any().downcast_ref::<StringArray>().unwrap();
+    assert_eq!(name.value(1), "Jane");
+    let zip = column("address.zip");
+    let zip = zip.as_any().downcast_ref::<Int64Array>().unwrap();
+    assert_eq!(zip.value(0), 10001);
+    assert!(zip.is_null(1));
+    let active = column("active");
+    assert!(active
+        .as_any()
+        .downcast_ref::<BooleanArray>()
+        .unwrap()
+        .value(0));
+    let joined = column("joined");
+    let joined = joined
+        .as_any()
+        .downcast_ref::<TimestampMillisecondArray>()
+        .unwrap();
+    assert_eq!(joined.value(0), 1000);
+    // Redacted fields are exported as nulls unless the reader may see them.
+    assert_eq!(column("email").null_count(), 2);
+    let batch = db.unredacted().to_arrow(&user, Query::All).await?;
+    assert_eq!(batch.column_by_name("email").unwrap().null_count(), 1);
+
+    assert!(db.to_arrow(&Entity::new("user"), Query::All).await.is_err());
+    Ok(())
+}